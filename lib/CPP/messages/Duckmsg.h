@@ -1,16 +1,154 @@
+#ifndef DUCKMSG_H
+#define DUCKMSG_H
 
 #include <stdint.h>
+#include <stddef.h>
 
 #define clamp(lo, v, hi) ((v) < (lo)) ? (lo) : ((hi) < (v)) ? (hi) : (v);
 
 
 class DuckMsg {
 public:
-  virtual void to_bytes(uint8_t *buffer) = 0;
+  virtual uint8_t get_id() = 0;
+  // The generated messages define int to_bytes(uint8_t *buffer), returning the
+  // size of the frame written in buffer. It is deleted in the messages the side
+  // they were generated for only receives, so sending them is a compile error.
+  // Whether the decoded values are in range, e.g. enum values are known.
+  virtual bool is_valid() const { return true; }
+
+  /**
+   * Fletcher-16 checksum, computed over the message id, length and payload.
+   * Same algorithm as the C messages and the Python SerialCom.
+   */
   static uint16_t compute_cheksum(uint8_t *buffer, int len) {
-    (void)buffer;
-    (void)len;
-    return 10;
+    uint8_t ck_a = 0;
+    uint8_t ck_b = 0;
+    for(int i=0; i<len; i++) {
+      ck_a = (ck_a + buffer[i]);       // % 256 by overflow
+      ck_b = (ck_b + ck_a);    // % 256 by overflow
+    }
+    uint16_t ck = (ck_a << 8) | ck_b;
+    return ck;
+  }
+
+  /**
+   * Integer nearest to (v - offset) / scale, within [lo, hi], sent for scaled fields.
+   * Same algorithm as duck_quantize, see CGenerator::QUANTIZE in the message generator.
+   */
+  static double quantize(double v, double scale, double offset, double lo, double hi) {
+    double x = (v - offset) / scale + 0.5;
+    x = x >= lo ? x : lo;    // also catches NaN
+    x = x <= hi ? x : hi;
+    double t = (double)(int64_t)x;    // floor, without libm
+    return t > x ? t - 1 : t;
   }
 };
 
+
+/**
+ * Receive side of the link, mirroring the Python SerialCom state machine.
+ * Feed it the incoming bytes one at a time with parse(). When it returns
+ * COMPLETE, msg_id(), payload() and payload_len() describe the received
+ * message until the next call to parse().
+ * If a msg_len function is given, frames with an unknown id or a length
+ * byte that doesn't match the id are dropped as soon as they are detected.
+ * Messages with variable length fields also need msg_min_len, the length
+ * byte being then accepted between msg_min_len(id) and msg_len(id).
+ * It does not allocate, and parse() can be called from an ISR.
+ */
+class DuckFrameParser {
+public:
+  enum Status {
+    INCOMPLETE,
+    COMPLETE,
+    BAD_ID,
+    BAD_LENGTH,
+    BAD_CHECKSUM,
+  };
+
+  // Largest (or smallest) value of the length byte for a message id, 0 if the id is unknown.
+  typedef uint8_t (*MsgLenFn)(uint8_t id);
+
+  DuckFrameParser(MsgLenFn msg_len = NULL, MsgLenFn msg_min_len = NULL):
+    _msg_len(msg_len), _msg_min_len(msg_min_len), _state(IDLE), _nb_received(0) {}
+
+  Status parse(uint8_t c) {
+    switch(_state) {
+    case IDLE:    // wait for 0xFF
+      if(c == 0xFF) {
+        _state = START_1ST;
+      }
+      break;
+    case START_1ST:
+      if(c == 0xFF) {
+        _state = START_2ND;
+      } else {    // fallback to Idle
+        _state = IDLE;
+      }
+      break;
+    case START_2ND:
+      if(_msg_len && _msg_len(c) == 0) {
+        _state = IDLE;
+        return BAD_ID;
+      }
+      _buffer[0] = c;    // msg id
+      _state = MSG_ID;
+      break;
+    case MSG_ID:
+      if(!length_ok(c)) {
+        _state = IDLE;
+        return BAD_LENGTH;
+      }
+      _buffer[1] = c;    // msg len
+      _nb_received = 0;
+      _state = MSG_LEN;
+      break;
+    case MSG_LEN:
+      _buffer[2 + _nb_received++] = c;
+      if(_nb_received == _buffer[1]) {
+        _state = IDLE;
+        uint8_t len = _buffer[1];
+        uint16_t ck = DuckMsg::compute_cheksum(_buffer, len);    // id, len and payload
+        uint16_t rcv_ck = _buffer[len] | (_buffer[len + 1] << 8);
+        return ck == rcv_ck ? COMPLETE : BAD_CHECKSUM;
+      }
+      break;
+    }
+    return INCOMPLETE;
+  }
+
+  uint8_t msg_id() { return _buffer[0]; }
+  uint8_t payload_len() { return _buffer[1] - 2; }
+  uint8_t* payload() { return _buffer + 2; }
+
+  void reset() { _state = IDLE; }
+
+private:
+  bool length_ok(uint8_t c) {
+    if(c < 2) {    // the length includes the 2 checksum bytes
+      return false;
+    }
+    if(!_msg_len) {
+      return true;
+    }
+    uint8_t min_len = _msg_min_len ? _msg_min_len(_buffer[0]) : _msg_len(_buffer[0]);
+    return min_len <= c && c <= _msg_len(_buffer[0]);
+  }
+
+  enum RcvState {
+    IDLE,
+    START_1ST,
+    START_2ND,
+    MSG_ID,
+    MSG_LEN,
+  };
+
+  MsgLenFn _msg_len;
+  MsgLenFn _msg_min_len;
+  RcvState _state;
+  uint8_t _nb_received;
+  // msg id, msg len, then up to 255 bytes of payload and checksum.
+  uint8_t _buffer[2 + 255];
+};
+
+#endif    // DUCKMSG_H
@@ -1,8 +1,18 @@
 #!/usr/bin/python3
 
+import math
+
 def clamp(l,v,h):
     return max(min(v, h), l)
 
+def quantize(v, scale, offset, lo, hi):
+    """Integer nearest to (v - offset) / scale, within [lo, hi], sent for scaled fields.
+    Same algorithm as duck_quantize, see CGenerator::QUANTIZE in the message generator."""
+    x = (v - offset) / scale + 0.5
+    x = x if x >= lo else lo    # also catches NaN
+    x = x if x <= hi else hi
+    return math.floor(x)
+
 class DuckMsg:
 
     def get_name(self):
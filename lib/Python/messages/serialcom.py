@@ -43,19 +43,26 @@ class SerialCom:
                 self._rcv_state = SerialCom.RcvState.MsgId
             elif self._rcv_state == SerialCom.RcvState.MsgId:
                 self._msg_len = ord(self.serial.read())
-                self._nb_bytes_expected = self._msg_len
-                self._rcv_state = SerialCom.RcvState.MsgLen
+                msgClass = messages.MESSAGES.get(self._msg_id)
+                if msgClass is None:
+                    print("message id {} unknown!".format(self._msg_id))
+                    self._rcv_state = SerialCom.RcvState.Idle
+                # the length includes the 2 checksum bytes, and varies for messages with variable length fields
+                elif not msgClass.MIN_SIZE - 4 <= self._msg_len <= msgClass.SIZE - 4:
+                    self._rcv_state = SerialCom.RcvState.Idle
+                else:
+                    self._nb_bytes_expected = self._msg_len
+                    self._rcv_state = SerialCom.RcvState.MsgLen
             elif self._rcv_state == SerialCom.RcvState.MsgLen:
                 payload = self.serial.read(self._msg_len)       # read message content
+                self._nb_bytes_expected = 1
+                self._rcv_state = SerialCom.RcvState.Idle
                 if self.control_checksum(self._msg_id, self._msg_len, payload):
+                    msg = messages.MESSAGES[self._msg_id]()
                     try:
-                        msgClass = messages.MESSAGES[self._msg_id]
-                    except KeyError:
-                        print("message id {} unknown!".format(self._msg_id))
-                    msg = msgClass()
-                    msg.deserialize(payload)
-                    self._nb_bytes_expected = 1
-                    self._rcv_state = SerialCom.RcvState.Idle
+                        msg.deserialize(payload)
+                    except ValueError:          # a decoded value or length is out of range, e.g. an unknown enum value
+                        return None
                     if self._msg_id == 0:   # UID message
                         if not msg.uid == messages.UID:
                             raise(Exception("Warning: Ducklink versions differs : remote is {}, local is {}".format(msg.uid, messages.UID)))
@@ -90,7 +97,7 @@ class SerialCom:
     @staticmethod
     def control_checksum(msg_id, msg_len, payload):
         # reconstruct the message from ID to payload(excluding checksum)
-        to_check = chr(msg_id).encode() + chr(msg_len).encode() + payload[:-2]
+        to_check = bytes([msg_id, msg_len]) + payload[:-2]
         ck = SerialCom.calculate_checksum(to_check)
         s = bitstring.BitStream(payload[-2:])
         rcv_ck, = s.unpack('uintle:16')         # coma to unpack the list as tuple
@@ -0,0 +1,315 @@
+use crate::c_generator::CGenerator;
+use crate::cpp_generator::CPPGenerator;
+use crate::doc_generator::DocGenerator;
+use crate::errors::Diagnostic;
+use crate::generator::Generator;
+use crate::locator::Locator;
+use crate::lock::{self, IdLock};
+use crate::message::{MsgSpec, Schema, Side};
+use crate::parser;
+use crate::python_generator::PythonGenerator;
+use crate::rust_generator::RustGenerator;
+use crate::uid;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    C,
+    Cpp,
+    Python,
+    Rust,
+    /// Markdown description of the protocol.
+    Doc,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 5] = [Lang::C, Lang::Cpp, Lang::Python, Lang::Rust, Lang::Doc];
+
+    /// Name of the language, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::C => "C",
+            Lang::Cpp => "CPP",
+            Lang::Python => "Python",
+            Lang::Rust => "Rust",
+            Lang::Doc => "Doc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lang> {
+        Lang::ALL.iter().copied().find(|lang| lang.name() == name)
+    }
+
+    /// Return the generated files as `(file name, contents)`.
+    pub fn generate_messages(self, schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        match self {
+            Lang::C => CGenerator::generate_messages(schema, uid),
+            Lang::Cpp => CPPGenerator::generate_messages(schema, uid),
+            Lang::Python => PythonGenerator::generate_messages(schema, uid),
+            Lang::Rust => RustGenerator::generate_messages(schema, uid),
+            Lang::Doc => DocGenerator::generate_messages(schema, uid),
+        }
+    }
+}
+
+/// Set the file of the diagnostics found while parsing `path`, unless they already have one.
+fn in_file(diagnostics: Vec<Diagnostic>, path: &Path) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .map(|d| match d.file {
+            Some(_) => d,
+            None => d.with_file(path.display().to_string()),
+        })
+        .collect()
+}
+
+fn io_error(path: &Path, what: &str, e: std::io::Error) -> Diagnostic {
+    Diagnostic::new("", format!("{}: {}", what, e)).with_file(path.display().to_string())
+}
+
+/// Schema read by `Builder::load`.
+struct Loaded {
+    schema: Schema,
+    /// Files the schema was read from, included files first.
+    paths: Vec<PathBuf>,
+    id_lock: IdLock,
+    /// Previous contents of the lock file, if any.
+    lock_contents: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct GeneratedFile {
+    pub lang: Lang,
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Generate messages from a schema file, e.g. in a `build.rs`:
+/// `Builder::new("messages.toml").lang(Lang::Rust).out_dir(out_dir).generate()`.
+#[derive(Debug)]
+pub struct Builder {
+    schemas: Vec<PathBuf>,
+    langs: Vec<Lang>,
+    out_dir: PathBuf,
+    lang_out_dirs: Vec<(Lang, PathBuf)>,
+    side: Option<Side>,
+    cargo_rerun: bool,
+    update_lock: bool,
+}
+
+impl Builder {
+    pub fn new<P: AsRef<Path>>(schema: P) -> Builder {
+        Builder {
+            schemas: vec![schema.as_ref().to_path_buf()],
+            langs: vec![],
+            out_dir: PathBuf::from("."),
+            lang_out_dirs: vec![],
+            side: None,
+            cargo_rerun: true,
+            update_lock: false,
+        }
+    }
+
+    /// Add a schema file, merged with the others in one namespace, e.g. to share the types
+    /// of several boards. The lock file is named after the first schema, e.g. `robot.lock`.
+    pub fn schema<P: AsRef<Path>>(mut self, schema: P) -> Builder {
+        self.schemas.push(schema.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn lang(mut self, lang: Lang) -> Builder {
+        if !self.langs.contains(&lang) {
+            self.langs.push(lang);
+        }
+        self
+    }
+
+    /// Directory the files are written in. Defaults to the current directory.
+    pub fn out_dir<P: AsRef<Path>>(mut self, dir: P) -> Builder {
+        self.out_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Directory the files of `lang` are written in, instead of `out_dir`.
+    pub fn lang_out_dir<P: AsRef<Path>>(mut self, lang: Lang, dir: P) -> Builder {
+        self.lang_out_dirs.retain(|(l, _dir)| *l != lang);
+        self.lang_out_dirs.push((lang, dir.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Only generate the encoders of the messages `side` sends, and the decoders of those
+    /// it receives, e.g. `up` messages are only encoded for the MCU. By default, every
+    /// message can be encoded and decoded.
+    pub fn side(mut self, side: Side) -> Builder {
+        self.side = Some(side);
+        self
+    }
+
+    /// Print `cargo:rerun-if-changed` lines for the schema files and the lock file. Enabled by default.
+    pub fn cargo_rerun(mut self, enabled: bool) -> Builder {
+        self.cargo_rerun = enabled;
+        self
+    }
+
+    /// Pin the ids of new messages in the lock file. Disabled by default, so that a build
+    /// script never writes into the source tree: `generate` fails instead if messages are
+    /// missing from the lock, until the `message_generator` binary is run on the schema.
+    pub fn update_lock(mut self, enabled: bool) -> Builder {
+        self.update_lock = enabled;
+        self
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        lock::lock_path(&self.schemas[0])
+    }
+
+    fn dir_of(&self, lang: Lang) -> &Path {
+        self.lang_out_dirs
+            .iter()
+            .find(|(l, _dir)| *l == lang)
+            .map_or(&self.out_dir, |(_l, dir)| dir)
+    }
+
+    /// Add the file at `path` to `files`, after the files it includes. Files included
+    /// several times, or by each other, are only added once.
+    fn read_schema(
+        path: &Path,
+        contents: String,
+        files: &mut Vec<(PathBuf, String)>,
+        seen: &mut Vec<PathBuf>,
+    ) -> Result<(), Vec<Diagnostic>> {
+        // The same file can be included with different relative paths.
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if seen.contains(&canonical) {
+            return Ok(());
+        }
+        seen.push(canonical);
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in parser::includes(&contents).map_err(|e| in_file(e, path))? {
+            let included = dir.join(&include);
+            let included_contents = fs::read_to_string(&included).map_err(|e| {
+                vec![
+                    Diagnostic::new("include", format!("fail to read {}: {}", include, e))
+                        .with_span(Locator::new(&contents).locate(&["include"]))
+                        .with_file(path.display().to_string())
+                        .with_hint("included files are relative to the file including them"),
+                ]
+            })?;
+            Builder::read_schema(&included, included_contents, files, seen)?;
+        }
+
+        files.push((path.to_path_buf(), contents));
+        Ok(())
+    }
+
+    /// Parse the schema files, along with the files they include.
+    fn load(&self) -> Result<Loaded, Vec<Diagnostic>> {
+        let mut files = vec![];
+        let mut seen = vec![];
+        for schema in &self.schemas {
+            let contents = fs::read_to_string(schema)
+                .map_err(|e| vec![io_error(schema, "fail to read", e)])?;
+            Builder::read_schema(schema, contents, &mut files, &mut seen)?;
+        }
+
+        let lock_path = self.lock_path();
+        let lock_contents = fs::read_to_string(&lock_path).ok();
+        let id_lock = match &lock_contents {
+            Some(lock_contents) => {
+                IdLock::from_toml(lock_contents).map_err(|e| in_file(e, &lock_path))?
+            }
+            None => IdLock::default(),
+        };
+
+        let names = files
+            .iter()
+            .map(|(path, _contents)| path.display().to_string())
+            .collect::<Vec<String>>();
+        let sources = names
+            .iter()
+            .zip(&files)
+            .map(|(name, (_path, contents))| (name.as_str(), contents.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        let schema = parser::parse_files(&sources, &id_lock)?;
+
+        Ok(Loaded {
+            schema,
+            paths: files.into_iter().map(|(path, _contents)| path).collect(),
+            id_lock,
+            lock_contents,
+        })
+    }
+
+    fn render_messages(&self, mut schema: Schema) -> Vec<GeneratedFile> {
+        schema.messages.push(MsgSpec::uid_msg());
+        schema.side = self.side;
+        let uid = uid::schema_uid(&schema);
+
+        self.langs
+            .iter()
+            .flat_map(|lang| {
+                lang.generate_messages(&schema, uid)
+                    .into_iter()
+                    .map(move |(f, contents)| GeneratedFile {
+                        lang: *lang,
+                        path: self.dir_of(*lang).join(f),
+                        contents,
+                    })
+            })
+            .collect()
+    }
+
+    /// Generate the files without writing anything, not even the lock file.
+    pub fn render(&self) -> Result<Vec<GeneratedFile>, Vec<Diagnostic>> {
+        Ok(self.render_messages(self.load()?.schema))
+    }
+
+    /// Generate and write the files, creating directories as needed. The lock file is
+    /// updated if enabled by `update_lock`, otherwise it shall already pin every message.
+    pub fn generate(&self) -> Result<Vec<GeneratedFile>, Vec<Diagnostic>> {
+        let Loaded {
+            schema,
+            paths,
+            mut id_lock,
+            lock_contents,
+        } = self.load()?;
+
+        let old_lock = id_lock.to_toml();
+        id_lock.update(&schema.messages);
+        let new_lock = id_lock.to_toml();
+        let lock_path = self.lock_path();
+        if !self.update_lock && new_lock != old_lock {
+            return Err(vec![Diagnostic::new("", "the lock file is out of date")
+                .with_file(lock_path.display().to_string())
+                .with_hint(
+                    "run the message generator on the schema to pin the ids of the new \
+                     messages, then commit the lock file",
+                )]);
+        }
+        // Only write the lock if it changed, so cargo doesn't rerun the build script every time.
+        if lock_contents.as_ref() != Some(&new_lock) && self.update_lock {
+            fs::write(&lock_path, new_lock)
+                .map_err(|e| vec![io_error(&lock_path, "fail to write", e)])?;
+        }
+
+        let files = self.render_messages(schema);
+        for file in &files {
+            if let Some(dir) = file.path.parent() {
+                fs::create_dir_all(dir).map_err(|e| vec![io_error(dir, "fail to create", e)])?;
+            }
+            fs::write(&file.path, &file.contents)
+                .map_err(|e| vec![io_error(&file.path, "fail to write", e)])?;
+        }
+
+        if self.cargo_rerun {
+            for path in &paths {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+            println!("cargo:rerun-if-changed={}", lock_path.display());
+        }
+
+        Ok(files)
+    }
+}
@@ -1,25 +1,131 @@
 use crate::generator::Generator;
-use crate::message::{MsgSpec, Type};
+use crate::message::{
+    BitRange, BitfieldSpec, ConstSpec, DefaultValue, Endianness, EnumSpec, Field, MsgSpec, Scale,
+    Schema, StructSpec, Type,
+};
 extern crate inflector;
 use inflector::Inflector;
+use std::rc::Rc;
 
 pub struct CGenerator;
 
 impl CGenerator {
     const HEADER_H: &'static str =
-        "#ifndef MESSAGES_H\n#define MESSAGES_H\n\n#include <stdint.h>\n#include <string.h>";
+        "#ifndef MESSAGES_H\n#define MESSAGES_H\n\n#include <stdint.h>\n#include <stdbool.h>\n#include <string.h>";
     const FOOTER_H: &'static str = "#endif    // MESSAGES_H";
 
     const HEADER_CPP: &'static str = "#include \"messages.h\"";
     const FOOTER_CPP: &'static str = "";
 
-    fn declare_class(msg: &MsgSpec) -> String {
+    const PARSER_H: &'static str = "enum DuckParserStatus {\n  \
+                                    DUCK_INCOMPLETE,\n  \
+                                    DUCK_COMPLETE,\n  \
+                                    DUCK_BAD_ID,    // unknown id, or a message this side only sends\n  \
+                                    DUCK_BAD_LENGTH,\n  \
+                                    DUCK_BAD_CHECKSUM,\n  \
+                                    DUCK_BAD_VALUE,    // e.g. an unknown enum value, or a variable length beyond the payload\n\
+                                    };\n\n\
+                                    enum DuckParserState {\n  \
+                                    DUCK_IDLE,\n  \
+                                    DUCK_START_1ST,\n  \
+                                    DUCK_START_2ND,\n  \
+                                    DUCK_MSG_ID,\n  \
+                                    DUCK_MSG_LEN,\n\
+                                    };\n\n\
+                                    /* Receive side of the link, mirroring the Python SerialCom state machine.\n \
+                                    * Feed it the incoming bytes one at a time with duck_parser_parse().\n \
+                                    * It does not allocate, and can be called from an ISR. */\n\
+                                    struct DuckParser {\n  \
+                                    enum DuckParserState state;\n  \
+                                    uint8_t nb_received;\n  \
+                                    uint8_t buffer[MAX_MSG_BUFFER_SIZE - 2];    // msg id, msg len, payload and checksum\n\
+                                    };\n\n\
+                                    /* Largest value of the length byte for the message id, 0 if the id is unknown.\n \
+                                    * It only differs from msg_min_len() for messages with variable length fields. */\n\
+                                    uint8_t msg_len(uint8_t id);\n\n\
+                                    /* Smallest value of the length byte for the message id, 0 if the id is unknown. */\n\
+                                    uint8_t msg_min_len(uint8_t id);\n\n\
+                                    void duck_parser_init(struct DuckParser* parser);\n\n\
+                                    /* Returns DUCK_COMPLETE when a valid message has been received and decoded in tmsg. */\n\
+                                    enum DuckParserStatus duck_parser_parse(struct DuckParser* parser, uint8_t c, struct TagMessage* tmsg);";
+
+    const PARSER_C: &'static str = "void duck_parser_init(struct DuckParser* parser) {\n  \
+                                    parser->state = DUCK_IDLE;\n  \
+                                    parser->nb_received = 0;\n\
+                                    }\n\n\
+                                    enum DuckParserStatus duck_parser_parse(struct DuckParser* parser, uint8_t c, struct TagMessage* tmsg) {\n  \
+                                    switch(parser->state) {\n  \
+                                    case DUCK_IDLE:    // wait for 0xFF\n    \
+                                    if(c == 0xFF) {\n      \
+                                    parser->state = DUCK_START_1ST;\n    \
+                                    }\n    \
+                                    break;\n  \
+                                    case DUCK_START_1ST:\n    \
+                                    if(c == 0xFF) {\n      \
+                                    parser->state = DUCK_START_2ND;\n    \
+                                    } else {    // fallback to Idle\n      \
+                                    parser->state = DUCK_IDLE;\n    \
+                                    }\n    \
+                                    break;\n  \
+                                    case DUCK_START_2ND:\n    \
+                                    if(msg_len(c) == 0) {\n      \
+                                    parser->state = DUCK_IDLE;\n      \
+                                    return DUCK_BAD_ID;\n    \
+                                    }\n    \
+                                    parser->buffer[0] = c;    // msg id\n    \
+                                    parser->state = DUCK_MSG_ID;\n    \
+                                    break;\n  \
+                                    case DUCK_MSG_ID:\n    \
+                                    if(c < msg_min_len(parser->buffer[0]) || c > msg_len(parser->buffer[0])) {\n      \
+                                    parser->state = DUCK_IDLE;\n      \
+                                    return DUCK_BAD_LENGTH;\n    \
+                                    }\n    \
+                                    parser->buffer[1] = c;    // msg len, including the 2 checksum bytes\n    \
+                                    parser->nb_received = 0;\n    \
+                                    parser->state = DUCK_MSG_LEN;\n    \
+                                    break;\n  \
+                                    case DUCK_MSG_LEN:\n    \
+                                    parser->buffer[2 + parser->nb_received++] = c;\n    \
+                                    if(parser->nb_received == parser->buffer[1]) {\n      \
+                                    uint8_t len = parser->buffer[1];\n      \
+                                    uint16_t ck = compute_cheksum(parser->buffer, len);    // id, len and payload\n      \
+                                    uint16_t rcv_ck = parser->buffer[len] | (parser->buffer[len + 1] << 8);\n      \
+                                    parser->state = DUCK_IDLE;\n      \
+                                    if(ck != rcv_ck) {\n        \
+                                    return DUCK_BAD_CHECKSUM;\n      \
+                                    }\n      \
+                                    if(!msg_from_bytes(tmsg, parser->buffer + 2, parser->buffer[0], len - 2)) {\n        \
+                                    return DUCK_BAD_VALUE;\n      \
+                                    }\n      \
+                                    return DUCK_COMPLETE;\n    \
+                                    }\n    \
+                                    break;\n  \
+                                    }\n  \
+                                    return DUCK_INCOMPLETE;\n\
+                                    }";
+
+    /// `duck_quantize` is also written as `DuckMsg::quantize` in C++ (lib/CPP), `quantize` in
+    /// Python (lib/Python) and `RustGenerator::QUANTIZE`. They must stay the same algorithm,
+    /// so that every side sends the same integer for a given value, even on ties or NaN:
+    /// `tests/round_trip.rs` compares them.
+    const QUANTIZE: &'static str =
+        "/* Integer nearest to (v - offset) / scale, within [lo, hi]. */\n\
+         static inline double duck_quantize(double v, double scale, double offset, double lo, double hi) {\n  \
+         double x = (v - offset) / scale + 0.5;\n  \
+         x = x >= lo ? x : lo;    // also catches NaN\n  \
+         x = x <= hi ? x : hi;\n  \
+         double t = (double)(int64_t)x;    // floor, without libm\n  \
+         return t > x ? t - 1 : t;\n\
+         }";
+
+    fn declare_class(msg: &MsgSpec, schema: &Schema) -> String {
         let vars = msg
             .fields
             .iter()
             .map(|field| {
                 format!(
-                    "  {}",
+                    "{}  {}",
+                    CGenerator::field_doc(field),
                     CGenerator::declare_variable(field.name.as_ref(), &field.t)
                 )
             })
@@ -28,42 +134,592 @@ impl CGenerator {
 
         let size = msg.get_buffer_size();
 
+        let sname = msg.name.to_snake_case();
+        let (scaled, _definitions) =
+            CGenerator::scaled_accessors(&msg.name, &sname, "msg", &msg.fields);
+        let scaled = if scaled.is_empty() {
+            scaled
+        } else {
+            format!("\n{}\n", scaled)
+        };
+
+        let from_bytes = if schema.decodes(msg) {
+            format!(
+                "/* Decodes the len bytes of payload in buffer.\n \
+                 * Returns false if len doesn't match the lengths of the fields,\n \
+                 * or if a decoded value is out of range, e.g. an unknown enum value. */\n\
+                 bool {sname}_from_bytes(union Message_t* msg_u, uint8_t *buffer, uint8_t len);\n",
+                sname = sname
+            )
+        } else {
+            String::new()
+        };
+        let to_bytes = if schema.encodes(msg) {
+            format!(
+                "/* Returns the size of the frame written in buffer, at most SIZE_{name}. */\n\
+                 int {sname}_to_bytes(struct {name}* msg, uint8_t *buffer);\n",
+                name = msg.name,
+                sname = sname
+            )
+        } else {
+            String::new()
+        };
+
         let code = format!(
             "#define SIZE_{name} {size}\n\
+             #define MIN_SIZE_{name} {min_size}\n\
              #define  ID_{name} {id}\n\n\
-             struct {name}{{\n\
+             {doc}struct {name}{{\n\
              {vars}\n}};\n\n\
-             void {sname}_from_bytes(union Message_t* msg_u, uint8_t *buffer);\n\
-             void {sname}_to_bytes(struct {name}* msg, uint8_t *buffer);\n\n\
+             {from_bytes}\
+             {to_bytes}\
+             /* Sets the fields to their default values. */\n\
+             void init_{sname}(struct {name}* msg);\n\
+             {scaled}\n\
              ",
             size = size,
+            min_size = msg.get_min_buffer_size(),
             id = msg.id,
             name = msg.name,
-            sname = msg.name.to_snake_case(),
-            vars = vars
+            sname = sname,
+            doc = msg
+                .description()
+                .map_or(String::new(), |doc| CGenerator::doc_comment(&doc, "")),
+            vars = vars,
+            from_bytes = from_bytes,
+            to_bytes = to_bytes,
+            scaled = scaled
         );
 
         code
     }
 
-    fn deserialise_var(name: &str, ty: &Type) -> String {
+    /// Sizes of the values that are written with the duck_write/duck_read helpers.
+    /// Smaller values and chars don't depend on the byte order and are just copied.
+    const ORDERED_SIZES: [usize; 3] = [2, 4, 8];
+
+    /// Helpers writing and reading values in the wire byte order, byte per byte.
+    /// When the target is known to have the same byte order, they are a plain memcpy.
+    /// Also used by the C++ generator.
+    pub fn byte_order(schema: &Schema) -> String {
+        let endianness = schema.endianness;
+        let (order, macro_order) = match endianness {
+            Endianness::Little => ("little", "LITTLE"),
+            Endianness::Big => ("big", "BIG"),
+        };
+
+        let helpers = CGenerator::ORDERED_SIZES
+            .iter()
+            .map(|&size| {
+                let bits = 8 * size;
+                let writes = (0..size)
+                    .map(|i| {
+                        format!(
+                            "  buffer[{}] = (uint8_t)(v >> {});",
+                            i,
+                            endianness.shift(i, size)
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                let reads = (0..size)
+                    .map(|i| {
+                        format!(
+                            "(uint{}_t)buffer[{}] << {}",
+                            bits,
+                            i,
+                            endianness.shift(i, size)
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" | ");
+
+                format!(
+                    "static inline void duck_write{bits}(uint8_t *buffer, const void *value) {{\n\
+                     #ifdef DUCK_NATIVE_ORDER\n  \
+                     memcpy(buffer, value, {size});\n\
+                     #else\n  \
+                     uint{bits}_t v;\n  \
+                     memcpy(&v, value, {size});\n\
+                     {writes}\n\
+                     #endif\n\
+                     }}\n\n\
+                     static inline void duck_read{bits}(void *value, const uint8_t *buffer) {{\n\
+                     #ifdef DUCK_NATIVE_ORDER\n  \
+                     memcpy(value, buffer, {size});\n\
+                     #else\n  \
+                     uint{bits}_t v = {reads};\n  \
+                     memcpy(value, &v, {size});\n\
+                     #endif\n\
+                     }}",
+                    bits = bits,
+                    size = size,
+                    writes = writes,
+                    reads = reads
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        // f64 fields are copied from and to doubles, which are only 32 bits on some targets (e.g. AVR).
+        let double_check = if schema.has_f64() {
+            "\n\n/* f64 fields need 64 bits doubles. */\n\
+             typedef char duck_double_is_64_bits[sizeof(double) == 8 ? 1 : -1];"
+        } else {
+            ""
+        };
+
         format!(
-            "  memcpy(&msg->{name}, buffer+offset, {size});\n  \
-             offset += {size};",
-            name = name,
-            size = ty.get_size()
+            "/* Fields are sent {order}-endian, whatever the byte order of the target. */\n\
+             #if defined(__BYTE_ORDER__) && __BYTE_ORDER__ == __ORDER_{macro_order}_ENDIAN__\n\
+             #define DUCK_NATIVE_ORDER\n\
+             #endif\n\n\
+             {helpers}{double_check}",
+            order = order,
+            macro_order = macro_order,
+            helpers = helpers,
+            double_check = double_check
         )
     }
 
-    fn serialise_var(name: &str, ty: &Type) -> String {
+    /// Whether `ty` is written with the duck_write/duck_read helpers.
+    pub fn is_ordered(ty: &Type) -> bool {
+        match ty {
+            Type::Bool
+            | Type::CHARS(_)
+            | Type::Array(..)
+            | Type::VarChars(_)
+            | Type::VarArray(..)
+            | Type::Struct(_) => false,
+            _ => CGenerator::ORDERED_SIZES.contains(&ty.get_size()),
+        }
+    }
+
+    /// Element of the array `lvalue`, and the index of the loop over it: `i`, then `j`, `k`...
+    /// when arrays are nested through the fields of structs. Also used by the C++ generator.
+    pub fn element(lvalue: &str) -> (char, String) {
+        let index = (b'i' + lvalue.matches('[').count() as u8) as char;
+        (index, format!("{}[{}]", lvalue, index))
+    }
+
+    /// Loop over the elements of an array, with `code` reading or writing the element at `index`.
+    fn for_each(index: char, len: &str, code: String) -> String {
         format!(
-            "  memcpy(buffer+offset, &msg->{name}, {size});\n  \
-             offset += {size};",
-            name = name,
-            size = ty.get_size()
+            "  for(int {i}=0; {i}<{}; {i}++) {{\n  {}\n  }}",
+            len,
+            code.replace('\n', "\n  "),
+            i = index
+        )
+    }
+
+    /// Reads or writes each field of the struct `lvalue`, in order.
+    fn for_fields(lvalue: &str, st: &StructSpec, code: fn(&str, &Type) -> String) -> String {
+        st.fields
+            .iter()
+            .map(|field| code(&format!("{}.{}", lvalue, field.name), &field.t))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Code reading `lvalue` from `buffer+offset`, then moving `offset` past it.
+    /// Also used by the C++ generator.
+    pub fn read_value(lvalue: &str, ty: &Type) -> String {
+        let size = ty.get_size();
+        let read = match ty {
+            Type::Array(elt, len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                return CGenerator::for_each(
+                    i,
+                    &len.to_string(),
+                    CGenerator::read_value(&element, elt),
+                );
+            }
+            Type::Struct(st) => return CGenerator::for_fields(lvalue, st, CGenerator::read_value),
+            // The length is clamped, so that a bad frame can't overflow the array.
+            Type::VarChars(max_len) => {
+                return format!(
+                    "  {lvalue}_len = buffer[offset] < {max_len} ? buffer[offset] : {max_len};\n  \
+                     offset += 1;\n  \
+                     memcpy({lvalue}, buffer+offset, {lvalue}_len);\n  \
+                     offset += {lvalue}_len;",
+                    lvalue = lvalue,
+                    max_len = max_len
+                )
+            }
+            Type::VarArray(elt, max_len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                return format!(
+                    "  {lvalue}_len = buffer[offset] < {max_len} ? buffer[offset] : {max_len};\n  \
+                     offset += 1;\n\
+                     {read}",
+                    lvalue = lvalue,
+                    max_len = max_len,
+                    read = CGenerator::for_each(
+                        i,
+                        &format!("{}_len", lvalue),
+                        CGenerator::read_value(&element, elt),
+                    )
+                );
+            }
+            Type::Bool => format!("{} = buffer[offset] != 0;", lvalue),
+            _ if CGenerator::is_ordered(ty) => {
+                format!("duck_read{}(&{}, buffer+offset);", 8 * size, lvalue)
+            }
+            _ => format!("memcpy(&{}, buffer+offset, {});", lvalue, size),
+        };
+        format!("  {}\n  offset += {};", read, size)
+    }
+
+    /// Code writing `lvalue` at `buffer+offset`, then moving `offset` past it.
+    /// Also used by the C++ generator.
+    pub fn write_value(lvalue: &str, ty: &Type) -> String {
+        let size = ty.get_size();
+        let write = match ty {
+            Type::Array(elt, len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                return CGenerator::for_each(
+                    i,
+                    &len.to_string(),
+                    CGenerator::write_value(&element, elt),
+                );
+            }
+            Type::VarChars(_) | Type::VarArray(..) => {
+                return CGenerator::write_var_len(lvalue, ty);
+            }
+            Type::Struct(st) => return CGenerator::for_fields(lvalue, st, CGenerator::write_value),
+            Type::Bool => format!("buffer[offset] = {} ? 1 : 0;", lvalue),
+            _ if CGenerator::is_ordered(ty) => {
+                format!("duck_write{}(buffer+offset, &{});", 8 * size, lvalue)
+            }
+            _ => format!("memcpy(buffer+offset, &{}, {});", lvalue, size),
+        };
+        format!("  {}\n  offset += {};", write, size)
+    }
+
+    /// Code returning false if `lvalue` is out of range, `None` if any value of `ty` is valid.
+    /// Also used by the C++ generator, that declares the same `<enum>_is_valid` functions.
+    pub fn check_value(lvalue: &str, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Enum(e) => Some(format!(
+                "  if(!{}_is_valid({})) {{\n    return false;\n  }}",
+                e.name.to_snake_case(),
+                lvalue
+            )),
+            Type::Array(elt, len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                CGenerator::check_value(&element, elt)
+                    .map(|check| CGenerator::for_each(i, &len.to_string(), check))
+            }
+            Type::VarArray(elt, _max_len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                CGenerator::check_value(&element, elt)
+                    .map(|check| CGenerator::for_each(i, &format!("{}_len", lvalue), check))
+            }
+            Type::Struct(st) => {
+                let checks = st
+                    .fields
+                    .iter()
+                    .filter_map(|field| {
+                        CGenerator::check_value(&format!("{}.{}", lvalue, field.name), &field.t)
+                    })
+                    .collect::<Vec<String>>();
+                if checks.is_empty() {
+                    None
+                } else {
+                    Some(checks.join("\n"))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// C enums, along with the declaration and the definition of their `<enum>_is_valid` function.
+    fn enums(enums: &[Rc<EnumSpec>]) -> (String, String) {
+        let declarations = enums
+            .iter()
+            .map(|e| {
+                let values = e
+                    .variants
+                    .iter()
+                    .map(|(name, value)| {
+                        format!(
+                            "  {}_{} = {},",
+                            e.name.to_screaming_snake_case(),
+                            name.to_screaming_snake_case(),
+                            value
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "/* Sent as {repr}. */\n\
+                     enum {name} {{\n\
+                     {values}\n\
+                     }};\n\n\
+                     bool {sname}_is_valid({repr} v);",
+                    name = e.name,
+                    sname = e.name.to_snake_case(),
+                    repr = CGenerator::get_type(&e.repr),
+                    values = values
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let definitions = enums
+            .iter()
+            .map(|e| {
+                let cases = e
+                    .variants
+                    .iter()
+                    .map(|(name, _value)| {
+                        format!(
+                            "  case {}_{}:",
+                            e.name.to_screaming_snake_case(),
+                            name.to_screaming_snake_case()
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "bool {sname}_is_valid({repr} v) {{\n  \
+                     switch(v) {{\n\
+                     {cases}\n    \
+                     return true;\n  \
+                     default:\n    \
+                     return false;\n  \
+                     }}\n\
+                     }}",
+                    sname = e.name.to_snake_case(),
+                    repr = CGenerator::get_type(&e.repr),
+                    cases = cases
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        (declarations, definitions)
+    }
+
+    /// Expression reading `range` from `bits`, `t` being the type of the bitfield.
+    /// Also used by the C++ generator.
+    pub fn bit_get(bits: &str, range: &BitRange, t: &str) -> String {
+        let shifted = if range.start == 0 {
+            bits.to_string()
+        } else {
+            format!("({} >> {})", bits, range.start)
+        };
+        if range.width == 1 {
+            format!("({} & 0x1) != 0", shifted)
+        } else {
+            format!("({})({} & 0x{:X})", t, shifted, range.max())
+        }
+    }
+
+    /// Statements writing `value` into `range` of `bits`, the value being clamped to the range.
+    /// Also used by the C++ generator.
+    pub fn bit_set(bits: &str, range: &BitRange, value: &str, t: &str, nb_bits: u32) -> String {
+        let clamp = if range.width > 1 && range.width < nb_bits {
+            format!(
+                "  if({value} > 0x{max:X}) {{\n    {value} = 0x{max:X};\n  }}\n",
+                value = value,
+                max = range.max()
+            )
+        } else {
+            String::new()
+        };
+        let shifted = if range.start == 0 {
+            format!("({}){}", t, value)
+        } else {
+            format!("(({}){} << {})", t, value, range.start)
+        };
+        format!(
+            "{clamp}  {bits} = ({t})(({bits} & ~({t})0x{mask:X}) | {shifted});",
+            clamp = clamp,
+            bits = bits,
+            t = t,
+            mask = range.mask(),
+            shifted = shifted
+        )
+    }
+
+    /// Getters and setters of the bit ranges of each bitfield, declared then defined.
+    fn bitfields(bitfields: &[Rc<BitfieldSpec>]) -> (String, String) {
+        let mut declarations = vec![];
+        let mut definitions = vec![];
+        for bf in bitfields {
+            let t = CGenerator::get_type(&bf.repr);
+            let nb_bits = 8 * bf.repr.get_size() as u32;
+            let mut functions = vec![];
+            for range in &bf.ranges {
+                let (value_t, comment) = if range.width == 1 {
+                    ("bool".to_string(), String::new())
+                } else if range.width < nb_bits {
+                    (
+                        t.clone(),
+                        format!("/* {} is clamped to {}. */\n", range.name, range.max()),
+                    )
+                } else {
+                    (t.clone(), String::new())
+                };
+                let getter = format!(
+                    "{value_t} {sname}_get_{name}({t} bits)",
+                    value_t = value_t,
+                    sname = bf.name.to_snake_case(),
+                    name = range.name,
+                    t = t
+                );
+                let setter = format!(
+                    "void {sname}_set_{name}({t} *bits, {value_t} {name})",
+                    value_t = value_t,
+                    sname = bf.name.to_snake_case(),
+                    name = range.name,
+                    t = t
+                );
+                functions.push(format!("{};\n{}{};", getter, comment, setter));
+                definitions.push(format!(
+                    "{getter} {{\n  return {get};\n}}\n\n\
+                     {setter} {{\n{set}\n}}",
+                    getter = getter,
+                    get = CGenerator::bit_get("bits", range, &t),
+                    setter = setter,
+                    set = CGenerator::bit_set("*bits", range, &range.name, &t, nb_bits)
+                ));
+            }
+            declarations.push(format!(
+                "/* Bits of {}, sent as {}. */\n{}",
+                bf.name,
+                t,
+                functions.join("\n")
+            ));
+        }
+        (declarations.join("\n\n"), definitions.join("\n\n"))
+    }
+
+    /// Expression converting the scaled integer `raw` to the value it stands for.
+    /// Also used by the other generators.
+    pub fn unscale(raw: &str, scale: &Scale) -> String {
+        if scale.offset == 0.0 {
+            format!("{} * {:?}", raw, scale.scale)
+        } else if scale.offset < 0.0 {
+            format!("{} * {:?} - {:?}", raw, scale.scale, -scale.offset)
+        } else {
+            format!("{} * {:?} + {:?}", raw, scale.scale, scale.offset)
+        }
+    }
+
+    /// Arguments of the quantize helper for `value`, `t` being the integer type sent.
+    /// They are float literals in every language. Also used by the other generators.
+    pub fn quantize_args(value: &str, t: &Type, scale: &Scale) -> String {
+        let b = match t {
+            Type::I8(b)
+            | Type::I16(b)
+            | Type::I32(b)
+            | Type::U8(b)
+            | Type::U16(b)
+            | Type::U32(b) => b,
+            _ => unreachable!(),
+        };
+        format!(
+            "{}, {:?}, {:?}, {:?}, {:?}",
+            value, scale.scale, scale.offset, b.min as f64, b.max as f64
+        )
+    }
+
+    /// Float getters and setters of the scaled fields of the struct `name`, declared then
+    /// defined. They are prefixed by `sname`, take the struct as `var`, and the index of
+    /// the element for arrays.
+    fn scaled_accessors(name: &str, sname: &str, var: &str, fields: &[Field]) -> (String, String) {
+        let mut declarations = vec![];
+        let mut definitions = vec![];
+        for field in fields {
+            if let Type::Scaled(t, scale) = field.t.scalar() {
+                let (index, lvalue) = match field.t {
+                    Type::Array(..) | Type::VarArray(..) => {
+                        (", int i", format!("{}->{}[i]", var, field.name))
+                    }
+                    _ => ("", format!("{}->{}", var, field.name)),
+                };
+                let getter = format!(
+                    "double {sname}_get_{field}(const struct {name}* {var}{index})",
+                    sname = sname,
+                    field = field.name,
+                    name = name,
+                    var = var,
+                    index = index
+                );
+                let setter = format!(
+                    "void {sname}_set_{field}(struct {name}* {var}{index}, double value)",
+                    sname = sname,
+                    field = field.name,
+                    name = name,
+                    var = var,
+                    index = index
+                );
+                declarations.push(format!("{};\n{};", getter, setter));
+                definitions.push(format!(
+                    "{getter} {{\n  return {get};\n}}\n\n\
+                     {setter} {{\n  {lvalue} = ({t})duck_quantize({args});\n}}",
+                    getter = getter,
+                    get = CGenerator::unscale(&lvalue, scale),
+                    setter = setter,
+                    lvalue = lvalue,
+                    t = CGenerator::get_type(t),
+                    args = CGenerator::quantize_args("value", t, scale)
+                ));
+            }
+        }
+        if !declarations.is_empty() {
+            declarations.insert(
+                0,
+                format!(
+                    "/* Scaled fields of {}, setters round and clamp the value. */",
+                    name
+                ),
+            );
+        }
+        (declarations.join("\n"), definitions.join("\n\n"))
+    }
+
+    /// Code writing the length byte of a variable length `lvalue`, then its elements.
+    fn write_var_len(lvalue: &str, ty: &Type) -> String {
+        let (elements, max_len) = match ty {
+            Type::VarChars(max_len) => (
+                format!(
+                    "  memcpy(buffer+offset, {}, len);\n  offset += len;",
+                    lvalue
+                ),
+                max_len,
+            ),
+            Type::VarArray(elt, max_len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                (
+                    CGenerator::for_each(i, "len", CGenerator::write_value(&element, elt)),
+                    max_len,
+                )
+            }
+            _ => unreachable!(),
+        };
+        format!(
+            "  {{\n    \
+             uint8_t len = {lvalue}_len < {max_len} ? {lvalue}_len : {max_len};\n    \
+             buffer[offset++] = len;\n  \
+             {elements}\n  \
+             }}",
+            lvalue = lvalue,
+            max_len = max_len,
+            elements = elements.replace('\n', "\n  ")
         )
     }
 
+    fn deserialise_var(name: &str, ty: &Type) -> String {
+        CGenerator::read_value(&format!("msg->{}", name), ty)
+    }
+
+    fn serialise_var(name: &str, ty: &Type) -> String {
+        CGenerator::write_value(&format!("msg->{}", name), ty)
+    }
+
     fn to_bytes(msg: &MsgSpec) -> String {
         let serialisations = msg
             .fields
@@ -73,16 +729,18 @@ impl CGenerator {
             .join("\n");
 
         let code = format!(
-            "void {sname}_to_bytes(struct {name}* msg, uint8_t *buffer) {{\n  \
+            "int {sname}_to_bytes(struct {name}* msg, uint8_t *buffer) {{\n  \
              int offset = 0;\n  \
              buffer[offset++] = 0xFF;\n  \
              buffer[offset++] = 0xFF;\n  \
              buffer[offset++] = ID_{name};\n  \
-             buffer[offset++] = SIZE_{name} - 4;\n\
+             offset++;    // msg len, known once the payload is written\n\
              {serialisations}\n  \
-             uint16_t checksum = compute_cheksum(buffer+2, SIZE_{name} - 4);\n  \
+             buffer[3] = offset - 2;\n  \
+             uint16_t checksum = compute_cheksum(buffer+2, offset - 2);\n  \
              buffer[offset++] = checksum & 0XFF;\n  \
-             buffer[offset++] = (checksum>>8) & 0XFF;\n\
+             buffer[offset++] = (checksum>>8) & 0XFF;\n  \
+             return offset;\n\
              }}",
             sname = msg.name.to_snake_case(),
             name = msg.name,
@@ -92,99 +750,278 @@ impl CGenerator {
         code
     }
 
-    fn get_type(ty: &Type) -> &str {
-        match ty {
+    /// C type of `ty`, or of its elements.
+    fn get_type(ty: &Type) -> String {
+        let t = match ty {
+            Type::Bool => "bool",
             Type::I8(_b) => "int8_t",
             Type::I16(_b) => "int16_t",
             Type::I32(_b) => "int32_t",
+            Type::I64(_b) => "int64_t",
             Type::U8(_b) => "uint8_t",
             Type::U16(_b) => "uint16_t",
             Type::U32(_b) => "uint32_t",
+            Type::U64(_b) => "uint64_t",
             Type::F32(_b) => "float",
-            Type::CHARS(_size) => "char*",
-        }
+            Type::F64(_b) => "double",
+            Type::CHARS(_size) | Type::VarChars(_size) => "char",
+            Type::Array(elt, _len) | Type::VarArray(elt, _len) => return CGenerator::get_type(elt),
+            // C enums have the size of an int, the value is stored as the type it is sent as.
+            Type::Enum(e) => return CGenerator::get_type(&e.repr),
+            Type::Struct(st) => return format!("struct {}", st.name),
+            Type::Bitfield(bf) => return CGenerator::get_type(&bf.repr),
+            Type::Scaled(t, _scale) => return CGenerator::get_type(t),
+        };
+        t.to_string()
     }
 
-    fn declare_variable(name: &str, ty: &Type) -> String {
+    /// Literal of a 64 bits integer. `-9223372036854775808` would be parsed as the negation
+    /// of a literal that doesn't fit in an int64_t. Also used by the C++ generator.
+    pub fn int64_literal(v: i128, ty: &Type) -> String {
         match ty {
-            Type::I8(_b) => format!("int8_t {};", name),
-            Type::I16(_b) => format!("int16_t {};", name),
-            Type::I32(_b) => format!("int32_t {};", name),
-            Type::U8(_b) => format!("uint8_t {};", name),
-            Type::U16(_b) => format!("uint16_t {};", name),
-            Type::U32(_b) => format!("uint32_t {};", name),
-            Type::F32(_b) => format!("float {};", name),
-            Type::CHARS(size) => format!("char {}[{}];", name, size),
+            Type::U64(_) => format!("{}ULL", v),
+            _ if v == i128::from(i64::MIN) => "(-9223372036854775807LL - 1)".to_string(),
+            _ => format!("{}LL", v),
         }
     }
 
-    fn make_get_set(name: &str, ty: &Type) -> String {
-        let setter = match ty {
-            Type::CHARS(size) => format!(
-                "  void set_{name}({t} {name}) {{\n    strncpy({name}, {name}, {size});\n  }}",
-                name = name,
-                t = CGenerator::get_type(ty),
-                size = size
-            ),
-            Type::I8(b)
-            | Type::I16(b)
-            | Type::I32(b)
-            | Type::U8(b)
-            | Type::U16(b)
-            | Type::U32(b) => format!(
-                "  void set_{name}({t} {name}){{ {name} = clamp({min}, {name}, {max}); }}",
-                name = name,
-                t = CGenerator::get_type(ty),
-                min = b.min,
-                max = b.max
-            ),
-            Type::F32(b) => format!(
-                "  void set_{name}({t} {name}){{ {name} = clamp({min:.2}, {name}, {max:.2}); }}",
-                name = name,
-                t = CGenerator::get_type(ty),
-                min = b.min,
-                max = b.max
+    /// String literal of `s`, the bytes that are not printable being escaped in octal.
+    /// Also used by the C++ generator.
+    pub fn string_literal(s: &str) -> String {
+        let mut literal = String::from("\"");
+        for b in s.bytes() {
+            match b {
+                b'"' | b'\\' => {
+                    literal.push('\\');
+                    literal.push(b as char);
+                }
+                0x20..=0x7E => literal.push(b as char),
+                _ => literal += &format!("\\{:03o}", b),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+
+    /// Literal of the default `value` of `ty`.
+    fn literal(value: &DefaultValue, ty: &Type) -> String {
+        match (value, ty) {
+            (DefaultValue::Int(v), Type::I64(_) | Type::U64(_)) => {
+                CGenerator::int64_literal(*v, ty)
+            }
+            (DefaultValue::Int(v), _) => v.to_string(),
+            (DefaultValue::Bool(v), _) => v.to_string(),
+            (DefaultValue::Float(v), _) => format!("{:?}", v),
+            (DefaultValue::Chars(v), _) => CGenerator::string_literal(v),
+            (DefaultValue::Variant(v), Type::Enum(e)) => format!(
+                "{}_{}",
+                e.name.to_screaming_snake_case(),
+                v.to_screaming_snake_case()
             ),
-        };
+            _ => unreachable!(),
+        }
+    }
 
-        let getter = format!(
-            "  {t} get_{name}(){{ return {name}; }}",
-            t = CGenerator::get_type(ty),
-            name = name
-        );
+    /// Statements setting `lvalue` to its default, or enums to their first value, once
+    /// zeroed. `None` if zero is the initial value.
+    fn init_value(lvalue: &str, ty: &Type, default: Option<&DefaultValue>) -> Option<String> {
+        match (ty, default) {
+            (Type::CHARS(_), Some(DefaultValue::Chars(v))) => Some(format!(
+                "  memcpy({}, {}, {});",
+                lvalue,
+                CGenerator::string_literal(v),
+                v.len()
+            )),
+            (Type::VarChars(_), Some(DefaultValue::Chars(v))) => Some(format!(
+                "  {lvalue}_len = {len};\n  memcpy({lvalue}, {literal}, {len});",
+                lvalue = lvalue,
+                literal = CGenerator::string_literal(v),
+                len = v.len()
+            )),
+            (Type::Array(elt, _len), Some(DefaultValue::List(values))) => {
+                CGenerator::init_elements(lvalue, elt, values)
+            }
+            (Type::Array(elt, len), None) => {
+                let (i, element) = CGenerator::element(lvalue);
+                CGenerator::init_value(&element, elt, None)
+                    .map(|init| CGenerator::for_each(i, &len.to_string(), init))
+            }
+            (Type::VarArray(elt, _max_len), Some(DefaultValue::List(values))) => {
+                let len = format!("  {}_len = {};", lvalue, values.len());
+                match CGenerator::init_elements(lvalue, elt, values) {
+                    Some(init) => Some(format!("{}\n{}", len, init)),
+                    None => Some(len),
+                }
+            }
+            (Type::Struct(st), _) => {
+                Some(format!("  init_{}(&{});", st.name.to_snake_case(), lvalue))
+            }
+            (Type::Enum(e), None) => Some(format!(
+                "  {} = {}_{};",
+                lvalue,
+                e.name.to_screaming_snake_case(),
+                e.variants[0].0.to_screaming_snake_case()
+            )),
+            (_, Some(value)) => Some(format!(
+                "  {} = {};",
+                lvalue,
+                CGenerator::literal(value, ty)
+            )),
+            _ => None,
+        }
+    }
+
+    /// Statements setting the first elements of the array `lvalue` to `values`, in a loop
+    /// when they are all the same.
+    fn init_elements(lvalue: &str, elt: &Type, values: &[DefaultValue]) -> Option<String> {
+        if values.is_empty() {
+            return None;
+        }
+        if values.len() > 1 && values.iter().all(|v| *v == values[0]) {
+            let (i, element) = CGenerator::element(lvalue);
+            return CGenerator::init_value(&element, elt, Some(&values[0]))
+                .map(|init| CGenerator::for_each(i, &values.len().to_string(), init));
+        }
+        let inits = values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| {
+                CGenerator::init_value(&format!("{}[{}]", lvalue, i), elt, Some(v))
+            })
+            .collect::<Vec<String>>();
+        Some(inits.join("\n"))
+    }
+
+    /// Definition of the `init_<sname>` function of the struct `name`, taken as `var`.
+    fn define_init(name: &str, sname: &str, var: &str, fields: &[Field]) -> String {
+        let inits = fields
+            .iter()
+            .filter_map(|field| {
+                CGenerator::init_value(
+                    &format!("{}->{}", var, field.name),
+                    &field.t,
+                    field.default.as_ref(),
+                )
+            })
+            .map(|init| init + "\n")
+            .collect::<String>();
 
-        format!("{}\n{}", getter, setter)
+        format!(
+            "void init_{sname}(struct {name}* {var}) {{\n  \
+             memset({var}, 0, sizeof(*{var}));\n\
+             {inits}\
+             }}",
+            name = name,
+            sname = sname,
+            var = var,
+            inits = inits
+        )
     }
 
-    fn init_variable(name: &str, ty: &Type) -> String {
-        match ty {
-            Type::I8(_b) => format!("  _{} = 0;", name),
-            Type::I16(_b) => format!("  _{} = 0;", name),
-            Type::I32(_b) => format!("  _{} = 0;", name),
-            Type::U8(_b) => format!("  _{} = 0;", name),
-            Type::U16(_b) => format!("  _{} = 0;", name),
-            Type::U32(_b) => format!("  _{} = 0;", name),
-            Type::F32(_b) => format!("  _{} = 0;", name),
-            Type::CHARS(_size) => format!("  _{}[0] = \'\\0\';", name),
+    /// Declaration of a struct from the [types] table.
+    fn declare_struct(st: &StructSpec) -> String {
+        let vars = st
+            .fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "{}  {}",
+                    CGenerator::field_doc(field),
+                    CGenerator::declare_variable(&field.name, &field.t)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "struct {name} {{\n\
+             {vars}\n\
+             }};\n\n\
+             /* Sets the fields to their default values. */\n\
+             void init_{sname}(struct {name}* v);",
+            name = st.name,
+            sname = st.name.to_snake_case(),
+            vars = vars
+        )
+    }
+
+    /// Doxygen comment of `doc`, on lines of their own indented by `indent`.
+    /// Also used by the C++ generator.
+    pub fn doc_comment(doc: &str, indent: &str) -> String {
+        let doc = doc.replace("*/", "* /");
+        let lines = doc.lines().collect::<Vec<&str>>();
+        if lines.len() <= 1 {
+            return format!("{}/** {} */\n", indent, doc);
         }
+        let lines = lines
+            .iter()
+            .map(|line| format!("{} * {}", indent, line).trim_end().to_string() + "\n")
+            .collect::<String>();
+        format!(
+            "{indent}/**\n{lines}{indent} */\n",
+            indent = indent,
+            lines = lines
+        )
     }
 
-    // fn constructor(msg: &MsgSpec) -> String {
-    //     let vars = msg
-    //         .fields
-    //         .iter()
-    //         .map(|field| CGenerator::init_variable(field.name.as_ref(), &field.t))
-    //         .collect::<Vec<String>>()
-    //         .join("\n");
+    /// Doxygen comment of the doc and unit of a field, if it has any.
+    /// Also used by the C++ generator.
+    pub fn field_doc(field: &Field) -> String {
+        field
+            .description()
+            .map_or(String::new(), |doc| CGenerator::doc_comment(&doc, "  "))
+    }
 
-    //     let code = format!(
-    //         "{name}::{name}() {{\n{vars}\n}}",
-    //         name = msg.name,
-    //         vars = vars
-    //     );
+    fn declare_variable(name: &str, ty: &Type) -> String {
+        let t = CGenerator::get_type(ty);
+        match ty {
+            Type::CHARS(len) | Type::Array(_, len) => format!("{} {}[{}];", t, name, len),
+            Type::VarChars(max_len) | Type::VarArray(_, max_len) => format!(
+                "uint8_t {name}_len;\n  {t} {name}[{max_len}];",
+                t = t,
+                name = name,
+                max_len = max_len
+            ),
+            _ => format!("{} {};", t, name),
+        }
+    }
 
-    //     code
-    // }
+    /// Body of a function returning whether `len` is the length of the payload in `buffer`,
+    /// for a message with variable length fields. It walks their length bytes, rejecting
+    /// them above the maximal length, as the Rust decoder does.
+    /// Also used by the C++ generator.
+    pub fn length_ok(msg: &MsgSpec) -> String {
+        let mut code = vec!["  int end = 0;".to_string()];
+        let mut fixed = 0;
+        for field in &msg.fields {
+            let (max_len, elt_size) = match &field.t {
+                Type::VarChars(max_len) => (max_len, 1),
+                Type::VarArray(elt, max_len) => (max_len, elt.get_size()),
+                ty => {
+                    fixed += ty.get_size();
+                    continue;
+                }
+            };
+            if fixed > 0 {
+                code.push(format!("  end += {};", fixed));
+                fixed = 0;
+            }
+            code.push(format!(
+                "  if(end >= len || buffer[end] > {}) {{\n    return false;\n  }}",
+                max_len
+            ));
+            code.push(if elt_size > 1 {
+                format!("  end += 1 + buffer[end] * {};", elt_size)
+            } else {
+                "  end += 1 + buffer[end];".to_string()
+            });
+        }
+        if fixed > 0 {
+            code.push(format!("  end += {};", fixed));
+        }
+        code.push("  return end == len;".to_string());
+        code.join("\n")
+    }
 
     fn constructor_from_bytes(msg: &MsgSpec) -> String {
         let deserialisations = msg
@@ -194,26 +1031,57 @@ impl CGenerator {
             .collect::<Vec<String>>()
             .join("\n");
 
+        let checks = msg
+            .fields
+            .iter()
+            .filter_map(|field| CGenerator::check_value(&format!("msg->{}", field.name), &field.t))
+            .map(|check| check + "\n")
+            .collect::<String>();
+
+        let sname = msg.name.to_snake_case();
+        let (length_ok, check_len) = if msg.is_variable() {
+            (
+                format!(
+                    "static bool {}_length_ok(const uint8_t *buffer, uint8_t len) {{\n{}\n}}\n\n",
+                    sname,
+                    CGenerator::length_ok(msg)
+                ),
+                format!("!{}_length_ok(buffer, len)", sname),
+            )
+        } else {
+            (String::new(), format!("len != {}", msg.get_payload_size()))
+        };
+
         let code = format!(
-            "void {sname}_from_bytes(union Message_t* msg_u, uint8_t *buffer) {{\n  \
+            "{length_ok}\
+             bool {sname}_from_bytes(union Message_t* msg_u, uint8_t *buffer, uint8_t len) {{\n  \
+             if({check_len}) {{\n    \
+             return false;\n  \
+             }}\n  \
              struct {name}* msg = (struct {name}*)msg_u;\n  \
              int offset = 0;\n\
-             {deser}\n}}",
-            sname = msg.name.to_snake_case(),
+             {deser}\n\
+             {checks}  \
+             return true;\n\
+             }}",
+            length_ok = length_ok,
+            sname = sname,
+            check_len = check_len,
             name = msg.name,
-            deser = deserialisations
+            deser = deserialisations,
+            checks = checks
         );
 
         code
     }
 
-    fn make_msg(messages: &Vec<MsgSpec>) -> String {
+    fn make_msg(messages: &[&MsgSpec]) -> String {
         let ifs = messages
             .iter()
             .map(|msg| {
                 format!(
                     "  if(id=={id}) {{\n    \
-                     {sname}_from_bytes(&tmsg->msg, buffer);\n  \
+                     return {sname}_from_bytes(&tmsg->msg, buffer, len);\n  \
                      }}",
                     id = msg.id,
                     sname = msg.name.to_snake_case()
@@ -223,24 +1091,124 @@ impl CGenerator {
             .join("\n");
 
         format!(
-            "void msg_from_bytes(struct TagMessage* tmsg, uint8_t* buffer, uint8_t id) {{\n\
-             {}\n  \
+            "bool msg_from_bytes(struct TagMessage* tmsg, uint8_t* buffer, uint8_t id, uint8_t len) {{\n  \
              tmsg->tag = id;\n\
+             {}\n  \
+             return false;\n\
              }}",
             ifs
         )
     }
+
+    /// `msg_len` or `msg_min_len` function, `prefix` being `` or `MIN_`.
+    fn msg_len(messages: &[&MsgSpec], prefix: &str) -> String {
+        let cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  case ID_{name}: return {prefix}SIZE_{name} - 4;",
+                    name = msg.name,
+                    prefix = prefix
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "uint8_t msg_{}len(uint8_t id) {{\n  \
+             switch(id) {{\n\
+             {}\n  \
+             default: return 0;\n  \
+             }}\n\
+             }}",
+            prefix.to_lowercase(),
+            cases
+        )
+    }
+
+    /// `#define` of each constant, followed by a blank line if there are any.
+    fn define_constants(constants: &[ConstSpec]) -> String {
+        let defines = constants
+            .iter()
+            .map(|c| {
+                let value = CGenerator::literal(&c.value, &c.t);
+                if value.starts_with('-') {
+                    format!("#define {} ({})", c.name, value)
+                } else {
+                    format!("#define {} {}", c.name, value)
+                }
+            })
+            .collect::<Vec<String>>();
+        if defines.is_empty() {
+            String::new()
+        } else {
+            defines.join("\n") + "\n\n"
+        }
+    }
+
+    fn handlers(messages: &[&MsgSpec]) -> (String, String) {
+        let members = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  void (*on_{sname})(struct {name}* msg);",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let declaration = format!(
+            "/* One callback per message. Callbacks left to NULL are ignored. */\n\
+             struct DuckHandlers {{\n\
+             {}\n\
+             }};\n\n\
+             /* Call the callback matching the message in tmsg. */\n\
+             void dispatch_msg(struct DuckHandlers* handlers, struct TagMessage* tmsg);",
+            members
+        );
+
+        let cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  case ID_{name}:\n    \
+                     if(handlers->on_{sname}) {{\n      \
+                     handlers->on_{sname}(&tmsg->msg.{sname});\n    \
+                     }}\n    \
+                     break;",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let definition = format!(
+            "void dispatch_msg(struct DuckHandlers* handlers, struct TagMessage* tmsg) {{\n  \
+             switch(tmsg->tag) {{\n\
+             {}\n  \
+             }}\n\
+             }}",
+            cases
+        );
+
+        (declaration, definition)
+    }
 }
 
 impl Generator for CGenerator {
-    fn generate_messages(messages: &Vec<MsgSpec>, UID: u32) -> Vec<(String, String)> {
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        let messages = &schema.messages;
+        let received = schema.received();
         let declarations = messages
             .iter()
-            .map(|msg| CGenerator::declare_class(msg))
+            .map(|msg| CGenerator::declare_class(msg, schema))
             .collect::<Vec<String>>()
             .join("\n\n\n");
 
-        let union_fields = messages
+        let union_fields = received
             .iter()
             .map(|msg| format!("  struct {} {};", msg.name, msg.name.to_snake_case()))
             .collect::<Vec<String>>()
@@ -254,40 +1222,98 @@ impl Generator for CGenerator {
             .max()
             .unwrap();
 
+        let (handlers_h, handlers_c) = CGenerator::handlers(&received);
+
+        let (mut enums_h, mut enums_c) = CGenerator::enums(&schema.enums);
+        if !schema.enums.is_empty() {
+            enums_h += "\n\n";
+            enums_c += "\n\n";
+        }
+        if !schema.bitfields.is_empty() {
+            let (bitfields_h, bitfields_c) = CGenerator::bitfields(&schema.bitfields);
+            enums_h += &(bitfields_h + "\n\n");
+            enums_c += &(bitfields_c + "\n\n");
+        }
+        for st in &schema.structs {
+            enums_h += &CGenerator::declare_struct(st);
+            enums_h += "\n\n";
+            enums_c +=
+                &CGenerator::define_init(&st.name, &st.name.to_snake_case(), "v", &st.fields);
+            enums_c += "\n\n";
+            let (scaled_h, scaled_c) =
+                CGenerator::scaled_accessors(&st.name, &st.name.to_snake_case(), "v", &st.fields);
+            if !scaled_h.is_empty() {
+                enums_h += &(scaled_h + "\n\n");
+                enums_c += &(scaled_c + "\n\n");
+            }
+        }
+
         let header = format!(
             "{}\n\n\
              #define UID {}\n\n\
-             union Message_t;\n\n\
+             {}union Message_t;\n\n\
              struct TagMessage;\n\n\
              uint16_t compute_cheksum(uint8_t *buffer, int len);\n\n\
              #define MAX_MSG_BUFFER_SIZE {}\n\n\
-             {}\n\n\
+             {}{}\n\n\
              {}\n\n\
              struct TagMessage {{\n  uint8_t tag;\n  union Message_t msg;\n}};\n\n\
-             void msg_from_bytes(struct TagMessage* tmsg, uint8_t* buffer, uint8_t id);\n\n\
+             /* Decodes the len bytes of payload of message id in buffer.\n \
+             * Returns false if the id is unknown, len doesn't match the payload\n \
+             * or a decoded value is out of range. */\n\
+             bool msg_from_bytes(struct TagMessage* tmsg, uint8_t* buffer, uint8_t id, uint8_t len);\n\n\
+             {}\n\n\
+             {}\n\n\
              {}",
             CGenerator::HEADER_H,
-            UID,
+            uid,
+            CGenerator::define_constants(&schema.constants),
             max_size,
+            enums_h,
             declarations,
             union_t,
+            CGenerator::PARSER_H,
+            handlers_h,
             CGenerator::FOOTER_H
         );
-        //            void make_msg(struct TagMessage* tmsg, uint8_t id);\n\n\
 
         let serialisations = messages
             .iter()
             .map(|msg| {
+                let (_declarations, scaled) = CGenerator::scaled_accessors(
+                    &msg.name,
+                    &msg.name.to_snake_case(),
+                    "msg",
+                    &msg.fields,
+                );
+                let scaled = if scaled.is_empty() {
+                    scaled
+                } else {
+                    format!("\n\n{}", scaled)
+                };
+                let mut coders = String::new();
+                if schema.encodes(msg) {
+                    coders += &(CGenerator::to_bytes(msg) + "\n\n");
+                }
+                if schema.decodes(msg) {
+                    coders += &(CGenerator::constructor_from_bytes(msg) + "\n\n");
+                }
                 format!(
-                    "{tb}\n\n{fb}",
-                    tb = CGenerator::to_bytes(msg),
-                    fb = CGenerator::constructor_from_bytes(msg),
+                    "{coders}{init}{scaled}",
+                    coders = coders,
+                    init = CGenerator::define_init(
+                        &msg.name,
+                        &msg.name.to_snake_case(),
+                        "msg",
+                        &msg.fields
+                    ),
+                    scaled = scaled
                 )
             })
             .collect::<Vec<String>>()
             .join("\n\n\n");
 
-        let make_msg = CGenerator::make_msg(messages);
+        let make_msg = CGenerator::make_msg(&received);
 
         let check = "uint16_t compute_cheksum(uint8_t *buffer, int len) {\n  \
                      uint8_t ck_a = 0;\n  \
@@ -300,11 +1326,24 @@ impl Generator for CGenerator {
                      return ck;\n\
                      }";
 
+        let quantize = if schema.has_scaled() {
+            format!("\n\n{}", CGenerator::QUANTIZE)
+        } else {
+            String::new()
+        };
+
         let source = format!(
-            "{}\n\n{}\n\n{}\n\n{}\n\n{}",
+            "{}\n\n{}{}\n\n{}\n\n{}{}\n\n{}\n\n{}\n\n{}\n\n{}\n\n{}\n\n{}",
             CGenerator::HEADER_CPP,
+            CGenerator::byte_order(schema),
+            quantize,
             check,
+            enums_c,
             make_msg,
+            CGenerator::msg_len(&received, ""),
+            CGenerator::msg_len(&received, "MIN_"),
+            CGenerator::PARSER_C,
+            handlers_c,
             serialisations,
             CGenerator::FOOTER_CPP
         );
@@ -1,16 +1,32 @@
+use crate::c_generator::CGenerator;
 use crate::generator::Generator;
-use crate::message::{MsgSpec, Type};
+use crate::message::{
+    BitfieldSpec, ConstSpec, DefaultValue, EnumSpec, MsgSpec, Schema, StructSpec, Type,
+};
+use inflector::Inflector;
+use std::rc::Rc;
 
 pub struct CPPGenerator;
 
 impl CPPGenerator {
-    const HEADER_H: &'static str = "#ifndef MESSAGES_H\n#define MESSAGES_H\n\n#include <stdint.h>\n#include <string.h>\n#include \"Duckmsg.h\"";
+    const HEADER_H: &'static str = "#ifndef MESSAGES_H\n#define MESSAGES_H\n\n#include <stdint.h>\n#include <string.h>\n#include <new>\n#include \"Duckmsg.h\"";
     const FOOTER_H: &'static str = "#endif    // MESSAGES_H";
 
     const HEADER_CPP: &'static str = "#include \"messages.h\"";
     const FOOTER_CPP: &'static str = "";
 
-    fn declare_class(msg: &MsgSpec) -> String {
+    const PARSER_H: &'static str =
+        "// Largest value of the length byte for the message id, 0 if the id is unknown.\n\
+         uint8_t msg_len(uint8_t id);\n\n\
+         // Smallest value of the length byte for the message id, 0 if the id is unknown.\n\
+         uint8_t msg_min_len(uint8_t id);\n\n\
+         // Frame parser that only accepts the messages declared here.\n\
+         class DuckParser: public DuckFrameParser {\n\
+         public:\n  \
+         DuckParser(): DuckFrameParser(msg_len, msg_min_len) {}\n\
+         };";
+
+    fn declare_class(msg: &MsgSpec, schema: &Schema) -> String {
         let vars = msg
             .fields
             .iter()
@@ -26,25 +42,61 @@ impl CPPGenerator {
         let getsets = msg
             .fields
             .iter()
-            .map(|field| CPPGenerator::make_get_set(field.name.as_ref(), &field.t))
+            .map(|field| {
+                CGenerator::field_doc(field)
+                    + &CPPGenerator::make_get_set(field.name.as_ref(), &field.t)
+            })
             .collect::<Vec<String>>()
             .join("\n\n");
 
         let msg_size: usize = msg.get_buffer_size(); // 2 start bytes, 1 byte for the ID, 1 for the length, ..., 2 for the checksum
 
+        let (from_bytes, is_valid) = if schema.decodes(msg) {
+            (
+                format!(
+                    "  // Decodes the payload in buffer, that must have passed length_ok().\n  \
+                     {}(uint8_t *buffer);\n  \
+                     // Whether len is the length of the payload in buffer, as given by its length bytes.\n  \
+                     static bool length_ok(const uint8_t *buffer, uint8_t len);\n",
+                    msg.name
+                ),
+                "  bool is_valid() const;\n",
+            )
+        } else {
+            (String::new(), "")
+        };
+        let to_bytes = if schema.encodes(msg) {
+            "  int to_bytes(uint8_t *buffer);\n"
+        } else {
+            "  // Only received by this side, so sending it doesn't compile.\n  \
+             int to_bytes(uint8_t *buffer) = delete;\n"
+        };
+
         let code = format!(
-            "class {name}: public DuckMsg {{\npublic:\n  \
+            "{doc}class {name}: public DuckMsg {{\npublic:\n  \
              static const size_t SIZE = {size};\n  \
+             static const size_t MIN_SIZE = {min_size};\n  \
              static const uint8_t ID = {id};\n\n  \
-             {name}();\n  \
-             {name}(uint8_t *buffer);\n\n  \
-             void to_bytes(uint8_t *buffer);\n\n\
+             {name}();\n\
+             {from_bytes}\n  \
+             uint8_t get_id() {{ return ID; }}\n\
+             {to_bytes}\
+             {is_valid}  \
+             // Sets the fields to their default values.\n  \
+             void reset();\n\n\
              {getsets}\n\n\
              private:\n\
              {vars}\n}};",
             name = msg.name,
+            doc = msg
+                .description()
+                .map_or(String::new(), |doc| CGenerator::doc_comment(&doc, "")),
             size = msg_size,
+            min_size = msg.get_min_buffer_size(),
             id = msg.id,
+            from_bytes = from_bytes,
+            to_bytes = to_bytes,
+            is_valid = is_valid,
             getsets = getsets,
             vars = vars
         );
@@ -53,21 +105,11 @@ impl CPPGenerator {
     }
 
     fn deserialise_var(name: &str, ty: &Type) -> String {
-        format!(
-            "  memcpy(&_{name}, buffer+offset, {size});\n  \
-             offset += {size};",
-            name = name,
-            size = ty.get_size()
-        )
+        CGenerator::read_value(&format!("_{}", name), ty)
     }
 
     fn serialise_var(name: &str, ty: &Type) -> String {
-        format!(
-            "  memcpy(buffer+offset, &_{name}, {size});\n  \
-             offset += {size};",
-            name = name,
-            size = ty.get_size()
-        )
+        CGenerator::write_value(&format!("_{}", name), ty)
     }
 
     fn to_bytes(msg: &MsgSpec) -> String {
@@ -79,20 +121,21 @@ impl CPPGenerator {
             .join("\n");
 
         let code = format!(
-            "void {name}::to_bytes(uint8_t *buffer) {{\n  \
+            "int {name}::to_bytes(uint8_t *buffer) {{\n  \
              int offset = 0;\n  \
              buffer[offset++] = 0xFF;\n  \
              buffer[offset++] = 0xFF;\n  \
              buffer[offset++] = ID;\n  \
-             buffer[offset++] = {lenght};\n\
+             offset++;    // msg len, known once the payload is written\n\
              {serialisations}\n  \
-             int16_t checksum = compute_cheksum(buffer+2, {lenght});\n  \
+             buffer[3] = offset - 2;\n  \
+             uint16_t checksum = compute_cheksum(buffer+2, offset - 2);\n  \
              buffer[offset++] = checksum & 0XFF;\n  \
-             buffer[offset++] = (checksum>>8) & 0XFF;\n\
+             buffer[offset++] = (checksum>>8) & 0XFF;\n  \
+             return offset;\n\
              }}",
             name = msg.name,
-            serialisations = serialisations,
-            lenght = msg.get_payload_size() + 2
+            serialisations = serialisations
         );
 
         code
@@ -100,91 +143,576 @@ impl CPPGenerator {
 
     fn get_type(ty: &Type) -> &str {
         match ty {
+            Type::Bool => "bool",
             Type::I8(_b) => "int8_t",
             Type::I16(_b) => "int16_t",
             Type::I32(_b) => "int32_t",
+            Type::I64(_b) => "int64_t",
             Type::U8(_b) => "uint8_t",
             Type::U16(_b) => "uint16_t",
             Type::U32(_b) => "uint32_t",
+            Type::U64(_b) => "uint64_t",
             Type::F32(_b) => "float",
-            Type::CHARS(_size) => "char*",
+            Type::F64(_b) => "double",
+            Type::CHARS(_size) | Type::VarChars(_size) => "char*",
+            // Arrays are accessed element by element.
+            Type::Array(elt, _len) | Type::VarArray(elt, _len) => CPPGenerator::get_type(elt),
+            Type::Enum(e) => &e.name,
+            Type::Struct(st) => &st.name,
+            Type::Bitfield(bf) => &bf.name,
+            Type::Scaled(t, _scale) => CPPGenerator::get_type(t),
         }
     }
 
     fn declare_variable(name: &str, ty: &Type) -> String {
         match ty {
-            Type::I8(_b) => format!("int8_t _{};", name),
-            Type::I16(_b) => format!("int16_t _{};", name),
-            Type::I32(_b) => format!("int32_t _{};", name),
-            Type::U8(_b) => format!("uint8_t _{};", name),
-            Type::U16(_b) => format!("uint16_t _{};", name),
-            Type::U32(_b) => format!("uint32_t _{};", name),
-            Type::F32(_b) => format!("float _{};", name),
             Type::CHARS(size) => format!("char _{}[{}];", name, size),
+            Type::Array(elt, len) => format!("{} _{}[{}];", CPPGenerator::get_type(elt), name, len),
+            Type::VarChars(max_len) => format!(
+                "uint8_t _{name}_len;\n  char _{name}[{max_len}];",
+                name = name,
+                max_len = max_len
+            ),
+            Type::VarArray(elt, max_len) => format!(
+                "uint8_t _{name}_len;\n  {t} _{name}[{max_len}];",
+                t = CPPGenerator::get_type(elt),
+                name = name,
+                max_len = max_len
+            ),
+            _ => format!("{} _{};", CPPGenerator::get_type(ty), name),
         }
     }
 
-    fn make_get_set(name: &str, ty: &Type) -> String {
-        let setter = match ty {
-            Type::CHARS(size) => format!(
-                "  void set_{name}({t} {name}) {{\n    strncpy(_{name}, {name}, {size});\n  }}",
-                name = name,
-                t = CPPGenerator::get_type(ty),
-                size = size
-            ),
+    /// Value stored by the setter of `name`, clamped to its bounds.
+    fn set_value(name: &str, ty: &Type) -> String {
+        match ty {
             Type::I8(b)
             | Type::I16(b)
             | Type::I32(b)
             | Type::U8(b)
             | Type::U16(b)
-            | Type::U32(b) => format!(
-                "  void set_{name}({t} {name}){{ _{name} = clamp({min}, {name}, {max}); }}",
+            | Type::U32(b) => {
+                format!("clamp({}, {}, {})", b.min, name, b.max)
+            }
+            Type::I64(b) | Type::U64(b) => format!(
+                "clamp({}, {}, {})",
+                CGenerator::int64_literal(b.min, ty),
+                name,
+                CGenerator::int64_literal(b.max, ty)
+            ),
+            Type::F32(b) | Type::F64(b) => format!("clamp({:?}, {}, {:?})", b.min, name, b.max),
+            Type::Scaled(t, scale) => format!(
+                "({})DuckMsg::quantize({})",
+                CPPGenerator::get_type(t),
+                CGenerator::quantize_args(name, t, scale)
+            ),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Type taken and returned by the accessors of `ty`, doubles for scaled integers.
+    fn value_type(ty: &Type) -> &str {
+        match ty {
+            Type::Scaled(..) => "double",
+            _ => CPPGenerator::get_type(ty),
+        }
+    }
+
+    /// Value returned by the getter of `lvalue`.
+    fn get_value(lvalue: &str, ty: &Type) -> String {
+        match ty {
+            Type::Scaled(_t, scale) => CGenerator::unscale(lvalue, scale),
+            _ => lvalue.to_string(),
+        }
+    }
+
+    /// Statement storing `name` into `lvalue`, clamped to its bounds.
+    fn store(lvalue: &str, name: &str, ty: &Type) -> String {
+        match ty {
+            Type::Struct(st) => format!(
+                "{lvalue} = {name}; {sname}_clamp({lvalue});",
+                lvalue = lvalue,
+                name = name,
+                sname = st.name.to_snake_case()
+            ),
+            _ => format!("{} = {};", lvalue, CPPGenerator::set_value(name, ty)),
+        }
+    }
+
+    /// Statements clamping `lvalue` to its bounds, `None` if any value of `ty` is in range.
+    fn clamp_value(lvalue: &str, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Struct(st) => Some(format!("  {}_clamp({});", st.name.to_snake_case(), lvalue)),
+            // The setters store quantized values, only the integer may be out of bounds.
+            Type::Scaled(t, _scale) => CPPGenerator::clamp_value(lvalue, t),
+            Type::Array(elt, len) => {
+                let (i, element) = CGenerator::element(lvalue);
+                CPPGenerator::clamp_value(&element, elt).map(|clamp| {
+                    format!(
+                        "  for(size_t {i}=0; {i}<{}; {i}++) {{\n  {}\n  }}",
+                        len,
+                        clamp.replace('\n', "\n  "),
+                        i = i
+                    )
+                })
+            }
+            _ => {
+                let value = CPPGenerator::set_value(lvalue, ty);
+                if value == lvalue {
+                    None
+                } else {
+                    Some(format!("  {} = {};", lvalue, value))
+                }
+            }
+        }
+    }
+
+    /// Declaration of the plain struct of a [types] entry, and of its clamping function.
+    fn declare_struct(st: &StructSpec) -> String {
+        let vars = st
+            .fields
+            .iter()
+            .map(|field| {
+                let init = match (&field.t, &field.default) {
+                    (Type::Array(elt, _len), Some(DefaultValue::List(values))) => values
+                        .iter()
+                        .map(|v| CPPGenerator::literal(v, elt))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    (_, Some(value)) => CPPGenerator::literal(value, &field.t),
+                    (Type::Enum(e), None) => CPPGenerator::first_variant(e),
+                    (Type::Array(elt, len), None) => match &**elt {
+                        Type::Enum(e) => vec![CPPGenerator::first_variant(e); *len].join(", "),
+                        _ => String::new(),
+                    },
+                    _ => String::new(),
+                };
+                let declaration = match &field.t {
+                    Type::CHARS(size) => format!("char {}[{}]", field.name, size),
+                    Type::Array(elt, len) => {
+                        format!("{} {}[{}]", CPPGenerator::get_type(elt), field.name, len)
+                    }
+                    _ => format!("{} {}", CPPGenerator::get_type(&field.t), field.name),
+                };
+                format!(
+                    "{}  {}{{{}}};",
+                    CGenerator::field_doc(field),
+                    declaration,
+                    init
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let getsets = st
+            .fields
+            .iter()
+            .filter_map(|field| match &field.t {
+                Type::Scaled(..) => Some(format!(
+                    "  double get_{name}() const {{ return {get}; }}\n  \
+                     void set_{name}(double value) {{ {name} = {value}; }}",
+                    name = field.name,
+                    get = CPPGenerator::get_value(&field.name, &field.t),
+                    value = CPPGenerator::set_value("value", &field.t)
+                )),
+                Type::Array(elt, len) if matches!(**elt, Type::Scaled(..)) => Some(format!(
+                    "  double get_{name}(size_t i) const {{ return i < {len} ? {get} : 0.0; }}\n  \
+                     void set_{name}(size_t i, double value) {{ if(i < {len}) {{ {name}[i] = {value}; }} }}",
+                    name = field.name,
+                    len = len,
+                    get = CPPGenerator::get_value(&format!("{}[i]", field.name), elt),
+                    value = CPPGenerator::set_value("value", elt)
+                )),
+                _ => None,
+            })
+            .map(|getset| format!("\n\n{}", getset))
+            .collect::<String>();
+
+        format!(
+            "struct {name} {{\n\
+             {vars}{getsets}\n\
+             }};\n\n\
+             // Clamps the fields of v to their bounds.\n\
+             void {sname}_clamp({name} &v);",
+            name = st.name,
+            sname = st.name.to_snake_case(),
+            vars = vars,
+            getsets = getsets
+        )
+    }
+
+    fn define_clamp(st: &StructSpec) -> String {
+        let clamps = st
+            .fields
+            .iter()
+            .filter_map(|field| CPPGenerator::clamp_value(&format!("v.{}", field.name), &field.t))
+            .map(|clamp| clamp + "\n")
+            .collect::<String>();
+
+        format!(
+            "void {sname}_clamp({name} &v) {{\n\
+             {clamps}  \
+             (void)v;\n\
+             }}",
+            name = st.name,
+            sname = st.name.to_snake_case(),
+            clamps = clamps
+        )
+    }
+
+    /// Class wrapping the bits of a bitfield, with a getter and a setter for each bit range.
+    fn declare_bitfield(bf: &BitfieldSpec) -> String {
+        let t = CPPGenerator::get_type(&bf.repr);
+        let nb_bits = 8 * bf.repr.get_size() as u32;
+        let getsets = bf
+            .ranges
+            .iter()
+            .map(|range| {
+                let value_t = if range.width == 1 { "bool" } else { t };
+                format!(
+                    "  {value_t} get_{name}() const {{ return {get}; }}\n  \
+                     void set_{name}({value_t} {name}) {{\n  \
+                     {set}\n  \
+                     }}",
+                    value_t = value_t,
+                    name = range.name,
+                    get = CGenerator::bit_get("_bits", range, t),
+                    set = CGenerator::bit_set("_bits", range, &range.name, t, nb_bits)
+                        .replace('\n', "\n  ")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        format!(
+            "// Bits packed into a {t}, wider ranges are clamped by their setter.\n\
+             class {name} {{\n\
+             public:\n  \
+             {name}({t} bits = 0): _bits(bits) {{}}\n  \
+             {t} get_bits() const {{ return _bits; }}\n\n\
+             {getsets}\n\n\
+             private:\n  \
+             {t} _bits;\n\
+             }};",
+            name = bf.name,
+            t = t,
+            getsets = getsets
+        )
+    }
+
+    fn make_get_set(name: &str, ty: &Type) -> String {
+        let t = CPPGenerator::get_type(ty);
+        match ty {
+            Type::CHARS(size) => format!(
+                "  const {t} get_{name}() const {{ return _{name}; }}\n  \
+                 void set_{name}(const {t} {name}) {{\n    strncpy(_{name}, {name}, {size});\n  }}",
                 name = name,
-                t = CPPGenerator::get_type(ty),
-                min = b.min,
-                max = b.max
+                t = t,
+                size = size
             ),
-            Type::F32(b) => format!(
-                "  void set_{name}({t} {name}){{ _{name} = clamp({min:.2}, {name}, {max:.2}); }}",
+            Type::Array(elt, len) => format!(
+                "  static const size_t {upper}_LEN = {len};\n  \
+                 {value_t} get_{name}(size_t i) const {{ return i < {upper}_LEN ? {get} : {zero}; }}\n\
+                 {pointer}  \
+                 void set_{name}(size_t i, {value_t} {name}){{ if(i < {upper}_LEN) {{ {store} }} }}",
+                upper = name.to_screaming_snake_case(),
+                len = len,
                 name = name,
-                t = CPPGenerator::get_type(ty),
-                min = b.min,
-                max = b.max
+                value_t = CPPGenerator::value_type(elt),
+                get = CPPGenerator::get_value(&format!("_{}[i]", name), elt),
+                zero = CPPGenerator::zero(elt),
+                pointer = CPPGenerator::array_pointer(name, elt),
+                store = CPPGenerator::store(&format!("_{}[i]", name), name, elt)
             ),
-        };
+            Type::VarChars(max_len) => format!(
+                "  static const size_t {upper}_MAX_LEN = {max_len};\n  \
+                 uint8_t get_{name}_len() const {{ return _{name}_len; }}\n  \
+                 const {t} get_{name}() const {{ return _{name}; }}\n  \
+                 void set_{name}(const {t} {name}, uint8_t len) {{\n    \
+                 _{name}_len = len < {upper}_MAX_LEN ? len : {upper}_MAX_LEN;\n    \
+                 memcpy(_{name}, {name}, _{name}_len);\n  \
+                 }}",
+                upper = name.to_screaming_snake_case(),
+                max_len = max_len,
+                name = name,
+                t = t
+            ),
+            Type::VarArray(elt, max_len) => format!(
+                "  static const size_t {upper}_MAX_LEN = {max_len};\n  \
+                 uint8_t get_{name}_len() const {{ return _{name}_len; }}\n  \
+                 void set_{name}_len(uint8_t len) {{ _{name}_len = len < {upper}_MAX_LEN ? len : {upper}_MAX_LEN; }}\n  \
+                 {value_t} get_{name}(size_t i) const {{ return i < _{name}_len ? {get} : {zero}; }}\n\
+                 {pointer}  \
+                 void set_{name}(size_t i, {value_t} {name}){{ if(i < _{name}_len) {{ {store} }} }}",
+                upper = name.to_screaming_snake_case(),
+                max_len = max_len,
+                name = name,
+                value_t = CPPGenerator::value_type(elt),
+                get = CPPGenerator::get_value(&format!("_{}[i]", name), elt),
+                zero = CPPGenerator::zero(elt),
+                pointer = CPPGenerator::array_pointer(name, elt),
+                store = CPPGenerator::store(&format!("_{}[i]", name), name, elt)
+            ),
+            Type::Struct(_) => format!(
+                "  const {t}& get_{name}() const {{ return _{name}; }}\n  \
+                 void set_{name}(const {t}& {name}){{ {store} }}",
+                name = name,
+                t = t,
+                store = CPPGenerator::store(&format!("_{}", name), name, ty)
+            ),
+            _ => format!(
+                "  {value_t} get_{name}() const {{ return {get}; }}\n  \
+                 void set_{name}({value_t} {name}){{ _{name} = {value}; }}",
+                name = name,
+                value_t = CPPGenerator::value_type(ty),
+                get = CPPGenerator::get_value(&format!("_{}", name), ty),
+                value = CPPGenerator::set_value(name, ty)
+            ),
+        }
+    }
 
-        let getter = format!(
-            "  {t} get_{name}(){{ return _{name}; }}",
-            t = CPPGenerator::get_type(ty),
-            name = name
-        );
+    /// Getter of the whole array `name`, not given for scaled integers whose elements
+    /// only make sense through the indexed getter.
+    fn array_pointer(name: &str, elt: &Type) -> String {
+        match elt {
+            Type::Scaled(..) => String::new(),
+            _ => format!(
+                "  const {t}* get_{name}() const {{ return _{name}; }}\n",
+                t = CPPGenerator::get_type(elt),
+                name = name
+            ),
+        }
+    }
 
-        format!("{}\n{}", getter, setter)
+    fn init_variable(name: &str, ty: &Type, default: Option<&DefaultValue>) -> String {
+        match (ty, default) {
+            (Type::CHARS(_size), Some(DefaultValue::Chars(v))) => format!(
+                "  memset(_{name}, 0, sizeof(_{name}));\n  memcpy(_{name}, {literal}, {len});",
+                name = name,
+                literal = CGenerator::string_literal(v),
+                len = v.len()
+            ),
+            (Type::VarChars(_), Some(DefaultValue::Chars(v))) => format!(
+                "  _{name}_len = {len};\n  \
+                 memset(_{name}, 0, sizeof(_{name}));\n  \
+                 memcpy(_{name}, {literal}, {len});",
+                name = name,
+                literal = CGenerator::string_literal(v),
+                len = v.len()
+            ),
+            (Type::Array(elt, len), Some(DefaultValue::List(values))) => {
+                CPPGenerator::init_elements(name, elt, *len, values)
+            }
+            (Type::VarArray(elt, max_len), Some(DefaultValue::List(values))) => format!(
+                "  _{}_len = {};\n{}",
+                name,
+                values.len(),
+                CPPGenerator::init_elements(name, elt, *max_len, values)
+            ),
+            (_, Some(value)) => format!("  _{} = {};", name, CPPGenerator::literal(value, ty)),
+            (Type::Bool, None) => format!("  _{} = false;", name),
+            (Type::CHARS(_size), None) => format!("  _{}[0] = \'\\0\';", name),
+            (Type::Enum(e), None) => format!("  _{} = {};", name, CPPGenerator::first_variant(e)),
+            (Type::Struct(st), None) => format!("  _{} = {}();", name, st.name),
+            (Type::Array(elt, len), None) => CPPGenerator::init_elements(name, elt, *len, &[]),
+            (Type::VarArray(elt, max_len), None) => format!(
+                "  _{}_len = 0;\n{}",
+                name,
+                CPPGenerator::init_elements(name, elt, *max_len, &[])
+            ),
+            (Type::VarChars(_), None) => format!(
+                "  _{name}_len = 0;\n  memset(_{name}, 0, sizeof(_{name}));",
+                name = name
+            ),
+            (_, None) => format!("  _{} = 0;", name),
+        }
     }
 
-    fn init_variable(name: &str, ty: &Type) -> String {
+    /// Sets the `len` elements of `_name` to `values`, the remaining ones to their zero value.
+    fn init_elements(name: &str, elt: &Type, len: usize, values: &[DefaultValue]) -> String {
+        let clear = match elt {
+            Type::Enum(_) | Type::Struct(_) | Type::Bitfield(_) => format!(
+                "  for(size_t i=0; i<{len}; i++) {{\n    _{name}[i] = {first};\n  }}",
+                len = len,
+                name = name,
+                first = CPPGenerator::zero_value(elt)
+            ),
+            _ => format!("  memset(_{name}, 0, sizeof(_{name}));", name = name),
+        };
+        if len > 1 && values.len() == len && values.iter().all(|v| *v == values[0]) {
+            return format!(
+                "  for(size_t i=0; i<{len}; i++) {{\n    _{name}[i] = {value};\n  }}",
+                len = len,
+                name = name,
+                value = CPPGenerator::literal(&values[0], elt)
+            );
+        }
+        let sets = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("  _{}[{}] = {};", name, i, CPPGenerator::literal(v, elt)));
+        if values.len() < len {
+            std::iter::once(clear)
+                .chain(sets)
+                .collect::<Vec<String>>()
+                .join("\n")
+        } else {
+            sets.collect::<Vec<String>>().join("\n")
+        }
+    }
+
+    /// Literal of the default `value` of a scalar or enum of type `ty`.
+    fn literal(value: &DefaultValue, ty: &Type) -> String {
+        match (value, ty) {
+            (DefaultValue::Int(v), Type::I64(_) | Type::U64(_)) => {
+                CGenerator::int64_literal(*v, ty)
+            }
+            (DefaultValue::Int(v), Type::Bitfield(bf)) => format!("{}({})", bf.name, v),
+            (DefaultValue::Int(v), _) => v.to_string(),
+            (DefaultValue::Bool(v), _) => v.to_string(),
+            (DefaultValue::Float(v), _) => format!("{:?}", v),
+            (DefaultValue::Chars(v), _) => CGenerator::string_literal(v),
+            (DefaultValue::Variant(v), Type::Enum(e)) => {
+                format!("{}::{}", e.name, v.to_pascal_case())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Value of a new enum, struct or bitfield.
+    fn zero_value(ty: &Type) -> String {
         match ty {
-            Type::I8(_b) => format!("  _{} = 0;", name),
-            Type::I16(_b) => format!("  _{} = 0;", name),
-            Type::I32(_b) => format!("  _{} = 0;", name),
-            Type::U8(_b) => format!("  _{} = 0;", name),
-            Type::U16(_b) => format!("  _{} = 0;", name),
-            Type::U32(_b) => format!("  _{} = 0;", name),
-            Type::F32(_b) => format!("  _{} = 0;", name),
-            Type::CHARS(_size) => format!("  _{}[0] = \'\\0\';", name),
+            Type::Enum(e) => CPPGenerator::first_variant(e),
+            Type::Struct(st) => format!("{}()", st.name),
+            Type::Bitfield(bf) => format!("{}()", bf.name),
+            _ => "0".to_string(),
         }
     }
 
+    /// Value returned by the indexed getters of arrays of `elt` out of their range:
+    /// zero, the first value of an enum or a default constructed struct or bitfield.
+    fn zero(elt: &Type) -> String {
+        match elt {
+            Type::Enum(e) => CPPGenerator::first_variant(e),
+            _ => format!("{}()", CPPGenerator::value_type(elt)),
+        }
+    }
+
+    fn first_variant(e: &EnumSpec) -> String {
+        format!("{}::{}", e.name, e.variants[0].0.to_pascal_case())
+    }
+
+    /// Enum classes, along with the declaration and the definition of their `<enum>_is_valid` function.
+    fn enums(enums: &[Rc<EnumSpec>]) -> (String, String) {
+        let declarations = enums
+            .iter()
+            .map(|e| {
+                let values = e
+                    .variants
+                    .iter()
+                    .map(|(name, value)| format!("  {} = {},", name.to_pascal_case(), value))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "enum class {name} : {repr} {{\n\
+                     {values}\n\
+                     }};\n\n\
+                     bool {sname}_is_valid({name} v);",
+                    name = e.name,
+                    sname = e.name.to_snake_case(),
+                    repr = CPPGenerator::get_type(&e.repr),
+                    values = values
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let definitions = enums
+            .iter()
+            .map(|e| {
+                let cases = e
+                    .variants
+                    .iter()
+                    .map(|(name, _value)| format!("  case {}::{}:", e.name, name.to_pascal_case()))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "bool {sname}_is_valid({name} v) {{\n  \
+                     switch(v) {{\n\
+                     {cases}\n    \
+                     return true;\n  \
+                     default:\n    \
+                     return false;\n  \
+                     }}\n\
+                     }}",
+                    name = e.name,
+                    sname = e.name.to_snake_case(),
+                    cases = cases
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        (declarations, definitions)
+    }
+
+    /// `constexpr` of each constant, followed by a blank line if there are any.
+    fn declare_constants(constants: &[ConstSpec]) -> String {
+        let declarations = constants
+            .iter()
+            .map(|c| {
+                let t = match c.t {
+                    Type::CHARS(_) => "const char*",
+                    _ => CPPGenerator::get_type(&c.t),
+                };
+                format!(
+                    "constexpr {} {} = {};",
+                    t,
+                    c.name,
+                    CPPGenerator::literal(&c.value, &c.t)
+                )
+            })
+            .collect::<Vec<String>>();
+        if declarations.is_empty() {
+            String::new()
+        } else {
+            declarations.join("\n") + "\n\n"
+        }
+    }
+
+    fn is_valid(msg: &MsgSpec) -> String {
+        let checks = msg
+            .fields
+            .iter()
+            .filter_map(|field| CGenerator::check_value(&format!("_{}", field.name), &field.t))
+            .map(|check| check + "\n")
+            .collect::<String>();
+
+        format!(
+            "bool {name}::is_valid() const {{\n\
+             {checks}  \
+             return true;\n\
+             }}",
+            name = msg.name,
+            checks = checks
+        )
+    }
+
     fn constructor(msg: &MsgSpec) -> String {
         let vars = msg
             .fields
             .iter()
-            .map(|field| CPPGenerator::init_variable(field.name.as_ref(), &field.t))
+            .map(|field| {
+                CPPGenerator::init_variable(field.name.as_ref(), &field.t, field.default.as_ref())
+            })
             .collect::<Vec<String>>()
             .join("\n");
 
         let code = format!(
-            "{name}::{name}() {{\n{vars}\n}}",
+            "{name}::{name}() {{\n  \
+             reset();\n\
+             }}\n\n\
+             void {name}::reset() {{\n{vars}\n}}",
             name = msg.name,
             vars = vars
         );
@@ -200,10 +728,26 @@ impl CPPGenerator {
             .collect::<Vec<String>>()
             .join("\n");
 
+        let length_ok = if msg.is_variable() {
+            format!(
+                "bool {}::length_ok(const uint8_t *buffer, uint8_t len) {{\n{}\n}}",
+                msg.name,
+                CGenerator::length_ok(msg)
+            )
+        } else {
+            format!(
+                "bool {}::length_ok(const uint8_t *, uint8_t len) {{\n  return len == {};\n}}",
+                msg.name,
+                msg.get_payload_size()
+            )
+        };
+
         let code = format!(
-            "{name}::{name}(uint8_t *buffer) {{\n  \
+            "{length_ok}\n\n\
+             {name}::{name}(uint8_t *buffer) {{\n  \
              int offset = 0;\n  \
              {deser}\n}}",
+            length_ok = length_ok,
             name = msg.name,
             deser = deserialisations
         );
@@ -211,61 +755,243 @@ impl CPPGenerator {
         code
     }
 
-    fn make_msg(messages: &Vec<MsgSpec>) -> String {
-        let ifs = messages
+    fn msg_storage(messages: &[&MsgSpec]) -> String {
+        let members = messages
+            .iter()
+            .map(|msg| format!("  {} {};", msg.name, msg.name.to_snake_case()))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let variants = messages
+            .iter()
+            .map(|msg| msg.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ");
+
+        format!(
+            "// Storage big enough for any message, to decode messages into with make_msg.\n\
+             union MsgStorage {{\n  \
+             MsgStorage() {{}}\n\
+             {members}\n\
+             }};\n\n\
+             // Decode the len bytes of payload of message id into storage.\n\
+             // Returns a pointer to the message, that stays valid as long as storage is not reused,\n\
+             // or NULL if the id is unknown, len doesn't match the payload (see length_ok())\n\
+             // or a decoded value is out of range (see is_valid()).\n\
+             // Use get_id() to find out the actual type.\n\
+             DuckMsg* make_msg(uint8_t id, uint8_t *buffer, uint8_t len, MsgStorage *storage);\n\n\
+             #if __cplusplus >= 201703L\n\
+             #include <variant>\n\n\
+             using MsgVariant = std::variant<std::monostate, {variants}>;\n\n\
+             // Decode the len bytes of payload of message id into msg.\n\
+             // Returns false if the id is unknown, len doesn't match the payload\n\
+             // or a decoded value is out of range.\n\
+             bool make_msg(uint8_t id, uint8_t *buffer, uint8_t len, MsgVariant &msg);\n\
+             #endif",
+            members = members,
+            variants = variants
+        )
+    }
+
+    fn make_msg(messages: &[&MsgSpec]) -> String {
+        let cases = messages
             .iter()
             .map(|msg| {
                 format!(
-                    "\tif id=={id} {{\n\t\t\
-                     return {name}();\n\t\
-                     }}",
-                    id = msg.id,
+                    "  case {name}::ID:\n    \
+                     if({name}::length_ok(buffer, len)) {{\n      \
+                     msg = new (&storage->{sname}) {name}(buffer);\n    \
+                     }}\n    \
+                     break;",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let variant_cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  case {name}::ID:\n    \
+                     return {name}::length_ok(buffer, len) && msg.emplace<{name}>(buffer).is_valid();",
+                    name = msg.name
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "DuckMsg* make_msg(uint8_t id, uint8_t *buffer, uint8_t len, MsgStorage *storage) {{\n  \
+             DuckMsg *msg = NULL;\n  \
+             switch(id) {{\n\
+             {cases}\n  \
+             }}\n  \
+             return msg && msg->is_valid() ? msg : NULL;\n\
+             }}\n\n\
+             #if __cplusplus >= 201703L\n\
+             bool make_msg(uint8_t id, uint8_t *buffer, uint8_t len, MsgVariant &msg) {{\n  \
+             switch(id) {{\n\
+             {variant_cases}\n  \
+             default:\n    \
+             return false;\n  \
+             }}\n\
+             }}\n\
+             #endif",
+            cases = cases,
+            variant_cases = variant_cases
+        )
+    }
+
+    /// `msg_len` or `msg_min_len` function, `prefix` being `` or `MIN_`.
+    fn msg_len(messages: &[&MsgSpec], prefix: &str) -> String {
+        let cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  case {name}::ID: return {name}::{prefix}SIZE - 4;",
+                    name = msg.name,
+                    prefix = prefix
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "uint8_t msg_{}len(uint8_t id) {{\n  \
+             switch(id) {{\n\
+             {}\n  \
+             default: return 0;\n  \
+             }}\n\
+             }}",
+            prefix.to_lowercase(),
+            cases
+        )
+    }
+
+    fn handler(messages: &[&MsgSpec]) -> (String, String) {
+        let methods = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  virtual void on(const {} &msg) {{ (void)msg; }}",
+                    msg.name
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let declaration = format!(
+            "// Override the on() methods of the messages you want to handle.\n\
+             class DuckHandler {{\n\
+             public:\n  \
+             virtual ~DuckHandler() {{}}\n\n\
+             {}\n\n  \
+             // Call the on() method matching the type of msg.\n  \
+             void dispatch(DuckMsg *msg);\n\
+             }};",
+            methods
+        );
+
+        let cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "  case {name}::ID:\n    \
+                     on(*static_cast<{name}*>(msg));\n    \
+                     break;",
                     name = msg.name
                 )
             })
             .collect::<Vec<String>>()
             .join("\n");
 
-        format!("DuckMsg make_msg(uint8_t id) {{\n{}\n}}", ifs)
+        let definition = format!(
+            "void DuckHandler::dispatch(DuckMsg *msg) {{\n  \
+             switch(msg->get_id()) {{\n\
+             {}\n  \
+             }}\n\
+             }}",
+            cases
+        );
+
+        (declaration, definition)
     }
 }
 
 impl Generator for CPPGenerator {
-    fn generate_messages(messages: &Vec<MsgSpec>, UID: u32) -> Vec<(String, String)> {
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        let messages = &schema.messages;
+        let received = schema.received();
         let declarations = messages
             .iter()
-            .map(|msg| CPPGenerator::declare_class(msg))
+            .map(|msg| CPPGenerator::declare_class(msg, schema))
             .collect::<Vec<String>>()
             .join("\n\n\n");
 
+        let (handler_h, handler_cpp) = CPPGenerator::handler(&received);
+
+        let (mut enums_h, mut enums_cpp) = CPPGenerator::enums(&schema.enums);
+        if !schema.enums.is_empty() {
+            enums_h += "\n\n";
+            enums_cpp += "\n\n";
+        }
+        for bf in &schema.bitfields {
+            enums_h += &CPPGenerator::declare_bitfield(bf);
+            enums_h += "\n\n";
+        }
+        for st in &schema.structs {
+            enums_h += &CPPGenerator::declare_struct(st);
+            enums_h += "\n\n";
+            enums_cpp += &CPPGenerator::define_clamp(st);
+            enums_cpp += "\n\n";
+        }
+
         let header = format!(
             "{}\n\n\
-             DuckMsg make_msg(uint8_t id);\n\n\
-             {}\n\n{}",
+             #define UID {}\n\n\
+             {}{}{}\n\n{}\n\n{}\n\n{}\n\n{}",
             CPPGenerator::HEADER_H,
+            uid,
+            CPPGenerator::declare_constants(&schema.constants),
+            enums_h,
             declarations,
+            CPPGenerator::msg_storage(&received),
+            CPPGenerator::PARSER_H,
+            handler_h,
             CPPGenerator::FOOTER_H
         );
 
         let serialisations = messages
             .iter()
             .map(|msg| {
-                format!(
-                    "{}\n\n{}\n\n{}",
-                    CPPGenerator::constructor(msg),
-                    CPPGenerator::constructor_from_bytes(msg),
-                    CPPGenerator::to_bytes(msg),
-                )
+                let mut code = CPPGenerator::constructor(msg);
+                if schema.decodes(msg) {
+                    code += &format!("\n\n{}", CPPGenerator::constructor_from_bytes(msg));
+                }
+                if schema.encodes(msg) {
+                    code += &format!("\n\n{}", CPPGenerator::to_bytes(msg));
+                }
+                if schema.decodes(msg) {
+                    code += &format!("\n\n{}", CPPGenerator::is_valid(msg));
+                }
+                code
             })
             .collect::<Vec<String>>()
             .join("\n\n\n");
 
-        let make_msg = CPPGenerator::make_msg(messages);
+        let make_msg = CPPGenerator::make_msg(&received);
 
         let source = format!(
-            "{}\n\n{}\n\n{}\n\n{}",
+            "{}\n\n{}\n\n{}{}\n\n{}\n\n{}\n\n{}\n\n{}\n\n{}",
             CPPGenerator::HEADER_CPP,
+            CGenerator::byte_order(schema),
+            enums_cpp,
             make_msg,
+            CPPGenerator::msg_len(&received, ""),
+            CPPGenerator::msg_len(&received, "MIN_"),
+            handler_cpp,
             serialisations,
             CPPGenerator::FOOTER_CPP
         );
@@ -0,0 +1,430 @@
+use crate::generator::Generator;
+use crate::message::{
+    BitfieldSpec, ConstSpec, DefaultValue, Direction, Endianness, EnumSpec, Field, MsgSpec, Scale,
+    Schema, Scope, StructSpec, Type,
+};
+
+/// Markdown description of the protocol, for the people who don't read the schema.
+pub struct DocGenerator;
+
+impl DocGenerator {
+    const FRAME: &'static str = "## Frame\n\n\
+                                 | Offset | Size | Content |\n\
+                                 |---|---|---|\n\
+                                 | 0 | 1 | 0xFF |\n\
+                                 | 1 | 1 | 0xFF |\n\
+                                 | 2 | 1 | Message id |\n\
+                                 | 3 | 1 | Length: size of the payload + 2 |\n\
+                                 | 4 | n | Payload, the fields of the message without padding |\n\
+                                 | 4 + n | 2 | Checksum |\n\n\
+                                 The checksum is a Fletcher-16 over the id, the length and the payload, \
+                                 both sums being modulo 256: `a = a + byte` then `b = b + a` for each byte, \
+                                 starting from 0. `b` is sent first, then `a`.\n\n\
+                                 Variable length fields are sent as their length on one byte, followed by \
+                                 that many elements.";
+
+    /// Name of the type as written in the schema, e.g. `f32[3]` or `chars[max 64]`.
+    fn type_name(ty: &Type) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::I8(_b) => "i8".to_string(),
+            Type::I16(_b) => "i16".to_string(),
+            Type::I32(_b) => "i32".to_string(),
+            Type::I64(_b) => "i64".to_string(),
+            Type::U8(_b) => "u8".to_string(),
+            Type::U16(_b) => "u16".to_string(),
+            Type::U32(_b) => "u32".to_string(),
+            Type::U64(_b) => "u64".to_string(),
+            Type::F32(_b) => "f32".to_string(),
+            Type::F64(_b) => "f64".to_string(),
+            Type::CHARS(size) => format!("chars[{}]", size),
+            Type::Array(elt, len) => DocGenerator::array_name(elt, &len.to_string()),
+            Type::VarChars(max_len) => format!("chars[max {}]", max_len),
+            Type::VarArray(elt, max_len) => {
+                DocGenerator::array_name(elt, &format!("max {}", max_len))
+            }
+            Type::Enum(e) => format!(
+                "[{name}](#{anchor})",
+                name = e.name,
+                anchor = e.name.to_lowercase()
+            ),
+            Type::Struct(st) => format!(
+                "[{name}](#{anchor})",
+                name = st.name,
+                anchor = st.name.to_lowercase()
+            ),
+            Type::Bitfield(bf) => format!(
+                "[{name}](#{anchor})",
+                name = bf.name,
+                anchor = bf.name.to_lowercase()
+            ),
+            Type::Scaled(t, scale) => {
+                DocGenerator::type_name(t) + &DocGenerator::scale_suffix(scale)
+            }
+        }
+    }
+
+    /// Name of an array of `len` elements, the scale coming after the length.
+    fn array_name(elt: &Type, len: &str) -> String {
+        match elt {
+            Type::Scaled(t, scale) => format!(
+                "{}[{}]{}",
+                DocGenerator::type_name(t),
+                len,
+                DocGenerator::scale_suffix(scale)
+            ),
+            _ => format!("{}[{}]", DocGenerator::type_name(elt), len),
+        }
+    }
+
+    fn scale_suffix(scale: &Scale) -> String {
+        if scale.offset == 0.0 {
+            format!(", scale {:?}", scale.scale)
+        } else {
+            format!(", scale {:?}, offset {:?}", scale.scale, scale.offset)
+        }
+    }
+
+    fn bytes(n: usize) -> String {
+        if n == 1 {
+            "1 byte".to_string()
+        } else {
+            format!("{} bytes", n)
+        }
+    }
+
+    /// Bounds of the values, only given if they are narrower than those of the type.
+    /// Scaled integers give the bounds of the values they stand for.
+    fn bounds(ty: &Type) -> String {
+        let full = Type::from_string(&DocGenerator::type_name(ty), &Scope::default());
+        match (ty, full) {
+            (
+                Type::I8(b)
+                | Type::I16(b)
+                | Type::I32(b)
+                | Type::I64(b)
+                | Type::U8(b)
+                | Type::U16(b)
+                | Type::U32(b)
+                | Type::U64(b),
+                Ok(Type::I8(f) | Type::I16(f) | Type::I32(f) | Type::I64(f))
+                | Ok(Type::U8(f) | Type::U16(f) | Type::U32(f) | Type::U64(f)),
+            ) if b.min != f.min || b.max != f.max => format!("{} to {}", b.min, b.max),
+            (Type::F32(b) | Type::F64(b), Ok(Type::F32(f) | Type::F64(f)))
+                if b.min != f.min || b.max != f.max =>
+            {
+                format!("{:?} to {:?}", b.min, b.max)
+            }
+            (Type::Scaled(t, scale), _) => match &**t {
+                Type::I8(b)
+                | Type::I16(b)
+                | Type::I32(b)
+                | Type::U8(b)
+                | Type::U16(b)
+                | Type::U32(b) => format!(
+                    "{:?} to {:?}",
+                    b.min as f64 * scale.scale + scale.offset,
+                    b.max as f64 * scale.scale + scale.offset
+                ),
+                _ => String::new(),
+            },
+            (Type::Array(elt, _len) | Type::VarArray(elt, _len), _) => DocGenerator::bounds(elt),
+            _ => String::new(),
+        }
+    }
+
+    /// Text fitting in a table cell.
+    fn cell(text: &str) -> String {
+        text.replace('|', "\\|").replace('\n', "<br>")
+    }
+
+    /// Table of the fields, with their offset from the start of `fields`. Offsets following
+    /// variable length fields depend on their length, e.g. `5 + text_len`.
+    fn fields_table(fields: &[Field]) -> String {
+        let mut fixed = 0;
+        let mut lens: Vec<String> = vec![];
+        let rows = fields
+            .iter()
+            .map(|field| {
+                let offset = std::iter::once(fixed.to_string())
+                    .chain(lens.iter().cloned())
+                    .collect::<Vec<String>>()
+                    .join(" + ");
+                fixed += field.t.get_min_size();
+                match &field.t {
+                    Type::VarChars(_) => lens.push(format!("{}_len", field.name)),
+                    Type::VarArray(elt, _max_len) if elt.get_size() == 1 => {
+                        lens.push(format!("{}_len", field.name))
+                    }
+                    Type::VarArray(elt, _max_len) => {
+                        lens.push(format!("{} × {}_len", elt.get_size(), field.name))
+                    }
+                    _ => (),
+                }
+                format!(
+                    "| {} | {} | {} | {} | {} | {} |",
+                    offset,
+                    field.name,
+                    DocGenerator::type_name(&field.t),
+                    DocGenerator::bounds(&field.t),
+                    field
+                        .unit
+                        .as_deref()
+                        .map_or(String::new(), DocGenerator::cell),
+                    field
+                        .doc
+                        .as_deref()
+                        .map_or(String::new(), DocGenerator::cell)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "| Offset | Field | Type | Bounds | Unit | Description |\n\
+             |---|---|---|---|---|---|\n\
+             {}",
+            rows
+        )
+    }
+
+    /// Size of the frame and of the payload, a range for variable length messages.
+    fn sizes(msg: &MsgSpec) -> String {
+        if msg.is_variable() {
+            format!(
+                "frame of {} to {} (payload of {} to {})",
+                msg.get_min_buffer_size(),
+                DocGenerator::bytes(msg.get_buffer_size()),
+                msg.get_min_payload_size(),
+                DocGenerator::bytes(msg.get_payload_size())
+            )
+        } else {
+            format!(
+                "frame of {} (payload of {})",
+                DocGenerator::bytes(msg.get_buffer_size()),
+                DocGenerator::bytes(msg.get_payload_size())
+            )
+        }
+    }
+
+    fn direction(direction: Direction) -> &'static str {
+        match direction {
+            Direction::Up => "MCU to host",
+            Direction::Down => "host to MCU",
+            Direction::Both => "both ways",
+        }
+    }
+
+    fn describe_message(msg: &MsgSpec) -> String {
+        let doc = msg
+            .description()
+            .map_or(String::new(), |doc| format!("{}\n\n", doc));
+        format!(
+            "### {name}\n\n\
+             {doc}Id {id}, class `{class}`, sent {direction}, {sizes}.\n\n\
+             {fields}",
+            name = msg.name,
+            doc = doc,
+            id = msg.id,
+            class = msg.class,
+            direction = DocGenerator::direction(msg.direction),
+            sizes = DocGenerator::sizes(msg),
+            fields = DocGenerator::fields_table(&msg.fields)
+        )
+    }
+
+    fn describe_struct(st: &StructSpec) -> String {
+        let size: usize = st.fields.iter().map(|f| f.t.get_size()).sum();
+        format!(
+            "### {name}\n\n\
+             Sent as its fields, {size}.\n\n\
+             {fields}",
+            name = st.name,
+            size = DocGenerator::bytes(size),
+            fields = DocGenerator::fields_table(&st.fields)
+        )
+    }
+
+    fn describe_enum(e: &EnumSpec) -> String {
+        let rows = e
+            .variants
+            .iter()
+            .map(|(name, value)| format!("| {} | {} |", value, name))
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "### {name}\n\n\
+             Sent as a {repr}.\n\n\
+             | Value | Name |\n\
+             |---|---|\n\
+             {rows}",
+            name = e.name,
+            repr = DocGenerator::type_name(&e.repr),
+            rows = rows
+        )
+    }
+
+    fn describe_bitfield(bf: &BitfieldSpec) -> String {
+        let rows = bf
+            .ranges
+            .iter()
+            .map(|range| {
+                if range.width == 1 {
+                    format!("| {} | {} | bool |", range.start, range.name)
+                } else {
+                    format!(
+                        "| {}..{} | {} | 0 to {} |",
+                        range.start,
+                        range.start + range.width,
+                        range.name,
+                        range.max()
+                    )
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "### {name}\n\n\
+             Sent as a {repr}, bit 0 being the least significant one.\n\n\
+             | Bits | Name | Values |\n\
+             |---|---|---|\n\
+             {rows}",
+            name = bf.name,
+            repr = DocGenerator::type_name(&bf.repr),
+            rows = rows
+        )
+    }
+
+    fn value(value: &DefaultValue) -> String {
+        match value {
+            DefaultValue::Bool(v) => v.to_string(),
+            DefaultValue::Int(v) => v.to_string(),
+            DefaultValue::Float(v) => format!("{:?}", v),
+            DefaultValue::Chars(v) | DefaultValue::Variant(v) => format!("`{:?}`", v),
+            DefaultValue::List(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(DocGenerator::value)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn constants_table(constants: &[ConstSpec]) -> String {
+        let rows = constants
+            .iter()
+            .map(|c| {
+                let t = match c.t {
+                    Type::CHARS(_) => "chars".to_string(),
+                    _ => DocGenerator::type_name(&c.t),
+                };
+                format!("| {} | {} | {} |", c.name, t, DocGenerator::value(&c.value))
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "## Constants\n\n\
+             | Name | Type | Value |\n\
+             |---|---|---|\n\
+             {}",
+            rows
+        )
+    }
+
+    fn summary(messages: &[&MsgSpec]) -> String {
+        let rows = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "| {id} | [{name}](#{anchor}) | {class} | {direction} | {size} |",
+                    id = msg.id,
+                    name = msg.name,
+                    anchor = msg.name.to_lowercase(),
+                    class = msg.class,
+                    direction = DocGenerator::direction(msg.direction),
+                    size = msg.get_buffer_size()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "| Id | Message | Class | Direction | Max frame size |\n\
+             |---|---|---|---|---|\n\
+             {}",
+            rows
+        )
+    }
+}
+
+impl Generator for DocGenerator {
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        let mut messages = schema.messages.iter().collect::<Vec<&MsgSpec>>();
+        messages.sort_by_key(|msg| msg.id);
+
+        let byte_order = match schema.endianness {
+            Endianness::Little => "little-endian",
+            Endianness::Big => "big-endian",
+        };
+
+        let mut sections = vec![
+            format!(
+                "# Protocol\n\n\
+                 UID: `0x{uid:08X}` ({uid}). Both sides shall have the same UID, which changes \
+                 whenever the wire format of a message changes.\n\n\
+                 Multi-byte values are sent {byte_order}.",
+                uid = uid,
+                byte_order = byte_order
+            ),
+            DocGenerator::FRAME.to_string(),
+            format!(
+                "## Messages\n\n{}\n\n{}",
+                DocGenerator::summary(&messages),
+                messages
+                    .iter()
+                    .map(|msg| DocGenerator::describe_message(msg))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            ),
+        ];
+        if !schema.structs.is_empty() {
+            sections.push(format!(
+                "## Types\n\n{}",
+                schema
+                    .structs
+                    .iter()
+                    .map(|st| DocGenerator::describe_struct(st))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            ));
+        }
+        if !schema.enums.is_empty() {
+            sections.push(format!(
+                "## Enums\n\n{}",
+                schema
+                    .enums
+                    .iter()
+                    .map(|e| DocGenerator::describe_enum(e))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            ));
+        }
+        if !schema.bitfields.is_empty() {
+            sections.push(format!(
+                "## Bitfields\n\n{}",
+                schema
+                    .bitfields
+                    .iter()
+                    .map(|bf| DocGenerator::describe_bitfield(bf))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            ));
+        }
+        if !schema.constants.is_empty() {
+            sections.push(DocGenerator::constants_table(&schema.constants));
+        }
+
+        vec![("protocol.md".to_string(), sections.join("\n\n") + "\n")]
+    }
+}
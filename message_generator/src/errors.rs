@@ -7,16 +7,71 @@ pub enum ParserError {
     TypeNotFound,
     SizeNotFound,
     BoundsInvalid,
+    LenInvalid,
+    ScaleInvalid,
+    DefaultInvalid,
+    ConstantNotFound,
+    DocInvalid,
+}
+
+impl ParserError {
+    /// How to fix the error.
+    pub fn hint(&self) -> &'static str {
+        match *self {
+            ParserError::TypeInvalid => {
+                "valid types are bool, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, chars \
+                 and the enums, types and bitfields declared in [enums], [types] and [bitfields], \
+                 either as a string or as the \"type\" of a table"
+            }
+            ParserError::CharSizeInvalid => {
+                "the size of chars shall be a positive integer, at most 253"
+            }
+            ParserError::TypeNotFound => "add a \"type\" key, e.g. {type=\"i16\", min=-10, max=10}",
+            ParserError::SizeNotFound => "chars need a size, e.g. {type=\"chars\", size=12}",
+            ParserError::BoundsInvalid => {
+                "min and max shall have the same kind as the type (integer or float), the \
+                 constants they name keeping their value once converted to it, be within its \
+                 range, and min shall be lower than max"
+            }
+            ParserError::LenInvalid => {
+                "len and max_len shall be positive integers, len at most 253 and max_len at most \
+                 255, and only one of them can be given. Fixed size arrays of chars are not \
+                 supported, use size instead"
+            }
+            ParserError::ScaleInvalid => {
+                "scale and offset only apply to i8, i16, i32, u8, u16 and u32 (not to i64 and \
+                 u64, that doubles can't hold exactly), shall be numbers and scale shall not be \
+                 0, e.g. {type=\"i16\", scale=0.001}"
+            }
+            ParserError::DefaultInvalid => {
+                "default shall have the kind of the type (bool, integer, float, string or name of \
+                 an enum value) and be within its bounds. Arrays take a list of values or a value \
+                 for every element, and types take the defaults of their fields"
+            }
+            ParserError::ConstantNotFound => {
+                "bounds and lengths can name a number declared in [constants], \
+                 e.g. {type=\"f32\", max=\"MAX_SPEED\"}"
+            }
+            ParserError::DocInvalid => {
+                "doc and unit shall be strings, e.g. {type=\"f32\", unit=\"rad\"}"
+            }
+        }
+    }
 }
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            ParserError::TypeInvalid => write!(f, "ParserError: type invalid!"),
-            ParserError::CharSizeInvalid => write!(f, "ParserError: chars size invalid!"),
-            ParserError::TypeNotFound => write!(f, "ParserError: type not found!"),
-            ParserError::SizeNotFound => write!(f, "ParserError: size not found!"),
-            ParserError::BoundsInvalid => write!(f, "ParserError: bounds invalid!"),
+            ParserError::TypeInvalid => write!(f, "type invalid"),
+            ParserError::CharSizeInvalid => write!(f, "chars size invalid"),
+            ParserError::TypeNotFound => write!(f, "type not found"),
+            ParserError::SizeNotFound => write!(f, "size not found"),
+            ParserError::BoundsInvalid => write!(f, "bounds invalid"),
+            ParserError::LenInvalid => write!(f, "array length invalid"),
+            ParserError::ScaleInvalid => write!(f, "scale invalid"),
+            ParserError::DefaultInvalid => write!(f, "default invalid"),
+            ParserError::ConstantNotFound => write!(f, "constant not found"),
+            ParserError::DocInvalid => write!(f, "doc invalid"),
         }
     }
 }
@@ -27,3 +82,141 @@ impl error::Error for ParserError {
         None
     }
 }
+
+/// Location of a diagnostic in the schema. Line and column start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    /// The whole source line, to be shown under the message.
+    pub text: String,
+}
+
+/// An error in the schema, with enough context to fix it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    /// Path of the offending item in the schema, e.g. `down.SpeedCommand.vy`.
+    pub path: String,
+    pub message: String,
+    /// The offending value, as written in TOML.
+    pub value: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new<P: Into<String>, M: Into<String>>(path: P, message: M) -> Diagnostic {
+        Diagnostic {
+            file: None,
+            span: None,
+            path: path.into(),
+            message: message.into(),
+            value: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_file<F: Into<String>>(mut self, file: F) -> Diagnostic {
+        self.file = Some(file.into());
+        self
+    }
+
+    pub fn with_span(mut self, span: Option<Span>) -> Diagnostic {
+        self.span = span;
+        self
+    }
+
+    pub fn with_value<V: fmt::Display>(mut self, value: V) -> Diagnostic {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    /// Same as `with_value`, with tables and arrays kept on one line.
+    pub fn with_toml(self, value: &toml::Value) -> Diagnostic {
+        self.with_value(inline_toml(value))
+    }
+
+    pub fn with_hint<H: Into<String>>(mut self, hint: H) -> Diagnostic {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Diagnostic for a TOML syntax error, located where the toml crate found it.
+    pub fn from_toml_error(e: &toml::de::Error, source: &str) -> Diagnostic {
+        let span = e.line_col().and_then(|(line, col)| {
+            source.lines().nth(line).map(|text| Span {
+                line: line + 1,
+                column: col + 1,
+                len: 1,
+                text: text.to_string(),
+            })
+        });
+        Diagnostic::new("", format!("invalid TOML: {}", e)).with_span(span)
+    }
+}
+
+/// Render a TOML value the way it is written inline, e.g. `{ type = "i8", min = 0 }`.
+fn inline_toml(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(t) => {
+            let entries = t
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, inline_toml(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        toml::Value::Array(a) => {
+            let values = a.iter().map(inline_toml).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// Render the diagnostic with the offending line, its location underlined with carets.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            writeln!(f, "error: {}", self.message)?;
+        } else {
+            writeln!(f, "error: {}: {}", self.path, self.message)?;
+        }
+
+        let gutter = self
+            .span
+            .as_ref()
+            .map_or(1, |span| span.line.to_string().len());
+        let pad = " ".repeat(gutter);
+
+        match (&self.file, &self.span) {
+            (Some(file), Some(span)) => {
+                writeln!(f, "{}--> {}:{}:{}", pad, file, span.line, span.column)?
+            }
+            (Some(file), None) => writeln!(f, "{}--> {}", pad, file)?,
+            (None, Some(span)) => writeln!(f, "{}--> line {}:{}", pad, span.line, span.column)?,
+            (None, None) => {}
+        }
+
+        if let Some(span) = &self.span {
+            writeln!(f, "{} |", pad)?;
+            writeln!(f, "{} | {}", span.line, span.text)?;
+            writeln!(
+                f,
+                "{} | {}{}",
+                pad,
+                " ".repeat(span.column - 1),
+                "^".repeat(span.len.max(1))
+            )?;
+        }
+
+        if let Some(value) = &self.value {
+            writeln!(f, "{} = value: {}", pad, value)?;
+        }
+        if let Some(hint) = &self.hint {
+            writeln!(f, "{} = hint: {}", pad, hint)?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
-use crate::message::MsgSpec;
+use crate::message::Schema;
 
 pub trait Generator {
-    fn generate_messages(messages: &Vec<MsgSpec>, UID: u32) -> Vec<(String, String)>; //return Vec<(filename, txt)>    TODO: improve lisibility (make a struct ?)
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)>; //return Vec<(filename, txt)>    TODO: improve lisibility (make a struct ?)
 }
@@ -0,0 +1,40 @@
+//! Ducklink message generator.
+//!
+//! Parses a TOML message schema and generates the code to serialize and deserialize
+//! those messages in C, C++, Python and Rust. It can be used from a `build.rs`:
+//!
+//! ```no_run
+//! use ducklink_codegen::{Builder, Lang};
+//!
+//! Builder::new("messages.toml")
+//!     .lang(Lang::Rust)
+//!     .out_dir(std::env::var("OUT_DIR").unwrap())
+//!     .generate()
+//!     .unwrap();
+//! ```
+//!
+//! The build script only reads the lock file pinning the message ids: run the
+//! `message_generator` binary on the schema when adding messages, to update it.
+
+pub mod builder;
+pub mod c_generator;
+pub mod cpp_generator;
+pub mod doc_generator;
+pub mod errors;
+pub mod generator;
+pub mod locator;
+pub mod lock;
+pub mod message;
+pub mod parser;
+pub mod python_generator;
+pub mod rust_generator;
+pub mod uid;
+
+pub use builder::{Builder, GeneratedFile, Lang};
+pub use errors::Diagnostic;
+pub use generator::Generator;
+pub use message::{
+    BitRange, BitfieldSpec, ConstSpec, DefaultValue, Direction, Endianness, EnumSpec, Field,
+    MsgSpec, Scale, Schema, Scope, Side, StructSpec, Type,
+};
+pub use parser::{parse_files, parse_toml};
@@ -0,0 +1,135 @@
+use crate::errors::Span;
+
+/// Finds where keys are declared in a TOML source.
+///
+/// The toml crate doesn't keep the position of values, so this does a light scan of the
+/// source: table headers, then `key = ` lines, then `key = ` inside inline tables.
+///
+/// A schema split in several files has one source per file, each key being declared
+/// in only one of them.
+pub struct Locator<'a> {
+    /// Name of each source, if any, and its contents.
+    sources: Vec<(Option<&'a str>, &'a str)>,
+}
+
+/// Split a dotted key (`a."b.c".d`) in its parts, without quotes.
+fn split_key(key: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut quote = None;
+    for c in key.chars() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '.') => parts.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+/// Byte offset of `key = ` in `text`, with `key` not being the end of a longer key.
+fn find_key(text: &str, key: &str) -> Option<usize> {
+    text.match_indices(key).map(|(i, _)| i).find(|&i| {
+        let before = text[..i].chars().last();
+        let after = text[i + key.len()..].trim_start().chars().next();
+        let ident = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+        !before.is_some_and(ident) && after == Some('=')
+    })
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(source: &'a str) -> Locator<'a> {
+        Locator {
+            sources: vec![(None, source)],
+        }
+    }
+
+    /// Locator over several files, given as `(name, contents)`.
+    pub fn with_files(files: &[(&'a str, &'a str)]) -> Locator<'a> {
+        Locator {
+            sources: files
+                .iter()
+                .map(|(name, contents)| (Some(*name), *contents))
+                .collect(),
+        }
+    }
+
+    fn span(line: usize, text: &str, start: usize, len: usize) -> Span {
+        Span {
+            line: line + 1,
+            column: start + 1,
+            len,
+            text: text.to_string(),
+        }
+    }
+
+    /// Span of the key at `path`, e.g. `["down", "SpeedCommand", "vy"]`.
+    pub fn locate(&self, path: &[&str]) -> Option<Span> {
+        self.sources
+            .iter()
+            .find_map(|(_name, source)| Locator::locate_in(source, path))
+    }
+
+    /// Whether the keys are spread over several files.
+    pub fn is_split(&self) -> bool {
+        self.sources.len() > 1
+    }
+
+    /// Name of the file declaring the key at `path`, or its closest parent.
+    pub fn file(&self, path: &[&str]) -> Option<&'a str> {
+        (1..=path.len()).rev().find_map(|len| {
+            self.sources
+                .iter()
+                .find(|(_name, source)| Locator::locate_in(source, &path[..len]).is_some())
+                .and_then(|(name, _source)| *name)
+        })
+    }
+
+    fn locate_in(source: &str, path: &[&str]) -> Option<Span> {
+        let mut header: Vec<String> = vec![];
+        for (n, text) in source.lines().enumerate() {
+            let trimmed = text.trim();
+            if trimmed.starts_with('[') {
+                let end = trimmed.find(']')?;
+                header = split_key(trimmed[1..end].trim_start_matches('['));
+                if header == path {
+                    let start = text.find('[').unwrap();
+                    return Some(Locator::span(n, text, start, text.trim_end().len() - start));
+                }
+                continue;
+            }
+
+            if header.len() >= path.len() || header.iter().zip(path).any(|(h, p)| h != p) {
+                continue;
+            }
+            let rest = &path[header.len()..];
+
+            // `key = value`, where key may be dotted.
+            let eq = match trimmed.find('=') {
+                Some(eq) if !trimmed.starts_with('#') => eq,
+                _ => continue,
+            };
+            let key = split_key(&trimmed[..eq]);
+            if key.len() > rest.len() || key.iter().zip(rest).any(|(k, r)| k != r) {
+                continue;
+            }
+
+            let key_start = text.len() - text.trim_start().len();
+            if key.len() == rest.len() {
+                let key_len = trimmed[..eq].trim_end().len();
+                return Some(Locator::span(n, text, key_start, key_len));
+            }
+
+            // The remaining keys are inside an inline table on this line.
+            let mut offset = key_start + eq;
+            for sub_key in &rest[key.len()..] {
+                offset += find_key(&text[offset..], sub_key)?;
+            }
+            let last = rest.last().unwrap();
+            return Some(Locator::span(n, text, offset, last.len()));
+        }
+        None
+    }
+}
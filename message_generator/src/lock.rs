@@ -0,0 +1,104 @@
+use crate::errors::Diagnostic;
+use crate::locator::Locator;
+use crate::message::MsgSpec;
+use crate::parser::MAX_ID;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::value::Value;
+
+/// Path of the lock file of `schema`: next to it, named after it, e.g. `robot.lock` for
+/// `robot.toml`. Each schema of a directory thus numbers its messages on its own.
+pub fn lock_path(schema: &Path) -> PathBuf {
+    schema.with_extension("lock")
+}
+
+const LOCK_HEADER: &str = "# Generated by the Ducklink message generator. Commit it along with the schema.\n\
+                           # It pins message ids so that adding or removing messages never renumbers\n\
+                           # the others. Ids of removed messages are kept so they are not reused.";
+
+/// Message ids assigned by previous runs of the generator.
+#[derive(Debug, Default)]
+pub struct IdLock {
+    ids: BTreeMap<String, usize>,
+}
+
+impl IdLock {
+    pub fn from_toml(contents: &str) -> Result<IdLock, Vec<Diagnostic>> {
+        let value = contents
+            .parse::<Value>()
+            .map_err(|e| vec![Diagnostic::from_toml_error(&e, contents)])?;
+        let locator = Locator::new(contents);
+
+        let mut lock = IdLock::default();
+        let ids = match value.get("ids") {
+            Some(Value::Table(ids)) => ids,
+            Some(ids) => {
+                return Err(vec![Diagnostic::new("ids", "shall be a table")
+                    .with_span(locator.locate(&["ids"]))
+                    .with_toml(ids)])
+            }
+            None => return Ok(lock),
+        };
+
+        let mut errs = vec![];
+        for (name, id) in ids {
+            match id {
+                Value::Integer(id) if *id > 0 && *id as usize <= MAX_ID => {
+                    lock.ids.insert(name.clone(), *id as usize);
+                }
+                _ => errs.push(
+                    Diagnostic::new(format!("ids.{}", name), "invalid id")
+                        .with_span(locator.locate(&["ids", name]))
+                        .with_toml(id)
+                        .with_hint(format!(
+                            "ids shall be in 1..={}, delete the lock file to assign fresh ids",
+                            MAX_ID
+                        )),
+                ),
+            }
+        }
+
+        if errs.is_empty() {
+            Ok(lock)
+        } else {
+            Err(errs)
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut entries = self.ids.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_name, id)| **id);
+        let ids = entries
+            .iter()
+            .map(|(name, id)| format!("{} = {}", name, id))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!("{}\n\n[ids]\n{}\n", LOCK_HEADER, ids)
+    }
+
+    /// Id pinned for the message `name`, if any.
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    /// Name of the message the id `id` is pinned to, if any.
+    pub fn owner(&self, id: usize) -> Option<&str> {
+        self.ids
+            .iter()
+            .find(|(_name, i)| **i == id)
+            .map(|(name, _i)| name.as_str())
+    }
+
+    /// Pin the ids of `messages`. Entries of messages that no longer exist are kept,
+    /// unless their id has been taken by another message. Fresh ids skip every id of the
+    /// lock, so the id of a removed message is never reused unless it is given explicitly
+    /// to another message, or its entry is deleted from the lock file.
+    pub fn update(&mut self, messages: &[MsgSpec]) {
+        for msg in messages {
+            self.ids
+                .retain(|name, id| *id != msg.id || *name == msg.name);
+            self.ids.insert(msg.name.clone(), msg.id);
+        }
+    }
+}
@@ -1,33 +1,53 @@
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
 use termion::color;
 extern crate clap;
 use clap::{App, Arg};
-use rand::Rng;
+use ducklink_codegen::{Builder, Diagnostic, Lang, Side};
+use std::path::Path;
 
-mod c_generator;
-mod cpp_generator;
-mod errors;
-mod generator;
-mod message;
-mod parser;
-mod python_generator;
+/// Default output root, relative to the current directory. Files of each language
+/// are written in `<out-dir>/<lang>/messages`.
+const DEFAULT_OUT_DIR: &str = "../lib";
 
-use c_generator::CGenerator;
-use cpp_generator::CPPGenerator;
-use generator::Generator;
-use python_generator::PythonGenerator;
+/// Argument overriding the output directory of each language, and its help.
+fn lang_out_arg(lang: Lang) -> (&'static str, &'static str) {
+    match lang {
+        Lang::C => ("c-out", "Write the C files directly in DIR."),
+        Lang::Cpp => ("cpp-out", "Write the C++ files directly in DIR."),
+        Lang::Python => ("python-out", "Write the Python files directly in DIR."),
+        Lang::Rust => ("rust-out", "Write the Rust files directly in DIR."),
+        Lang::Doc => (
+            "doc-out",
+            "Write the protocol documentation directly in DIR.",
+        ),
+    }
+}
+
+fn main() {
+    if let Err(diagnostics) = run() {
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        eprintln!(
+            "{}could not generate messages due to {} error{}{}",
+            color::Fg(color::Red),
+            diagnostics.len(),
+            if diagnostics.len() > 1 { "s" } else { "" },
+            color::Fg(color::Reset)
+        );
+        std::process::exit(1);
+    }
+}
 
-fn main() -> Result<(), Vec<String>> {
-    let matches = App::new("Ducklink message generator")
+fn run() -> Result<(), Vec<Diagnostic>> {
+    let mut app = App::new("Ducklink message generator")
         .version("0.1")
         .author("Fabien B. <fabien.bonneval@gmail.com>")
         .about("Generate messages according to input toml file")
         .arg(
             Arg::with_name("FILE")
-                .help("set input toml message file")
+                .help("set input toml message files, merged in one namespace")
                 .required(true)
+                .multiple(true)
                 .index(1),
         )
         .arg(
@@ -38,42 +58,89 @@ fn main() -> Result<(), Vec<String>> {
                 .takes_value(true)
                 .multiple(true)
                 .required(true)
-                .help("Languages to generate messages for. Possible values: C, CPP, Python."),
+                .help("Languages to generate messages for. Possible values: C, CPP, Python, Rust, Doc."),
         )
-        .get_matches();
-
-    let msg_file = matches.value_of("FILE").unwrap();
+        .arg(
+            Arg::with_name("out-dir")
+                .short("o")
+                .long("out-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Output root directory. Files are written in DIR/<LANG>/messages. [default: ../lib]"),
+        )
+        .arg(
+            Arg::with_name("side")
+                .long("side")
+                .value_name("SIDE")
+                .takes_value(true)
+                .possible_values(&["mcu", "host"])
+                .help("Only generate the encoders of the messages SIDE sends, and the decoders of those it receives."),
+        )
+        .arg(
+            Arg::with_name("stdout")
+                .long("stdout")
+                .help("Print the generated files instead of writing them. Nothing is written to disk."),
+        );
 
-    let contents = fs::read_to_string(msg_file).expect("Something went wrong reading the file");
-    let mut messages = parser::parse_toml(&contents)?;
-    messages.push(message::MsgSpec::uid_msg());
+    for lang in Lang::ALL.iter() {
+        let (arg, help) = lang_out_arg(*lang);
+        app = app.arg(
+            Arg::with_name(arg)
+                .long(arg)
+                .value_name("DIR")
+                .takes_value(true)
+                .help(help),
+        );
+    }
 
-    let mut rng = rand::thread_rng();
-    let UID: u32 = rng.gen();
+    let matches = app.get_matches();
+    let out_dir = Path::new(matches.value_of("out-dir").unwrap_or(DEFAULT_OUT_DIR));
 
+    let mut files = matches.values_of("FILE").unwrap();
+    let mut builder = Builder::new(files.next().unwrap())
+        .cargo_rerun(false)
+        .update_lock(true);
+    for file in files {
+        builder = builder.schema(file);
+    }
     for lang in matches.values_of("lang").unwrap() {
-        let files = match lang {
-            "Python" => PythonGenerator::generate_messages(&messages, UID),
-            "C" => CGenerator::generate_messages(&messages, UID),
-            "CPP" => CPPGenerator::generate_messages(&messages, UID),
-            _ => panic!("{} not supported!", lang),
+        let lang = Lang::from_name(lang).ok_or_else(|| {
+            vec![Diagnostic::new("", format!("{} not supported", lang))
+                .with_hint("possible values are C, CPP, Python, Rust and Doc")]
+        })?;
+        let lang_dir = match matches.value_of(lang_out_arg(lang).0) {
+            Some(dir) => Path::new(dir).to_path_buf(),
+            None => out_dir.join(lang.name()).join("messages"),
         };
+        builder = builder.lang(lang).lang_out_dir(lang, lang_dir);
+    }
+
+    if let Some(side) = matches.value_of("side") {
+        builder = builder.side(Side::from_name(side).unwrap());
+    }
 
-        for (f, txt) in files {
+    let to_stdout = matches.is_present("stdout");
+    let files = if to_stdout {
+        builder.render()?
+    } else {
+        builder.generate()?
+    };
+
+    for file in files {
+        // Printed files are kept free of escape codes, e.g. to be redirected.
+        if to_stdout {
+            println!(
+                "{}\n----------------------------\n{}\n",
+                file.path.display(),
+                file.contents
+            );
+        } else {
             println!(
                 "{}{}\n----------------------------{}",
                 color::Fg(color::Blue),
-                f,
+                file.path.display(),
                 color::Fg(color::Reset)
             );
-            //println!("{}\n", txt);
-            let path = format!("../lib/{}/messages/{}", lang, f);
-            let mut file = File::create(path).map_err(|e| {
-                println!("{}", e);
-                vec!["Fail to create file!".to_string()]
-            })?;
-            file.write_all(&txt.into_bytes())
-                .map_err(|_e| vec!["Fail write file!".to_string()])?;
         }
     }
 
@@ -1,18 +1,22 @@
 use crate::errors::ParserError;
-use toml::value::Value;
+use crate::parser::MAX_PAYLOAD_SIZE;
+use crate::uid::UidHasher;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use toml::value::{Table, Value};
 
 #[macro_export]
 macro_rules! bounds {
     ($name:ident) => {
         Bounds {
-            min: std::$name::MIN as i64,
-            max: std::$name::MAX as i64,
+            min: $name::MIN as i128,
+            max: $name::MAX as i128,
         }
     };
     ($name:ident; $T:ty) => {
         Bounds {
-            min: std::$name::MIN as $T,
-            max: std::$name::MAX as $T,
+            min: $name::MIN as $T,
+            max: $name::MAX as $T,
         }
     };
 }
@@ -22,8 +26,9 @@ macro_rules! set_min_max {
         let mut new_min = $b.min;
         if let Some(v) = $t_table.get("min") {
             if let $val(min) = v {
-                if min >= &$b.min {
-                    new_min = *min;
+                let min = From::from(*min);
+                if min >= $b.min {
+                    new_min = min;
                 } else {
                     return Err(ParserError::BoundsInvalid);
                 }
@@ -35,8 +40,9 @@ macro_rules! set_min_max {
         let mut new_max = $b.max;
         if let Some(v) = $t_table.get("max") {
             if let $val(max) = v {
-                if max <= &$b.max {
-                    new_max = *max;
+                let max = From::from(*max);
+                if max <= $b.max {
+                    new_max = max;
                 } else {
                     return Err(ParserError::BoundsInvalid);
                 }
@@ -58,17 +64,311 @@ macro_rules! set_min_max {
     }};
 }
 
+/// Byte order of the fields on the wire. Checksums are always sent little-endian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Which side sends a message, given by the class it is declared in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Declared in `[up]`: sent by the MCU to the host.
+    Up,
+    /// Declared in `[down]`: sent by the host to the MCU.
+    Down,
+    /// Declared in any other class: sent by both sides.
+    Both,
+}
+
+impl Direction {
+    pub fn from_class(class: &str) -> Direction {
+        match class {
+            "up" => Direction::Up,
+            "down" => Direction::Down,
+            _ => Direction::Both,
+        }
+    }
+}
+
+/// Side of the link the code is generated for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Mcu,
+    Host,
+}
+
+impl Side {
+    pub const ALL: [Side; 2] = [Side::Mcu, Side::Host];
+
+    /// Name of the side, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Mcu => "mcu",
+            Side::Host => "host",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Side> {
+        Side::ALL.iter().copied().find(|side| side.name() == name)
+    }
+
+    pub fn sends(self, direction: Direction) -> bool {
+        matches!(
+            (self, direction),
+            (_, Direction::Both) | (Side::Mcu, Direction::Up) | (Side::Host, Direction::Down)
+        )
+    }
+
+    pub fn receives(self, direction: Direction) -> bool {
+        direction == Direction::Both || !self.sends(direction)
+    }
+}
+
+/// Everything the generators need to know about the protocol.
+#[derive(Debug)]
+pub struct Schema {
+    pub messages: Vec<MsgSpec>,
+    pub enums: Vec<Rc<EnumSpec>>,
+    pub bitfields: Vec<Rc<BitfieldSpec>>,
+    /// Types declared in the `[types]` table, each one after the types it uses.
+    pub structs: Vec<Rc<StructSpec>>,
+    pub constants: Vec<ConstSpec>,
+    pub endianness: Endianness,
+    /// Side the code is generated for. If `None`, every message can be both encoded and decoded.
+    pub side: Option<Side>,
+}
+
+impl Schema {
+    /// Whether the code encoding `msg` is generated, i.e. this side sends it.
+    pub fn encodes(&self, msg: &MsgSpec) -> bool {
+        self.side.is_none_or(|side| side.sends(msg.direction))
+    }
+
+    /// Whether the code decoding `msg` is generated, i.e. this side receives it.
+    /// Other messages are rejected as unknown by the receiving code.
+    pub fn decodes(&self, msg: &MsgSpec) -> bool {
+        self.side.is_none_or(|side| side.receives(msg.direction))
+    }
+
+    /// Messages this side receives, in the order they are declared.
+    pub fn received(&self) -> Vec<&MsgSpec> {
+        self.messages
+            .iter()
+            .filter(|msg| self.decodes(msg))
+            .collect()
+    }
+
+    /// Feed the wire format settings to `hasher`. The default ones are not hashed,
+    /// so that UIDs of existing schemas don't change.
+    pub fn hash_wire(&self, hasher: &mut UidHasher) {
+        if self.endianness == Endianness::Big {
+            hasher.write_str("endianness=big");
+        }
+    }
+
+    /// Fields of the messages and of the types.
+    fn fields(&self) -> impl Iterator<Item = &Field> {
+        self.messages
+            .iter()
+            .flat_map(|msg| &msg.fields)
+            .chain(self.structs.iter().flat_map(|st| &st.fields))
+    }
+
+    /// Whether a field of a message or of a type is scaled, the generators then
+    /// emitting their quantize helper.
+    pub fn has_scaled(&self) -> bool {
+        self.fields()
+            .any(|field| matches!(field.t.scalar(), Type::Scaled(..)))
+    }
+
+    /// Whether a field of a message or of a type is a f64, that the C code copies
+    /// from and to a double.
+    pub fn has_f64(&self) -> bool {
+        self.fields()
+            .any(|field| matches!(field.t.scalar(), Type::F64(_)))
+    }
+}
+
+impl Endianness {
+    /// Shift of the byte `i` of a `size` bytes value, in bits.
+    pub fn shift(self, i: usize, size: usize) -> usize {
+        match self {
+            Endianness::Little => 8 * i,
+            Endianness::Big => 8 * (size - 1 - i),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MsgSpec {
     pub name: String,
+    /// Top-level table the message is declared in, e.g. `up`, the name starting with it.
+    pub class: String,
+    pub direction: Direction,
     pub id: usize,
     pub fields: Vec<Field>,
+    /// Description given by the `doc` key of the message table.
+    pub doc: Option<String>,
+    /// Unit given by the `unit` key of the message table, e.g. of all its fields.
+    pub unit: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Field {
     pub name: String,
     pub t: Type,
+    /// Value of the field in new messages, zero (or the first enum value) if not given.
+    pub default: Option<DefaultValue>,
+    pub doc: Option<String>,
+    /// Unit of the value, e.g. `rad`, only used in the documentation.
+    pub unit: Option<String>,
+}
+
+/// Value given as `default` in the schema, checked against the type of the field.
+/// Scaled integers hold the value sent, and arrays one value per element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Chars(String),
+    /// Name of an enum value, as declared.
+    Variant(String),
+    List(Vec<DefaultValue>),
+}
+
+/// Enum declared in the `[enums]` table, e.g. `[enums.DriveMode]`.
+#[derive(Debug)]
+pub struct EnumSpec {
+    pub name: String,
+    /// Integer type the values are sent as, `u8` by default.
+    pub repr: Type,
+    pub variants: Vec<(String, i128)>,
+}
+
+/// Type declared in the `[types]` table, e.g. `[types.Pose2D]`.
+/// Its fields are laid out on the wire as if they were fields of the message.
+#[derive(Debug)]
+pub struct StructSpec {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+/// Bitfield declared in the `[bitfields]` table, e.g. `[bitfields.ResetFlags]`.
+#[derive(Debug)]
+pub struct BitfieldSpec {
+    pub name: String,
+    /// Unsigned integer type the bits are packed into, `u8` by default.
+    pub repr: Type,
+    pub ranges: Vec<BitRange>,
+}
+
+/// Constant declared in the `[constants]` table, e.g. `MAX_SPEED = 1.5`. Bounds and lengths
+/// can name it instead of repeating its value.
+#[derive(Debug)]
+pub struct ConstSpec {
+    pub name: String,
+    /// i32 (or i64 if it doesn't fit), f64 or chars unless given, e.g. `{type="u16", value=1000}`.
+    pub t: Type,
+    pub value: DefaultValue,
+}
+
+/// Named bits of a bitfield, e.g. `level = "2..4"`. A single bit is a bool,
+/// wider ranges are unsigned integers.
+#[derive(Debug)]
+pub struct BitRange {
+    pub name: String,
+    /// Index of the lowest bit.
+    pub start: u32,
+    pub width: u32,
+}
+
+impl BitRange {
+    /// Largest value of the range.
+    pub fn max(&self) -> u64 {
+        (1 << self.width) - 1
+    }
+
+    /// Bits of the range, in place.
+    pub fn mask(&self) -> u64 {
+        self.max() << self.start
+    }
+}
+
+/// Named items declared in the schema, that fields can refer to.
+#[derive(Debug, Default)]
+pub struct Scope {
+    pub enums: Vec<Rc<EnumSpec>>,
+    pub structs: Vec<Rc<StructSpec>>,
+    pub bitfields: Vec<Rc<BitfieldSpec>>,
+    pub constants: Vec<ConstSpec>,
+}
+
+impl Scope {
+    pub fn find_enum(&self, name: &str) -> Option<&Rc<EnumSpec>> {
+        self.enums.iter().find(|e| e.name == name)
+    }
+
+    pub fn find_struct(&self, name: &str) -> Option<&Rc<StructSpec>> {
+        self.structs.iter().find(|s| s.name == name)
+    }
+
+    pub fn find_bitfield(&self, name: &str) -> Option<&Rc<BitfieldSpec>> {
+        self.bitfields.iter().find(|b| b.name == name)
+    }
+
+    pub fn find_constant(&self, name: &str) -> Option<&ConstSpec> {
+        self.constants.iter().find(|c| c.name == name)
+    }
+
+    /// Copy of `t_table` with the constants named by its bounds and lengths replaced by
+    /// their value, e.g. `max = "MAX_SPEED"`. The value is converted to a float for the
+    /// bounds of floats and to an integer otherwise, as long as it stays the same.
+    fn resolve_constants(&self, t_table: &Table) -> Result<Table, ParserError> {
+        let is_float = matches!(
+            t_table.get("type").and_then(Value::as_str),
+            Some("f32") | Some("f64")
+        );
+        let mut resolved = t_table.clone();
+        for key in &["min", "max", "len", "max_len"] {
+            if let Some(Value::String(name)) = t_table.get(*key) {
+                let is_bound = *key == "min" || *key == "max";
+                let as_float = is_float && is_bound;
+                let exact = match (self.find_constant(name).map(|c| &c.value), as_float) {
+                    (Some(DefaultValue::Int(v)), true) => Some(*v as f64)
+                        .filter(|f| *f as i128 == *v)
+                        .map(Value::Float),
+                    (Some(DefaultValue::Int(v)), false) => {
+                        i64::try_from(*v).ok().map(Value::Integer)
+                    }
+                    (Some(DefaultValue::Float(v)), true) => Some(Value::Float(*v)),
+                    // i64::MAX as f64 is 2^63, that doesn't fit.
+                    (Some(DefaultValue::Float(v)), false) => Some(*v)
+                        .filter(|v| {
+                            v.fract() == 0.0 && *v >= i64::MIN as f64 && *v < i64::MAX as f64
+                        })
+                        .map(|v| Value::Integer(v as i64)),
+                    _ => return Err(ParserError::ConstantNotFound),
+                };
+                let value = exact.ok_or(if is_bound {
+                    ParserError::BoundsInvalid
+                } else {
+                    ParserError::LenInvalid
+                })?;
+                resolved.insert(key.to_string(), value);
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Conversion of a scaled integer to the value it stands for: `raw * scale + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub scale: f64,
+    pub offset: f64,
 }
 
 #[derive(Debug)]
@@ -77,16 +377,39 @@ pub struct Bounds<T> {
     pub max: T,
 }
 
+/// Integer bounds are stored as i128 so that they can hold the whole u64 range.
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Type {
-    I8(Bounds<i64>),
-    I16(Bounds<i64>),
-    I32(Bounds<i64>),
-    U8(Bounds<i64>),
-    U16(Bounds<i64>),
-    U32(Bounds<i64>),
+    Bool,
+    I8(Bounds<i128>),
+    I16(Bounds<i128>),
+    I32(Bounds<i128>),
+    I64(Bounds<i128>),
+    U8(Bounds<i128>),
+    U16(Bounds<i128>),
+    U32(Bounds<i128>),
+    U64(Bounds<i128>),
     F32(Bounds<f64>),
+    F64(Bounds<f64>),
     CHARS(usize),
+    /// Fixed size array of `len` elements, e.g. `{type="u16", len=16}`. Elements can't be chars.
+    Array(Box<Type>, usize),
+    /// Up to `max_len` chars, e.g. `{type="chars", max_len=64}`. Sent as a length byte then the chars.
+    VarChars(usize),
+    /// Up to `max_len` elements, e.g. `{type="u16", max_len=16}`. Sent as a length byte then the elements.
+    VarArray(Box<Type>, usize),
+    /// Value of an enum declared in the schema, sent as its underlying integer type.
+    Enum(Rc<EnumSpec>),
+    /// Value of a type declared in the schema, sent as its fields.
+    Struct(Rc<StructSpec>),
+    /// Bits of a bitfield declared in the schema, sent as its underlying integer type.
+    Bitfield(Rc<BitfieldSpec>),
+    /// Integer standing for a float, e.g. `{type="i16", scale=0.001}`. The bounds of the
+    /// integer apply to the value sent, the API converts from and to `raw * scale + offset`.
+    /// Only integers of at most 32 bits can be scaled, as the conversion goes through
+    /// doubles, that can't hold every i64 or u64.
+    Scaled(Box<Type>, Scale),
 }
 
 impl MsgSpec {
@@ -97,94 +420,461 @@ impl MsgSpec {
     }
 
     /// Returns the payload size. This does NOT include msg id, len and payload.
+    /// For messages with variable length fields, this is the maximum size.
     pub fn get_payload_size(&self) -> usize {
-        self.fields.iter().map(|f| f.t.get_size()).sum()
+        self.fields
+            .iter()
+            .fold(0, |size, f| size.saturating_add(f.t.get_size()))
+    }
+
+    /// Same as `get_buffer_size`, with every variable length field empty.
+    pub fn get_min_buffer_size(&self) -> usize {
+        self.get_min_payload_size() + 6
+    }
+
+    pub fn get_min_payload_size(&self) -> usize {
+        self.fields.iter().map(|f| f.t.get_min_size()).sum()
+    }
+
+    /// Whether the length of the message depends on its content.
+    pub fn is_variable(&self) -> bool {
+        self.fields.iter().any(|f| f.t.is_variable())
     }
 
     pub fn uid_msg() -> MsgSpec {
         MsgSpec {
             name: "InterMcuUid".to_string(),
+            class: "inter_mcu".to_string(),
+            direction: Direction::Both,
             id: 0,
             fields: vec![Field {
                 name: "uid".to_string(),
                 t: Type::U32(bounds!(u32)),
+                default: None,
+                doc: Some("Hash of the wire format of the schema.".to_string()),
+                unit: None,
             }],
+            doc: Some(
+                "Sent to check that both sides were generated from the same schema.".to_string(),
+            ),
+            unit: None,
+        }
+    }
+
+    /// Doc of the message followed by its unit, as for fields.
+    pub fn description(&self) -> Option<String> {
+        describe(&self.doc, &self.unit)
+    }
+
+    /// Feed everything that matters on the wire to `hasher`: name, id, and every field.
+    pub fn hash_wire(&self, hasher: &mut UidHasher) {
+        hasher.write_str(&self.name);
+        hasher.write_u64(self.id as u64);
+        hasher.write_u64(self.fields.len() as u64);
+        for field in &self.fields {
+            field.hash_wire(hasher);
         }
     }
 }
 
+impl Field {
+    /// Parse the field `name`, given as a type name or as a table, e.g.
+    /// `{type="f32", default=1.5}`.
+    pub fn from_toml(name: &str, raw: &Value, scope: &Scope) -> Result<Field, ParserError> {
+        let t = Type::from_toml(raw, scope)?;
+        let default = match raw.get("default") {
+            Some(default) => Some(t.parse_default(default)?),
+            None => None,
+        };
+        let text = |key| match raw.get(key) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(ParserError::DocInvalid),
+            None => Ok(None),
+        };
+        Ok(Field {
+            name: name.to_string(),
+            t,
+            default,
+            doc: text("doc")?,
+            unit: text("unit")?,
+        })
+    }
+
+    /// Doc of the field followed by its unit, e.g. `Heading of the robot [rad]`.
+    pub fn description(&self) -> Option<String> {
+        describe(&self.doc, &self.unit)
+    }
+
+    /// Defaults and docs only change the generated code, not what is sent.
+    pub fn hash_wire(&self, hasher: &mut UidHasher) {
+        hasher.write_str(&self.name);
+        self.t.hash_wire(hasher);
+    }
+}
+
+/// `doc` followed by `unit`, e.g. `Heading of the robot [rad]`.
+fn describe(doc: &Option<String>, unit: &Option<String>) -> Option<String> {
+    match (doc, unit) {
+        (Some(doc), Some(unit)) => Some(format!("{} [{}]", doc, unit)),
+        (Some(doc), None) => Some(doc.clone()),
+        (None, Some(unit)) => Some(format!("[{}]", unit)),
+        (None, None) => None,
+    }
+}
+
 impl Type {
     const DEFAULT_CHARS_SIZE: usize = 10;
 
+    /// The length of variable length types is sent in one byte.
+    const MAX_VAR_LEN: i64 = 255;
+
+    /// Fixed lengths and sizes can't exceed the payload of a message.
+    const MAX_LEN: i64 = MAX_PAYLOAD_SIZE as i64;
+
+    /// Feed the type, its bounds and its size to `hasher`.
+    pub fn hash_wire(&self, hasher: &mut UidHasher) {
+        let tag = match self {
+            Type::I8(_) => 0,
+            Type::I16(_) => 1,
+            Type::I32(_) => 2,
+            Type::U8(_) => 3,
+            Type::U16(_) => 4,
+            Type::U32(_) => 5,
+            Type::F32(_) => 6,
+            Type::CHARS(_) => 7,
+            Type::Bool => 8,
+            Type::I64(_) => 9,
+            Type::U64(_) => 10,
+            Type::F64(_) => 11,
+            Type::Array(..) => 12,
+            Type::VarChars(_) => 13,
+            Type::VarArray(..) => 14,
+            Type::Enum(_) => 15,
+            Type::Struct(_) => 16,
+            Type::Bitfield(_) => 17,
+            Type::Scaled(..) => 18,
+        };
+        hasher.write_u8(tag);
+        hasher.write_u64(self.get_size() as u64);
+        match self {
+            Type::I8(b)
+            | Type::I16(b)
+            | Type::I32(b)
+            | Type::U8(b)
+            | Type::U16(b)
+            | Type::U32(b) => {
+                // Those bounds always fit in an i64, hashing them as such keeps the UIDs
+                // generated before 64 bits types were added.
+                hasher.write_i64(b.min as i64);
+                hasher.write_i64(b.max as i64);
+            }
+            Type::I64(b) | Type::U64(b) => {
+                hasher.write_i128(b.min);
+                hasher.write_i128(b.max);
+            }
+            Type::F32(b) | Type::F64(b) => {
+                hasher.write_f64(b.min);
+                hasher.write_f64(b.max);
+            }
+            Type::Array(elt, _len) => elt.hash_wire(hasher),
+            Type::VarArray(elt, _max_len) => elt.hash_wire(hasher),
+            // Only the values matter on the wire, not the names.
+            Type::Enum(e) => {
+                e.repr.hash_wire(hasher);
+                hasher.write_u64(e.variants.len() as u64);
+                for (_name, value) in &e.variants {
+                    hasher.write_i128(*value);
+                }
+            }
+            Type::Struct(st) => {
+                hasher.write_u64(st.fields.len() as u64);
+                for field in &st.fields {
+                    field.hash_wire(hasher);
+                }
+            }
+            Type::Bitfield(bf) => {
+                bf.repr.hash_wire(hasher);
+                hasher.write_u64(bf.ranges.len() as u64);
+                for range in &bf.ranges {
+                    hasher.write_u64(u64::from(range.start));
+                    hasher.write_u64(u64::from(range.width));
+                }
+            }
+            Type::Scaled(t, scale) => {
+                t.hash_wire(hasher);
+                hasher.write_f64(scale.scale);
+                hasher.write_f64(scale.offset);
+            }
+            Type::Bool | Type::CHARS(_) | Type::VarChars(_) => {}
+        }
+    }
+
+    /// Type of the elements of an array, the type itself otherwise.
+    pub fn scalar(&self) -> &Type {
+        match self {
+            Type::Array(elt, _len) | Type::VarArray(elt, _len) => elt,
+            _ => self,
+        }
+    }
+
+    pub fn is_variable(&self) -> bool {
+        matches!(self, Type::VarChars(_) | Type::VarArray(..))
+    }
+
+    /// Size on the wire. For variable length types, this is the size of the length byte.
+    pub fn get_min_size(&self) -> usize {
+        if self.is_variable() {
+            1
+        } else {
+            self.get_size()
+        }
+    }
+
+    /// Size on the wire, the maximum one for variable length types. Saturates rather than
+    /// overflows for arrays of large types, that are then reported as too large.
     pub fn get_size(&self) -> usize {
         match self {
+            Type::Bool => 1,
             Type::I8(_b) => 1,
             Type::I16(_b) => 2,
             Type::I32(_b) => 4,
+            Type::I64(_b) => 8,
             Type::U8(_b) => 1,
             Type::U16(_b) => 2,
             Type::U32(_b) => 4,
+            Type::U64(_b) => 8,
             Type::F32(_b) => 4,
+            Type::F64(_b) => 8,
             Type::CHARS(size) => *size,
+            Type::Array(elt, len) => elt.get_size().saturating_mul(*len),
+            Type::VarChars(max_len) => 1 + max_len,
+            Type::VarArray(elt, max_len) => {
+                elt.get_size().saturating_mul(*max_len).saturating_add(1)
+            }
+            Type::Enum(e) => e.repr.get_size(),
+            Type::Struct(st) => st
+                .fields
+                .iter()
+                .fold(0, |size, f| size.saturating_add(f.t.get_size())),
+            Type::Bitfield(bf) => bf.repr.get_size(),
+            Type::Scaled(t, _scale) => t.get_size(),
         }
     }
 
-    fn from_string(s: &str) -> Result<Type, ParserError> {
-        match s.as_ref() {
+    /// Check `raw` against the type and its bounds. A single value can be given for every
+    /// element of a fixed size array.
+    pub fn parse_default(&self, raw: &Value) -> Result<DefaultValue, ParserError> {
+        let in_bounds = |b: &Bounds<f64>, v: f64| b.min <= v && v <= b.max;
+        let value = match (self, raw) {
+            (Type::Bool, Value::Boolean(v)) => DefaultValue::Bool(*v),
+            (
+                Type::I8(b)
+                | Type::I16(b)
+                | Type::I32(b)
+                | Type::I64(b)
+                | Type::U8(b)
+                | Type::U16(b)
+                | Type::U32(b)
+                | Type::U64(b),
+                Value::Integer(v),
+            ) if b.min <= i128::from(*v) && i128::from(*v) <= b.max => {
+                DefaultValue::Int(i128::from(*v))
+            }
+            (Type::F32(b) | Type::F64(b), Value::Float(v)) if in_bounds(b, *v) => {
+                DefaultValue::Float(*v)
+            }
+            (Type::F32(b) | Type::F64(b), Value::Integer(v)) if in_bounds(b, *v as f64) => {
+                DefaultValue::Float(*v as f64)
+            }
+            // Quantized as the generated setters do, the value shall not need to be clamped.
+            (Type::Scaled(t, scale), Value::Float(_) | Value::Integer(_)) => {
+                let v = raw
+                    .as_float()
+                    .unwrap_or_else(|| raw.as_integer().unwrap() as f64);
+                let x = ((v - scale.offset) / scale.scale + 0.5).floor();
+                if !x.is_finite() {
+                    return Err(ParserError::DefaultInvalid);
+                }
+                return t.parse_default(&Value::Integer(x as i64));
+            }
+            // Fixed size chars keep room for the terminating null character.
+            (Type::CHARS(size), Value::String(v)) if v.len() < *size => {
+                DefaultValue::Chars(v.clone())
+            }
+            (Type::VarChars(max_len), Value::String(v)) if v.len() <= *max_len => {
+                DefaultValue::Chars(v.clone())
+            }
+            (Type::Enum(e), Value::String(v)) if e.variants.iter().any(|(name, _)| name == v) => {
+                DefaultValue::Variant(v.clone())
+            }
+            (Type::Bitfield(bf), Value::Integer(_)) => return bf.repr.parse_default(raw),
+            (Type::Array(elt, len), Value::Array(values)) if values.len() == *len => {
+                DefaultValue::List(
+                    values
+                        .iter()
+                        .map(|v| elt.parse_default(v))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (Type::Array(elt, len), _) => DefaultValue::List(vec![elt.parse_default(raw)?; *len]),
+            (Type::VarArray(elt, max_len), Value::Array(values)) if values.len() <= *max_len => {
+                DefaultValue::List(
+                    values
+                        .iter()
+                        .map(|v| elt.parse_default(v))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            _ => return Err(ParserError::DefaultInvalid),
+        };
+        Ok(value)
+    }
+
+    /// Parse a built-in type, or the name of an enum, type or bitfield of `scope`.
+    pub fn from_string(s: &str, scope: &Scope) -> Result<Type, ParserError> {
+        match s {
+            "bool" => Ok(Type::Bool),
             "i8" => Ok(Type::I8(bounds!(i8))),
             "i16" => Ok(Type::I16(bounds!(i16))),
             "i32" => Ok(Type::I32(bounds!(i32))),
+            "i64" => Ok(Type::I64(bounds!(i64))),
             "u8" => Ok(Type::U8(bounds!(u8))),
             "u16" => Ok(Type::U16(bounds!(u16))),
             "u32" => Ok(Type::U32(bounds!(u32))),
+            "u64" => Ok(Type::U64(bounds!(u64))),
             "f32" => Ok(Type::F32(bounds!(f32; f64))),
+            "f64" => Ok(Type::F64(bounds!(f64; f64))),
             "chars" => Ok(Type::CHARS(Type::DEFAULT_CHARS_SIZE)),
-            _ => {
-                println!("Type {} invalid.", s);
-                Err(ParserError::TypeInvalid)
-            }
+            _ => match (
+                scope.find_enum(s),
+                scope.find_struct(s),
+                scope.find_bitfield(s),
+            ) {
+                (Some(e), _, _) => Ok(Type::Enum(Rc::clone(e))),
+                (None, Some(st), _) => Ok(Type::Struct(Rc::clone(st))),
+                (None, None, Some(bf)) => Ok(Type::Bitfield(Rc::clone(bf))),
+                (None, None, None) => Err(ParserError::TypeInvalid),
+            },
         }
     }
 
-    pub fn from_toml(raw: &Value) -> Result<Type, ParserError> {
+    pub fn from_toml(raw: &Value, scope: &Scope) -> Result<Type, ParserError> {
         match raw {
-            Value::String(s) => Type::from_string(s.as_ref()),
+            Value::String(s) => Type::from_string(s.as_ref(), scope),
             Value::Table(t_table) => {
-                if let Value::String(s) = t_table.get("type").ok_or(ParserError::TypeNotFound)? {
-                    let mut t = Type::from_string(s.as_ref())?;
-                    match t {
-                        Type::I8(ref mut b)
-                        | Type::I16(ref mut b)
-                        | Type::I32(ref mut b)
-                        | Type::U8(ref mut b)
-                        | Type::U16(ref mut b)
-                        | Type::U32(ref mut b) => {
-                            set_min_max!(Value::Integer, t_table, b, t)?;
-                            Ok(t)
-                        }
-                        Type::F32(ref mut b) => {
-                            set_min_max!(Value::Float, t_table, b, t)?;
-                            Ok(t)
-                        }
-                        Type::CHARS(_size) => {
-                            if let Value::Integer(size) =
-                                t_table.get("size").ok_or(ParserError::SizeNotFound)?
+                let t_table = &scope.resolve_constants(t_table)?;
+                match (t_table.get("len"), t_table.get("max_len")) {
+                    (None, None) => Type::from_table(t_table, scope),
+                    (Some(len), None) => {
+                        let t = Type::from_table(t_table, scope)?;
+                        match len {
+                            Value::Integer(len)
+                                if *len > 0
+                                    && *len <= Type::MAX_LEN
+                                    && !matches!(t, Type::CHARS(_)) =>
                             {
-                                if size > &0 {
-                                    Ok(Type::CHARS(*size as usize))
-                                } else {
-                                    Err(ParserError::CharSizeInvalid)
-                                }
-                            } else {
-                                Err(ParserError::CharSizeInvalid)
+                                Ok(Type::Array(Box::new(t), *len as usize))
                             }
+                            _ => Err(ParserError::LenInvalid),
                         }
                     }
-                } else {
-                    Err(ParserError::TypeInvalid)
+                    (None, Some(Value::Integer(max_len)))
+                        if *max_len > 0 && *max_len <= Type::MAX_VAR_LEN =>
+                    {
+                        let max_len = *max_len as usize;
+                        if t_table.get("type").and_then(Value::as_str) == Some("chars") {
+                            Ok(Type::VarChars(max_len))
+                        } else {
+                            Ok(Type::VarArray(
+                                Box::new(Type::from_table(t_table, scope)?),
+                                max_len,
+                            ))
+                        }
+                    }
+                    _ => Err(ParserError::LenInvalid),
                 }
             }
             _ => Err(ParserError::TypeInvalid),
         }
     }
+
+    /// Parse a type given as a table, e.g. `{type="i16", min=-10, max=10}`.
+    /// Arrays are handled by `from_toml`, the bounds being those of the elements.
+    fn from_table(t_table: &Table, scope: &Scope) -> Result<Type, ParserError> {
+        let t = Type::from_unscaled_table(t_table, scope)?;
+        if !t_table.contains_key("scale") && !t_table.contains_key("offset") {
+            return Ok(t);
+        }
+
+        let number = |key, default| match t_table.get(key) {
+            None => Some(default),
+            Some(Value::Float(v)) if v.is_finite() => Some(*v),
+            Some(Value::Integer(v)) => Some(*v as f64),
+            _ => None,
+        };
+        // i64 and u64 are left out, see `Type::Scaled`.
+        match (t, number("scale", 1.0), number("offset", 0.0)) {
+            (
+                t @ (Type::I8(_)
+                | Type::I16(_)
+                | Type::I32(_)
+                | Type::U8(_)
+                | Type::U16(_)
+                | Type::U32(_)),
+                Some(scale),
+                Some(offset),
+            ) if scale != 0.0 => Ok(Type::Scaled(Box::new(t), Scale { scale, offset })),
+            _ => Err(ParserError::ScaleInvalid),
+        }
+    }
+
+    fn from_unscaled_table(t_table: &Table, scope: &Scope) -> Result<Type, ParserError> {
+        if let Value::String(s) = t_table.get("type").ok_or(ParserError::TypeNotFound)? {
+            let mut t = Type::from_string(s.as_ref(), scope)?;
+            match t {
+                Type::Bool => Ok(t),
+                Type::I8(ref mut b)
+                | Type::I16(ref mut b)
+                | Type::I32(ref mut b)
+                | Type::I64(ref mut b)
+                | Type::U8(ref mut b)
+                | Type::U16(ref mut b)
+                | Type::U32(ref mut b)
+                | Type::U64(ref mut b) => {
+                    set_min_max!(Value::Integer, t_table, b, t)?;
+                    Ok(t)
+                }
+                Type::F32(ref mut b) | Type::F64(ref mut b) => {
+                    set_min_max!(Value::Float, t_table, b, t)?;
+                    Ok(t)
+                }
+                Type::CHARS(_size) => {
+                    if let Value::Integer(size) =
+                        t_table.get("size").ok_or(ParserError::SizeNotFound)?
+                    {
+                        if *size > 0 && *size <= Type::MAX_LEN {
+                            Ok(Type::CHARS(*size as usize))
+                        } else {
+                            Err(ParserError::CharSizeInvalid)
+                        }
+                    } else {
+                        Err(ParserError::CharSizeInvalid)
+                    }
+                }
+                // Enums are bounded by their values, types by the bounds of their fields
+                // and bitfields by the width of their ranges.
+                Type::Enum(_) | Type::Struct(_) | Type::Bitfield(_)
+                    if t_table.contains_key("min") || t_table.contains_key("max") =>
+                {
+                    Err(ParserError::BoundsInvalid)
+                }
+                Type::Enum(_) | Type::Struct(_) | Type::Bitfield(_) => Ok(t),
+                Type::Array(..) | Type::VarChars(_) | Type::VarArray(..) | Type::Scaled(..) => {
+                    Err(ParserError::TypeInvalid)
+                }
+            }
+        } else {
+            Err(ParserError::TypeInvalid)
+        }
+    }
 }
@@ -1,87 +1,1334 @@
-use crate::message::{Field, MsgSpec, Type};
+use crate::bounds;
+use crate::errors::Diagnostic;
+use crate::locator::Locator;
+use crate::lock::IdLock;
+use crate::message::{
+    BitRange, BitfieldSpec, Bounds, ConstSpec, Direction, Endianness, EnumSpec, Field, MsgSpec,
+    Schema, Scope, StructSpec, Type,
+};
 use inflector::Inflector;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
 use toml::value::{Table, Value};
 
-// TODO refaire ça proprement
+/// Name of the top-level table holding schema options. It can't be used as a message class.
+const OPTIONS_TABLE: &str = "options";
 
-pub fn parse_toml(contents: &str) -> Result<Vec<MsgSpec>, Vec<String>> {
-    let value = contents
-        .parse::<Value>()
-        .map_err(|_e| vec!["IoError at TOML parsing!".to_string()])?;
+/// Name of the top-level table holding the enums, e.g. `[enums.DriveMode]`.
+/// It can't be used as a message class.
+const ENUMS_TABLE: &str = "enums";
 
-    let mut messages = vec![];
+/// Name of the top-level table holding the types, e.g. `[types.Pose2D]`.
+/// It can't be used as a message class.
+const TYPES_TABLE: &str = "types";
+
+/// Name of the top-level table holding the bitfields, e.g. `[bitfields.ResetFlags]`.
+/// It can't be used as a message class.
+const BITFIELDS_TABLE: &str = "bitfields";
+
+/// Name of the top-level table holding the constants, e.g. `MAX_SPEED = 1.5`.
+/// It can't be used as a message class.
+const CONSTANTS_TABLE: &str = "constants";
+
+/// Key of the list of files a schema includes, e.g. `include = ["common.toml"]`.
+/// Paths are relative to the including file. It can't be used as a message class.
+const INCLUDE_KEY: &str = "include";
+
+/// Names already defined by the generated code, that constants can't take.
+/// The names generated from the schema, e.g. `SIZE_UpOdom`, are checked by `check_names`.
+const RESERVED_CONSTANTS: [&str; 5] = [
+    "UID",
+    "MAX_MSG_BUFFER_SIZE",
+    "MESSAGES_H",
+    "DUCKMSG_H",
+    "DUCK_NATIVE_ORDER",
+];
+
+/// Keywords of C, C++, Python then Rust, along with the names defined by the C standard
+/// headers they include, e.g. `NULL`. Constants can't take them either.
+const KEYWORDS: &str = "\
+    auto break case char const continue default do double else enum extern float for goto if \
+    inline int long register restrict return short signed sizeof static struct switch typedef \
+    union unsigned void volatile while bool true false NULL \
+    alignas alignof and and_eq asm bitand bitor catch char16_t char32_t class compl constexpr \
+    const_cast decltype delete dynamic_cast explicit export friend mutable namespace new \
+    noexcept not not_eq nullptr operator or or_eq private protected public reinterpret_cast \
+    static_assert static_cast template this thread_local throw try typeid typename using \
+    virtual wchar_t xor xor_eq \
+    False None True as assert async await def del elif except finally from global import in is \
+    lambda nonlocal pass raise with yield \
+    crate dyn fn impl let loop match mod move mut pub ref self Self super trait type unsafe use \
+    where abstract become box final macro override priv typeof unsized";
+
+/// Key of the underlying integer type of an enum, the other keys being its values.
+const ENUM_TYPE_KEY: &str = "type";
+
+/// Key of the integer type a bitfield is packed into, the other keys being its bit ranges.
+const BITFIELD_TYPE_KEY: &str = "type";
+
+/// Key of the optional explicit message id, e.g. `id = 12`.
+/// It is only taken as the message id if its value is an integer, so fields can still be named `id`.
+const ID_KEY: &str = "id";
+
+/// Key of the optional message description, e.g. `doc = "Sent every 10 ms"`.
+/// It is only taken as the description if its value is a string, so fields named `doc`
+/// can still be declared as tables, e.g. `doc = {type="u8"}`. A string naming a type,
+/// e.g. `doc = "u8"`, is reported rather than guessed.
+const DOC_KEY: &str = "doc";
+
+/// Key of the optional unit of a message, e.g. `unit = "m/s"`, read as `DOC_KEY` is.
+const UNIT_KEY: &str = "unit";
+
+/// Message ids are sent in one byte, and id 0 is reserved to the UID message.
+pub(crate) const MAX_ID: usize = 255;
+/// The len byte of a frame counts the payload plus 2 bytes.
+pub(crate) const MAX_PAYLOAD_SIZE: usize = 253;
+
+/// Order in which message classes, messages and fields are laid out.
+#[derive(Debug, PartialEq)]
+pub enum Order {
+    /// Same order as in the TOML file. This is the default.
+    Declaration,
+    /// Sorted by name, as the generator used to do. Use it to stay compatible with
+    /// firmwares generated before declaration order was preserved.
+    Alphabetical,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub order: Order,
+    /// Byte order of the fields on the wire, little-endian by default.
+    pub endianness: Endianness,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            order: Order::Declaration,
+            endianness: Endianness::Little,
+        }
+    }
+}
+
+impl Options {
+    fn from_toml(raw: &Value, locator: &Locator) -> Result<Options, Vec<Diagnostic>> {
+        let mut options = Options::default();
+        let t = raw.as_table().ok_or_else(|| {
+            vec![diag(locator, &[OPTIONS_TABLE], "shall be a table").with_toml(raw)]
+        })?;
+
+        let mut errs = vec![];
+        for (key, value) in t {
+            match (key.as_str(), value.as_str()) {
+                ("order", Some("declaration")) => options.order = Order::Declaration,
+                ("order", Some("alphabetical")) => options.order = Order::Alphabetical,
+                ("order", _) => errs.push(
+                    diag(locator, &[OPTIONS_TABLE, key], "invalid order")
+                        .with_toml(value)
+                        .with_hint("expected \"declaration\" or \"alphabetical\""),
+                ),
+                ("endianness", Some("little")) => options.endianness = Endianness::Little,
+                ("endianness", Some("big")) => options.endianness = Endianness::Big,
+                ("endianness", _) => errs.push(
+                    diag(locator, &[OPTIONS_TABLE, key], "invalid endianness")
+                        .with_toml(value)
+                        .with_hint("expected \"little\" or \"big\""),
+                ),
+                _ => errs.push(
+                    diag(locator, &[OPTIONS_TABLE, key], "unknown option")
+                        .with_hint("options are \"order\" and \"endianness\""),
+                ),
+            }
+        }
+
+        if errs.is_empty() {
+            Ok(options)
+        } else {
+            Err(errs)
+        }
+    }
+}
+
+/// Diagnostic for the item at `path`, located in the source.
+fn diag<M: Into<String>>(locator: &Locator, path: &[&str], message: M) -> Diagnostic {
+    let diagnostic = Diagnostic::new(path.join("."), message).with_span(locator.locate(path));
+    match locator.file(path) {
+        Some(file) => diagnostic.with_file(file),
+        None => diagnostic,
+    }
+}
+
+/// Return the entries of `t` in the order required by `options`.
+fn ordered<'a>(t: &'a Table, options: &Options) -> Vec<(&'a String, &'a Value)> {
+    let mut entries = t.iter().collect::<Vec<_>>();
+    if options.order == Order::Alphabetical {
+        entries.sort_by_key(|(name, _)| *name);
+    }
+    entries
+}
+
+/// Parse the schema. Messages without an explicit id get the one pinned in `lock`,
+/// or a fresh one if they have never been assigned one.
+///
+/// All the errors of the schema are returned, not only the first one.
+pub fn parse_toml(contents: &str, lock: &IdLock) -> Result<Schema, Vec<Diagnostic>> {
+    let t_root = toml::from_str::<Table>(contents)
+        .map_err(|e| vec![Diagnostic::from_toml_error(&e, contents)])?;
+    parse_table(&t_root, &Locator::new(contents), lock)
+}
+
+/// Parse a schema split in several files, given as `(name, contents)`, in one namespace.
+/// Included files are not read: they shall be given too, before the files including them.
+pub fn parse_files(files: &[(&str, &str)], lock: &IdLock) -> Result<Schema, Vec<Diagnostic>> {
+    let mut tables = vec![];
+    let mut errs = vec![];
+    for (name, contents) in files {
+        match toml::from_str::<Table>(contents) {
+            Ok(table) => tables.push(table),
+            Err(e) => errs.push(Diagnostic::from_toml_error(&e, contents).with_file(*name)),
+        }
+    }
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    let t_root = merge_tables(files, tables, &mut errs);
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+    parse_table(&t_root, &Locator::with_files(files), lock)
+}
+
+/// Files included by a schema, as written in its `include` list.
+pub fn includes(contents: &str) -> Result<Vec<String>, Vec<Diagnostic>> {
+    let t_root = toml::from_str::<Table>(contents)
+        .map_err(|e| vec![Diagnostic::from_toml_error(&e, contents)])?;
+    let raw = match t_root.get(INCLUDE_KEY) {
+        Some(raw) => raw,
+        None => return Ok(vec![]),
+    };
+
+    let files = raw.as_array().and_then(|files| {
+        files
+            .iter()
+            .map(|file| file.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+    });
+    files.ok_or_else(|| {
+        vec![diag(
+            &Locator::new(contents),
+            &[INCLUDE_KEY],
+            "expected a list of files",
+        )
+        .with_toml(raw)
+        .with_hint(format!("e.g. {} = [\"common.toml\"]", INCLUDE_KEY))]
+    })
+}
 
-    match value {
-        Value::Table(t_root) => {
-            for (class, msgs) in t_root {
-                if let Value::Table(msgs) = msgs {
-                    let mut msgs = parse_message_class(&class.to_class_case(), &msgs)?;
-                    messages.append(&mut msgs);
+/// Merge the top-level tables of the schema files in one. Options shall have the same
+/// value in every file setting them, and the other entries, e.g. `types.Pose2D` or
+/// `up.OdomReport`, shall only be declared once.
+fn merge_tables(files: &[(&str, &str)], tables: Vec<Table>, errs: &mut Vec<Diagnostic>) -> Table {
+    let mut merged = Table::new();
+    // File declaring each entry, by path, e.g. `types.Pose2D`.
+    let mut origins: HashMap<String, &str> = HashMap::new();
+    for ((file, contents), table) in files.iter().zip(tables) {
+        let locator = Locator::new(contents);
+        let duplicate = |path: &[&str], first: &str| {
+            diag(&locator, path, format!("already declared in {}", first))
+                .with_file(*file)
+                .with_hint("the schema files share one namespace, rename one of them")
+        };
+
+        for (key, value) in table {
+            if key == INCLUDE_KEY {
+                continue;
+            }
+            match (merged.get_mut(&key), value) {
+                (Some(Value::Table(entries)), Value::Table(new_entries)) => {
+                    for (name, entry) in new_entries {
+                        let path = format!("{}.{}", key, name);
+                        match entries.get(&name) {
+                            Some(first) if key == OPTIONS_TABLE => {
+                                if *first != entry {
+                                    errs.push(
+                                        diag(
+                                            &locator,
+                                            &[&key, &name],
+                                            format!("differs from {}", origins[&path]),
+                                        )
+                                        .with_file(*file)
+                                        .with_toml(&entry)
+                                        .with_hint("options shall be the same in every file"),
+                                    );
+                                }
+                            }
+                            Some(_) => errs.push(duplicate(&[&key, &name], origins[&path])),
+                            None => {
+                                origins.insert(path, file);
+                                entries.insert(name, entry);
+                            }
+                        }
+                    }
+                }
+                (Some(_), _) => errs.push(duplicate(&[&key], origins[&key])),
+                (None, value) => {
+                    if let Value::Table(entries) = &value {
+                        for name in entries.keys() {
+                            origins.insert(format!("{}.{}", key, name), file);
+                        }
+                    }
+                    origins.insert(key.clone(), file);
+                    merged.insert(key, value);
                 }
             }
         }
-        _ => println!("ERROR ! Not a table !"),
     }
+    merged
+}
 
-    for (i, msg) in messages.iter_mut().enumerate() {
-        msg.id = i + 1; // id 0 is reserved to UID message.
+fn parse_table(
+    t_root: &Table,
+    locator: &Locator,
+    lock: &IdLock,
+) -> Result<Schema, Vec<Diagnostic>> {
+    let mut errs = vec![];
+    let options = match t_root.get(OPTIONS_TABLE) {
+        Some(raw) => Options::from_toml(raw, locator).unwrap_or_else(|mut e| {
+            errs.append(&mut e);
+            Options::default()
+        }),
+        None => Options::default(),
+    };
+
+    let mut scope = Scope::default();
+    if let Some(raw) = t_root.get(CONSTANTS_TABLE) {
+        scope.constants = parse_constants(raw, &options, locator, &mut errs);
+    }
+    if let Some(raw) = t_root.get(ENUMS_TABLE) {
+        scope.enums = parse_enums(raw, &options, locator, &mut errs);
+    }
+    if let Some(raw) = t_root.get(BITFIELDS_TABLE) {
+        scope.bitfields = parse_bitfields(raw, &options, locator, &scope, &mut errs);
+    }
+    if let Some(raw) = t_root.get(TYPES_TABLE) {
+        parse_types(raw, &options, locator, &mut scope, &mut errs);
+    }
+
+    // Messages, along with the path of their table, e.g. `down.SpeedCommand`.
+    let mut messages = vec![];
+    for (class, msgs) in ordered(t_root, &options) {
+        if [
+            OPTIONS_TABLE,
+            CONSTANTS_TABLE,
+            ENUMS_TABLE,
+            BITFIELDS_TABLE,
+            TYPES_TABLE,
+            INCLUDE_KEY,
+        ]
+        .contains(&class.as_str())
+        {
+            continue;
+        }
+        match msgs {
+            Value::Table(msgs) => parse_message_class(
+                class,
+                msgs,
+                &options,
+                &scope,
+                locator,
+                &mut messages,
+                &mut errs,
+            ),
+            _ => errs.push(
+                diag(locator, &[class], "expected a table of messages")
+                    .with_toml(msgs)
+                    .with_hint(format!("declare messages as [{}.MessageName]", class)),
+            ),
+        }
     }
 
-    Ok(messages)
+    check_names(&scope, &messages, locator, &mut errs);
+
+    if errs.is_empty() {
+        assign_ids(&mut messages, lock, locator, &mut errs);
+    }
+
+    if errs.is_empty() {
+        Ok(Schema {
+            messages: messages.into_iter().map(|(msg, _path)| msg).collect(),
+            enums: scope.enums,
+            bitfields: scope.bitfields,
+            structs: scope.structs,
+            constants: scope.constants,
+            endianness: options.endianness,
+            side: None,
+        })
+    } else {
+        Err(errs)
+    }
 }
 
-fn parse_message_class(class: &str, t: &Table) -> Result<Vec<MsgSpec>, Vec<String>> {
-    let msg_errs_tuples = t
+/// Report the declarations generating the same name, e.g. `up.odom_report` and
+/// `up.OdomReport` that both generate `UpOdomReport`, whatever their files, and the constants
+/// named like a C macro generated from the schema, e.g. `DRIVE_MODE_STOP` or `SIZE_UpOdom`.
+fn check_names(
+    scope: &Scope,
+    messages: &[(MsgSpec, Vec<String>)],
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) {
+    let declarations = scope
+        .enums
         .iter()
-        .filter_map(|(msg_name, m)| get_messages(class, &msg_name.to_class_case(), m))
-        .collect::<Vec<_>>();
-
-    let (msgs, errs) = msg_errs_tuples.into_iter().fold(
-        (Vec::<MsgSpec>::new(), Vec::new()),
-        |(mut msgs, mut errs), (m, e)| {
-            msgs.push(m);
-            errs.push(e);
-            (msgs, errs)
+        .map(|e| (&e.name, vec![ENUMS_TABLE.to_string(), e.name.clone()]))
+        .chain(
+            scope
+                .bitfields
+                .iter()
+                .map(|bf| (&bf.name, vec![BITFIELDS_TABLE.to_string(), bf.name.clone()])),
+        )
+        .chain(
+            scope
+                .structs
+                .iter()
+                .map(|st| (&st.name, vec![TYPES_TABLE.to_string(), st.name.clone()])),
+        )
+        .chain(messages.iter().map(|(msg, path)| (&msg.name, path.clone())));
+
+    // Path of a declaration, with its file if the schema is split.
+    let declared = |path: &[&str]| match locator.file(path) {
+        Some(file) if locator.is_split() => format!("{} ({})", path.join("."), file),
+        _ => path.join("."),
+    };
+
+    // Declaration generating each name.
+    let mut generated: HashMap<String, String> = HashMap::new();
+    for (name, path) in declarations {
+        let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+        let declared = declared(&path);
+        let name = name.to_class_case();
+        match generated.get(&name) {
+            Some(other) => errs.push(
+                diag(locator, &path, format!("generates {} like {}", name, other))
+                    .with_hint("the generated names are class-cased, rename one of them"),
+            ),
+            None => {
+                generated.insert(name, declared);
+            }
+        }
+    }
+
+    // Constants are defined as C macros, along with the values of the enums and the sizes
+    // and ids of the messages.
+    let mut macros: HashMap<String, Vec<String>> = HashMap::new();
+    for e in &scope.enums {
+        for (variant, _value) in &e.variants {
+            let name = format!(
+                "{}_{}",
+                e.name.to_screaming_snake_case(),
+                variant.to_screaming_snake_case()
+            );
+            macros.insert(
+                name,
+                vec![ENUMS_TABLE.to_string(), e.name.clone(), variant.clone()],
+            );
+        }
+    }
+    for (msg, path) in messages {
+        for prefix in &["SIZE_", "MIN_SIZE_", "ID_"] {
+            macros.insert(format!("{}{}", prefix, msg.name), path.clone());
+        }
+    }
+    for c in &scope.constants {
+        if let Some(path) = macros.get(&c.name) {
+            let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+            errs.push(
+                diag(
+                    locator,
+                    &[CONSTANTS_TABLE, &c.name],
+                    format!("{} is also generated for {}", c.name, declared(&path)),
+                )
+                .with_hint("rename the constant"),
+            );
+        }
+    }
+}
+
+/// Give an id to every message whose id is still 0, i.e. without an explicit id.
+///
+/// Explicit ids come first, then ids pinned in the lock, then the lowest ids never used
+/// before, so that existing messages keep their id whatever is added to the schema.
+fn assign_ids(
+    messages: &mut [(MsgSpec, Vec<String>)],
+    lock: &IdLock,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) {
+    let id_diag = |path: &[String], message: String| {
+        let mut path = path.iter().map(String::as_str).collect::<Vec<_>>();
+        path.push(ID_KEY);
+        diag(locator, &path, message)
+    };
+
+    // Name of the message at `path`, with its file if the schema is split in several.
+    let declared = |name: &str, path: &[String]| match locator
+        .file(&path.iter().map(String::as_str).collect::<Vec<_>>())
+    {
+        Some(file) if locator.is_split() => format!("{} ({})", name, file),
+        _ => name.to_string(),
+    };
+
+    let mut taken: HashMap<usize, String> = HashMap::new();
+    for (msg, path) in messages.iter().filter(|(msg, _path)| msg.id != 0) {
+        if let Some(other) = taken.insert(msg.id, declared(&msg.name, path)) {
+            errs.push(
+                id_diag(path, format!("id {} is already used by {}", msg.id, other))
+                    .with_value(msg.id),
+            );
+        }
+    }
+
+    let mut pinned = vec![];
+    for (msg, path) in messages.iter_mut().filter(|(msg, _path)| msg.id == 0) {
+        if let Some(id) = lock.get(&msg.name) {
+            match taken.get(&id) {
+                Some(other) => errs.push(
+                    diag(
+                        locator,
+                        &path.iter().map(String::as_str).collect::<Vec<_>>(),
+                        format!(
+                            "id {} is pinned to {} in the lock file, but {} uses it",
+                            id, msg.name, other
+                        ),
+                    )
+                    .with_hint(format!(
+                        "change the explicit id of {} or remove the {} entry from the lock file",
+                        other, msg.name
+                    )),
+                ),
+                None => {
+                    msg.id = id;
+                    pinned.push(id);
+                }
+            }
+        }
+    }
+
+    if !errs.is_empty() {
+        return;
+    }
+
+    let used =
+        |id: usize| pinned.contains(&id) || taken.contains_key(&id) || lock.owner(id).is_some();
+
+    let mut next_id = 1;
+    for (msg, path) in messages.iter_mut().filter(|(msg, _path)| msg.id == 0) {
+        while used(next_id) {
+            next_id += 1;
+        }
+        if next_id > MAX_ID {
+            errs.push(
+                diag(
+                    locator,
+                    &path.iter().map(String::as_str).collect::<Vec<_>>(),
+                    "no id left",
+                )
+                .with_hint(format!("at most {} messages can be declared", MAX_ID)),
+            );
+            return;
+        }
+        msg.id = next_id;
+        next_id += 1;
+    }
+}
+
+fn parse_message_class(
+    class: &str,
+    t: &Table,
+    options: &Options,
+    scope: &Scope,
+    locator: &Locator,
+    messages: &mut Vec<(MsgSpec, Vec<String>)>,
+    errs: &mut Vec<Diagnostic>,
+) {
+    for (msg_name, msg_table) in ordered(t, options) {
+        let path = [class, msg_name.as_str()];
+        match msg_table {
+            Value::Table(msg_table) => {
+                if let Some(msg) = get_message(&path, msg_table, options, scope, locator, errs) {
+                    messages.push((msg, path.iter().map(|p| p.to_string()).collect()));
+                }
+            }
+            _ => errs.push(
+                diag(locator, &path, "expected a message table")
+                    .with_toml(msg_table)
+                    .with_hint(format!("declare the message as [{}.{}]", class, msg_name)),
+            ),
+        }
+    }
+}
+
+/// Whether `name` can be used as is as a type or constant name in every generated language.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the `[constants]` table. Constants with errors are left out, the errors being appended
+/// to `errs`.
+fn parse_constants(
+    raw: &Value,
+    options: &Options,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Vec<ConstSpec> {
+    let constants = match raw {
+        Value::Table(constants) => constants,
+        _ => {
+            errs.push(
+                diag(locator, &[CONSTANTS_TABLE], "expected a table of constants")
+                    .with_toml(raw)
+                    .with_hint(format!(
+                        "declare constants as [{}] followed by NAME = value",
+                        CONSTANTS_TABLE
+                    )),
+            );
+            return vec![];
+        }
+    };
+
+    ordered(constants, options)
+        .into_iter()
+        .filter_map(|(name, value)| get_constant(&[CONSTANTS_TABLE, name], value, locator, errs))
+        .collect()
+}
+
+/// Parse the constant at `path`, i.e. `[constants, name]`, given as its value or as a table
+/// with its type and value. Errors are appended to `errs`.
+fn get_constant(
+    path: &[&str; 2],
+    raw: &Value,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Option<ConstSpec> {
+    let name = path[1];
+    if !is_identifier(name) {
+        errs.push(diag(locator, path, "invalid constant name").with_hint(
+            "constant names shall be made of letters, digits and underscores, and start with a \
+             letter",
+        ));
+        return None;
+    }
+    let is_keyword = KEYWORDS.split_whitespace().any(|keyword| keyword == name);
+    if RESERVED_CONSTANTS.contains(&name) || is_keyword {
+        let reason = if is_keyword {
+            "a keyword of the generated languages"
+        } else {
+            "defined by the generated code"
+        };
+        errs.push(
+            diag(locator, path, "reserved constant name")
+                .with_hint(format!("{} is {}, rename the constant", name, reason)),
+        );
+        return None;
+    }
+
+    let (t, value) = match raw {
+        Value::Table(t) => (t.get("type").and_then(Value::as_str), t.get("value")),
+        _ => (None, Some(raw)),
+    };
+    let t = match (t, value) {
+        (None, Some(Value::Integer(v))) if i32::try_from(*v).is_ok() => {
+            Some(Type::I32(bounds!(i32)))
+        }
+        (None, Some(Value::Integer(_))) => Some(Type::I64(bounds!(i64))),
+        (None, Some(Value::Float(_))) => Some(Type::F64(bounds!(f64; f64))),
+        // Room is kept for the terminating null character, as for chars fields.
+        (None | Some("chars"), Some(Value::String(s))) => Some(Type::CHARS(s.len() + 1)),
+        (Some(t), Some(_)) => match Type::from_string(t, &Scope::default()) {
+            Ok(
+                t @ (Type::I8(_)
+                | Type::I16(_)
+                | Type::I32(_)
+                | Type::I64(_)
+                | Type::U8(_)
+                | Type::U16(_)
+                | Type::U32(_)
+                | Type::U64(_)
+                | Type::F32(_)
+                | Type::F64(_)),
+            ) => Some(t),
+            _ => None,
+        },
+        _ => None,
+    };
+    match (t, value) {
+        (Some(t), Some(value)) => match t.parse_default(value) {
+            Ok(value) => Some(ConstSpec {
+                name: name.to_string(),
+                t,
+                value,
+            }),
+            Err(_) => {
+                errs.push(
+                    diag(locator, path, "invalid constant value")
+                        .with_toml(raw)
+                        .with_hint("the value shall be within the bounds of the type"),
+                );
+                None
+            }
         },
-    );
+        _ => {
+            errs.push(
+                diag(locator, path, "invalid constant")
+                    .with_toml(raw)
+                    .with_hint(
+                        "constants are integers, floats or strings, e.g. MAX_SPEED = 1.5, \
+                         or tables giving their type, e.g. {type=\"u16\", value=1000}",
+                    ),
+            );
+            None
+        }
+    }
+}
 
-    let errs = errs.into_iter().flatten().collect::<Vec<_>>();
+/// Parse the `[enums]` table. Enums with errors are left out, the errors being appended to `errs`.
+fn parse_enums(
+    raw: &Value,
+    options: &Options,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Vec<Rc<EnumSpec>> {
+    let enums = match raw {
+        Value::Table(enums) => enums,
+        _ => {
+            errs.push(
+                diag(locator, &[ENUMS_TABLE], "expected a table of enums")
+                    .with_toml(raw)
+                    .with_hint(format!("declare enums as [{}.EnumName]", ENUMS_TABLE)),
+            );
+            return vec![];
+        }
+    };
 
-    if errs.is_empty() {
-        Ok(msgs)
+    let mut specs = vec![];
+    for (name, enum_table) in ordered(enums, options) {
+        let path = [ENUMS_TABLE, name.as_str()];
+        match enum_table {
+            Value::Table(enum_table) => {
+                if let Some(spec) = get_enum(&path, enum_table, options, locator, errs) {
+                    specs.push(Rc::new(spec));
+                }
+            }
+            _ => errs.push(
+                diag(locator, &path, "expected an enum table")
+                    .with_toml(enum_table)
+                    .with_hint(format!("declare the enum as [{}.{}]", ENUMS_TABLE, name)),
+            ),
+        }
+    }
+    specs
+}
+
+/// Parse the enum at `path`, i.e. `[enums, name]`. Errors are appended to `errs`,
+/// and the enum is only returned if it has none.
+fn get_enum(
+    path: &[&str; 2],
+    enum_table: &Table,
+    options: &Options,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Option<EnumSpec> {
+    let name = path[1];
+    let nb_errs = errs.len();
+
+    if !is_identifier(name) || Type::from_string(name, &Scope::default()).is_ok() {
+        errs.push(diag(locator, path, "invalid enum name").with_hint(
+            "enum names shall be made of letters, digits and underscores, start with a \
+                 letter, and not be a built-in type",
+        ));
+    }
+
+    // Enum values shall fit in a C enum, i.e. in an int.
+    let repr = match enum_table.get(ENUM_TYPE_KEY) {
+        None => Type::U8(bounds!(u8)),
+        Some(raw) => match raw
+            .as_str()
+            .map(|s| Type::from_string(s, &Scope::default()))
+        {
+            Some(Ok(t @ Type::I8(_)))
+            | Some(Ok(t @ Type::I16(_)))
+            | Some(Ok(t @ Type::I32(_)))
+            | Some(Ok(t @ Type::U8(_)))
+            | Some(Ok(t @ Type::U16(_)))
+            | Some(Ok(t @ Type::U32(_))) => t,
+            _ => {
+                errs.push(
+                    diag(
+                        locator,
+                        &[path[0], path[1], ENUM_TYPE_KEY],
+                        "invalid enum type",
+                    )
+                    .with_toml(raw)
+                    .with_hint("the type of an enum shall be i8, i16, i32, u8, u16 or u32"),
+                );
+                Type::I32(bounds!(i32))
+            }
+        },
+    };
+    let (min, max) = match &repr {
+        Type::U32(b) => (b.min, i128::from(i32::MAX)),
+        Type::I8(b) | Type::I16(b) | Type::I32(b) | Type::U8(b) | Type::U16(b) => (b.min, b.max),
+        _ => unreachable!(),
+    };
+
+    let mut variants: Vec<(String, i128)> = vec![];
+    for (variant, value) in ordered(enum_table, options) {
+        if variant == ENUM_TYPE_KEY {
+            continue;
+        }
+        let variant_path = [path[0], path[1], variant.as_str()];
+        if !is_identifier(variant) {
+            errs.push(diag(locator, &variant_path, "invalid enum value name").with_hint(
+                "value names shall be made of letters, digits and underscores, and start with a letter",
+            ));
+        }
+        match value.as_integer().map(i128::from) {
+            Some(v) if min <= v && v <= max => {
+                if let Some((other, _)) = variants.iter().find(|(_, w)| *w == v) {
+                    errs.push(
+                        diag(locator, &variant_path, "duplicate enum value")
+                            .with_value(v)
+                            .with_hint(format!("{} already has this value", other)),
+                    );
+                }
+                variants.push((variant.to_string(), v));
+            }
+            _ => errs.push(
+                diag(locator, &variant_path, "invalid enum value")
+                    .with_toml(value)
+                    .with_hint(format!(
+                        "enum values shall be integers in {}..={}",
+                        min, max
+                    )),
+            ),
+        }
+    }
+
+    if variants.is_empty() && errs.len() == nb_errs {
+        errs.push(
+            diag(locator, path, "enum has no value")
+                .with_hint("an enum shall have at least one value, e.g. Stop = 0"),
+        );
+    }
+
+    if errs.len() == nb_errs {
+        Some(EnumSpec {
+            name: name.to_string(),
+            repr,
+            variants,
+        })
     } else {
-        Err(errs)
+        None
+    }
+}
+
+/// Parse the `[bitfields]` table. Bitfields with errors are left out, the errors being appended
+/// to `errs`. Their names shall not clash with the enums of `scope`.
+fn parse_bitfields(
+    raw: &Value,
+    options: &Options,
+    locator: &Locator,
+    scope: &Scope,
+    errs: &mut Vec<Diagnostic>,
+) -> Vec<Rc<BitfieldSpec>> {
+    let bitfields = match raw {
+        Value::Table(bitfields) => bitfields,
+        _ => {
+            errs.push(
+                diag(locator, &[BITFIELDS_TABLE], "expected a table of bitfields")
+                    .with_toml(raw)
+                    .with_hint(format!(
+                        "declare bitfields as [{}.BitfieldName]",
+                        BITFIELDS_TABLE
+                    )),
+            );
+            return vec![];
+        }
+    };
+
+    let mut specs = vec![];
+    for (name, bitfield_table) in ordered(bitfields, options) {
+        let path = [BITFIELDS_TABLE, name.as_str()];
+        match bitfield_table {
+            Value::Table(bitfield_table) => {
+                if let Some(spec) =
+                    get_bitfield(&path, bitfield_table, options, scope, locator, errs)
+                {
+                    specs.push(Rc::new(spec));
+                }
+            }
+            _ => errs.push(
+                diag(locator, &path, "expected a bitfield table")
+                    .with_toml(bitfield_table)
+                    .with_hint(format!(
+                        "declare the bitfield as [{}.{}]",
+                        BITFIELDS_TABLE, name
+                    )),
+            ),
+        }
+    }
+    specs
+}
+
+/// Parse the bitfield at `path`, i.e. `[bitfields, name]`. Errors are appended to `errs`,
+/// and the bitfield is only returned if it has none.
+fn get_bitfield(
+    path: &[&str; 2],
+    bitfield_table: &Table,
+    options: &Options,
+    scope: &Scope,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Option<BitfieldSpec> {
+    let name = path[1];
+    let nb_errs = errs.len();
+
+    if !is_identifier(name) || is_builtin(name) {
+        errs.push(diag(locator, path, "invalid bitfield name").with_hint(
+            "bitfield names shall be made of letters, digits and underscores, start with a \
+             letter, and not be a built-in type",
+        ));
+    } else if scope.find_enum(name).is_some() {
+        errs.push(
+            diag(locator, path, "invalid bitfield name")
+                .with_hint(format!("{} is already declared in [{}]", name, ENUMS_TABLE)),
+        );
+    }
+
+    let repr = match bitfield_table.get(BITFIELD_TYPE_KEY) {
+        None => Type::U8(bounds!(u8)),
+        Some(raw) => match raw
+            .as_str()
+            .map(|s| Type::from_string(s, &Scope::default()))
+        {
+            Some(Ok(t @ Type::U8(_))) | Some(Ok(t @ Type::U16(_))) | Some(Ok(t @ Type::U32(_))) => {
+                t
+            }
+            _ => {
+                errs.push(
+                    diag(
+                        locator,
+                        &[path[0], path[1], BITFIELD_TYPE_KEY],
+                        "invalid bitfield type",
+                    )
+                    .with_toml(raw)
+                    .with_hint("the type of a bitfield shall be u8, u16 or u32"),
+                );
+                Type::U32(bounds!(u32))
+            }
+        },
+    };
+    let nb_bits = 8 * repr.get_size() as u32;
+
+    let mut ranges: Vec<BitRange> = vec![];
+    for (range_name, value) in ordered(bitfield_table, options) {
+        if range_name == BITFIELD_TYPE_KEY {
+            continue;
+        }
+        let range_path = [path[0], path[1], range_name.as_str()];
+        // `bits` is the whole bitfield in the generated code.
+        if !is_identifier(range_name) || range_name == "bits" {
+            errs.push(diag(locator, &range_path, "invalid bit range name").with_hint(
+                "bit range names shall be made of letters, digits and underscores, start with a \
+                 letter, and not be \"bits\"",
+            ));
+        }
+        match parse_bit_range(value) {
+            Some((start, width)) if start + width <= nb_bits => {
+                let range = BitRange {
+                    name: range_name.to_string(),
+                    start,
+                    width,
+                };
+                if let Some(other) = ranges.iter().find(|r| r.mask() & range.mask() != 0) {
+                    errs.push(
+                        diag(locator, &range_path, "overlapping bit ranges")
+                            .with_toml(value)
+                            .with_hint(format!("{} already uses some of these bits", other.name)),
+                    );
+                }
+                ranges.push(range);
+            }
+            _ => errs.push(
+                diag(locator, &range_path, "invalid bit range")
+                    .with_toml(value)
+                    .with_hint(format!(
+                        "bits shall be given as an index, e.g. 0, or as a range, e.g. \"2..4\" \
+                         for bits 2 and 3, within the {} bits of the bitfield",
+                        nb_bits
+                    )),
+            ),
+        }
+    }
+
+    if ranges.is_empty() && errs.len() == nb_errs {
+        errs.push(
+            diag(locator, path, "bitfield has no bit")
+                .with_hint("a bitfield shall have at least one bit range, e.g. enabled = 0"),
+        );
+    }
+
+    if errs.len() == nb_errs {
+        Some(BitfieldSpec {
+            name: name.to_string(),
+            repr,
+            ranges,
+        })
+    } else {
+        None
+    }
+}
+
+/// Start and width of a bit range, given as a bit index or as a `"start..end"` string,
+/// `end` being excluded as in Rust ranges.
+fn parse_bit_range(raw: &Value) -> Option<(u32, u32)> {
+    match raw {
+        Value::Integer(bit) => u32::try_from(*bit).ok().map(|bit| (bit, 1)),
+        Value::String(range) => {
+            let (start, end) = range.split_once("..")?;
+            let start = start.trim().parse::<u32>().ok()?;
+            let end = end.trim().parse::<u32>().ok()?;
+            if start < end {
+                Some((start, end - start))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Name of the type `raw` refers to, e.g. `Pose2D` for `"Pose2D"` or `{type="Pose2D", len=3}`.
+fn type_name(raw: &Value) -> Option<&str> {
+    match raw {
+        Value::String(s) => Some(s),
+        Value::Table(t) => t.get("type").and_then(Value::as_str),
+        _ => None,
     }
 }
 
-fn get_messages(class: &str, msg_name: &str, msg_table: &Value) -> Option<(MsgSpec, Vec<String>)> {
-    let mut name = class.to_string();
-    name.push_str(msg_name);
-    if let Value::Table(msg_table) = msg_table {
-        let (fields, errs): (Vec<_>, Vec<_>) = msg_table
+/// Parse the `[types]` table into `scope`, each type after the types it uses.
+/// Types with errors are left out, the errors being appended to `errs`.
+fn parse_types(
+    raw: &Value,
+    options: &Options,
+    locator: &Locator,
+    scope: &mut Scope,
+    errs: &mut Vec<Diagnostic>,
+) {
+    let types = match raw {
+        Value::Table(types) => types,
+        _ => {
+            errs.push(
+                diag(locator, &[TYPES_TABLE], "expected a table of types")
+                    .with_toml(raw)
+                    .with_hint(format!("declare types as [{}.TypeName]", TYPES_TABLE)),
+            );
+            return;
+        }
+    };
+
+    let mut resolver = TypeResolver {
+        types,
+        options,
+        locator,
+        resolving: vec![],
+        done: vec![],
+        failed: vec![],
+    };
+    for (name, _) in ordered(types, options) {
+        resolver.resolve(name, scope, errs);
+    }
+}
+
+fn is_builtin(name: &str) -> bool {
+    Type::from_string(name, &Scope::default()).is_ok()
+}
+
+/// Parses the types in dependency order, and detects the types that contain themselves.
+struct TypeResolver<'a> {
+    types: &'a Table,
+    options: &'a Options,
+    locator: &'a Locator<'a>,
+    /// Types being parsed, each one using the next one.
+    resolving: Vec<&'a str>,
+    done: Vec<&'a str>,
+    /// Types left out because of errors. Fields using them are not reported again.
+    failed: Vec<&'a str>,
+}
+
+impl<'a> TypeResolver<'a> {
+    fn resolve(&mut self, name: &'a str, scope: &mut Scope, errs: &mut Vec<Diagnostic>) {
+        if self.done.contains(&name) {
+            return;
+        }
+        let path = [TYPES_TABLE, name];
+        let nb_errs = errs.len();
+
+        let table = match &self.types[name] {
+            Value::Table(table) => table,
+            raw => {
+                errs.push(
+                    diag(self.locator, &path, "expected a type table")
+                        .with_toml(raw)
+                        .with_hint(format!("declare the type as [{}.{}]", TYPES_TABLE, name)),
+                );
+                self.done.push(name);
+                self.failed.push(name);
+                return;
+            }
+        };
+
+        // Fields naming a built-in type, an enum or a bitfield use it, not this type.
+        if !is_identifier(name) || is_builtin(name) {
+            errs.push(diag(self.locator, &path, "invalid type name").with_hint(
+                "type names shall be made of letters, digits and underscores, start with a \
+                     letter, and not be a built-in type",
+            ));
+        } else if scope.find_enum(name).is_some() {
+            errs.push(
+                diag(self.locator, &path, "invalid type name")
+                    .with_hint(format!("{} is already declared in [{}]", name, ENUMS_TABLE)),
+            );
+        } else if scope.find_bitfield(name).is_some() {
+            errs.push(
+                diag(self.locator, &path, "invalid type name").with_hint(format!(
+                    "{} is already declared in [{}]",
+                    name, BITFIELDS_TABLE
+                )),
+            );
+        }
+        if errs.len() != nb_errs {
+            self.done.push(name);
+            self.failed.push(name);
+            return;
+        }
+
+        self.resolving.push(name);
+        let mut uses_failed = false;
+        let mut fields = vec![];
+        for (field_name, typ) in ordered(table, self.options) {
+            let field_path = [TYPES_TABLE, name, field_name.as_str()];
+            let types = self.types;
+            let used = type_name(typ)
+                .filter(|u| {
+                    !is_builtin(u)
+                        && scope.find_enum(u).is_none()
+                        && scope.find_bitfield(u).is_none()
+                })
+                .and_then(|u| types.keys().find(|k| *k == u));
+            if let Some(used) = used.map(String::as_str) {
+                if let Some(start) = self.resolving.iter().position(|r| *r == used) {
+                    let cycle = self.resolving[start..].join(" -> ");
+                    errs.push(
+                        diag(self.locator, &field_path, "recursive type")
+                            .with_toml(typ)
+                            .with_hint(format!("{} -> {} would never end", cycle, used)),
+                    );
+                    continue;
+                }
+                self.resolve(used, scope, errs);
+                if self.failed.contains(&used) {
+                    uses_failed = true;
+                    continue;
+                }
+            }
+
+            if let Some(field) = parse_field(&field_path, typ, scope, self.locator, errs) {
+                if field.t.is_variable() {
+                    errs.push(
+                        diag(self.locator, &field_path, "variable length field in a type")
+                            .with_toml(typ)
+                            .with_hint("types shall have a fixed size, use len instead of max_len"),
+                    );
+                }
+                fields.push(field);
+            }
+        }
+        self.resolving.pop();
+        self.done.push(name);
+
+        if fields.is_empty() && !uses_failed && errs.len() == nb_errs {
+            errs.push(
+                diag(self.locator, &path, "type has no field")
+                    .with_hint("a type shall have at least one field, e.g. x = \"f32\""),
+            );
+        }
+
+        let size = fields
             .iter()
-            .map(|(name, typ)| match Type::from_toml(typ) {
-                Ok(pty) => Ok(Field {
-                    name: name.to_string(),
-                    t: pty,
-                }),
-                Err(e) => Err(format!("{}.{}: {}", msg_name, name, e)),
-            })
-            .partition(Result::is_ok);
-        let fields: Vec<_> = fields.into_iter().map(Result::unwrap).collect();
-        let errs: Vec<_> = errs.into_iter().map(Result::unwrap_err).collect();
-        if fields.len() == 0 {
-            panic!(format!("Message {} shall have at least one member.", name));
-        }
-
-        Some((
-            MsgSpec {
-                name,
-                id: 0,
+            .fold(0, |size: usize, f| size.saturating_add(f.t.get_size()));
+        if errs.len() == nb_errs && size > MAX_PAYLOAD_SIZE {
+            errs.push(
+                diag(self.locator, &path, "type too large")
+                    .with_value(size)
+                    .with_hint(format!(
+                        "a type is sent in the payload of a message, that shall be at most {} bytes",
+                        MAX_PAYLOAD_SIZE
+                    )),
+            );
+        }
+
+        if uses_failed || errs.len() != nb_errs {
+            self.failed.push(name);
+        } else {
+            scope.structs.push(Rc::new(StructSpec {
+                name: name.to_string(),
                 fields,
-            },
-            errs,
-        ))
+            }));
+        }
+    }
+}
+
+/// Parse the field at `path`, the last element being its name.
+fn parse_field(
+    path: &[&str],
+    typ: &Value,
+    scope: &Scope,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Option<Field> {
+    match Field::from_toml(path[path.len() - 1], typ, scope) {
+        Ok(field) => Some(field),
+        Err(e) => {
+            errs.push(
+                diag(locator, path, e.to_string())
+                    .with_toml(typ)
+                    .with_hint(e.hint()),
+            );
+            None
+        }
+    }
+}
+
+/// Parse the message at `path`, i.e. `[class, name]`. Errors are appended to `errs`,
+/// and the message is only returned if it has none.
+fn get_message(
+    path: &[&str; 2],
+    msg_table: &Table,
+    options: &Options,
+    scope: &Scope,
+    locator: &Locator,
+    errs: &mut Vec<Diagnostic>,
+) -> Option<MsgSpec> {
+    let [class, msg_name] = *path;
+    let name = format!("{}{}", class.to_class_case(), msg_name.to_class_case());
+    let nb_errs = errs.len();
+
+    let mut id = 0;
+    if let Some(Value::Integer(i)) = msg_table.get(ID_KEY) {
+        if *i > 0 && *i as usize <= MAX_ID {
+            id = *i as usize;
+        } else {
+            errs.push(
+                diag(locator, &[class, msg_name, ID_KEY], "invalid id")
+                    .with_value(i)
+                    .with_hint(format!(
+                        "ids shall be in 1..={} (0 is reserved to the UID message)",
+                        MAX_ID
+                    )),
+            );
+        }
+    }
+
+    let mut fields = vec![];
+    for (field_name, typ) in ordered(msg_table, options) {
+        let is_text = (field_name == DOC_KEY || field_name == UNIT_KEY) && typ.is_str();
+        if (field_name == ID_KEY && typ.is_integer()) || is_text {
+            continue;
+        }
+        if let Some(field) = parse_field(&[class, msg_name, field_name], typ, scope, locator, errs)
+        {
+            fields.push(field);
+        }
+    }
+
+    // The length of a variable length field is generated as `<name>_len`.
+    for var in fields.iter().filter(|f| f.t.is_variable()) {
+        let len_name = format!("{}_len", var.name);
+        if fields.iter().any(|f| f.name == len_name) {
+            errs.push(
+                diag(
+                    locator,
+                    &[class, msg_name, &len_name],
+                    format!("clashes with the length of {}", var.name),
+                )
+                .with_hint(format!(
+                    "the length of {name} is generated as {len}, rename one of them",
+                    name = var.name,
+                    len = len_name
+                )),
+            );
+        }
+    }
+
+    if fields.is_empty() && errs.len() == nb_errs {
+        errs.push(
+            diag(locator, path, "message has no field")
+                .with_hint("a message shall have at least one field, e.g. x = \"i16\""),
+        );
+    }
+
+    let mut text = |key| {
+        let text = msg_table.get(key).and_then(Value::as_str)?;
+        if Type::from_string(text, scope).is_ok() {
+            errs.push(
+                diag(
+                    locator,
+                    &[class, msg_name, key],
+                    format!("ambiguous {}", key),
+                )
+                .with_value(format!("{:?}", text))
+                .with_hint(format!(
+                    "\"{text}\" is a type: declare a field named {key} as a table, \
+                         e.g. {key} = {{type=\"{text}\"}}, or reword the {key}",
+                    text = text,
+                    key = key
+                )),
+            );
+            return None;
+        }
+        Some(text.to_string())
+    };
+    let doc = text(DOC_KEY);
+    let unit = text(UNIT_KEY);
+    let msg = MsgSpec {
+        name,
+        class: class.to_string(),
+        direction: Direction::from_class(class),
+        id,
+        fields,
+        doc,
+        unit,
+    };
+    if errs.len() == nb_errs && msg.get_payload_size() > MAX_PAYLOAD_SIZE {
+        errs.push(
+            diag(locator, path, "message too large")
+                .with_value(msg.get_payload_size())
+                .with_hint(format!(
+                    "the payload of a message shall be at most {} bytes, variable length \
+                     fields counting for their maximum length plus one",
+                    MAX_PAYLOAD_SIZE
+                )),
+        );
+    }
+
+    if errs.len() == nb_errs {
+        Some(msg)
     } else {
         None
     }
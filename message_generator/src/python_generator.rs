@@ -1,15 +1,24 @@
+use crate::c_generator::CGenerator;
 use crate::generator::Generator;
-use crate::message::{MsgSpec, Type};
+use crate::message::{
+    BitfieldSpec, DefaultValue, Endianness, EnumSpec, Field, MsgSpec, Schema, StructSpec, Type,
+};
+use inflector::Inflector;
 
 pub struct PythonGenerator;
 
 impl PythonGenerator {
-    const HEADER: &'static str = "from duckmsg import DuckMsg, clamp\n\
+    const HEADER: &'static str = "from duckmsg import DuckMsg, clamp, quantize\n\
+                                  from enum import IntEnum\n\
                                   import bitstring";
 
-    fn declare_class(msg: &MsgSpec) -> String {
+    fn declare_class(msg: &MsgSpec, schema: &Schema) -> String {
         let msg_id = format!("\tID = {}", msg.id);
-        let msg_size = format!("\tSIZE = {}", msg.get_buffer_size());
+        let msg_size = format!(
+            "\tSIZE = {}\n\tMIN_SIZE = {}",
+            msg.get_buffer_size(),
+            msg.get_min_buffer_size()
+        );
 
         let declarations = msg
             .fields
@@ -17,7 +26,11 @@ impl PythonGenerator {
             .map(|field| {
                 format!(
                     "\t\t{}",
-                    PythonGenerator::init_variable(field.name.as_ref(), &field.t)
+                    PythonGenerator::init_variable(
+                        field.name.as_ref(),
+                        &field.t,
+                        field.default.as_ref()
+                    )
                 )
             })
             .collect::<Vec<String>>()
@@ -26,44 +39,191 @@ impl PythonGenerator {
         let getters = msg
             .fields
             .iter()
-            .map(|field| PythonGenerator::make_get_set(&field.name, &field.t))
+            .map(PythonGenerator::make_get_set)
             .collect::<Vec<String>>()
             .join("\n\n");
 
-        let serialize = PythonGenerator::serialize(msg);
-        let deserialize = PythonGenerator::deserialize(msg);
+        let mut coders = String::new();
+        if schema.encodes(msg) {
+            coders += &(PythonGenerator::serialize(msg, schema.endianness) + "\n\n");
+        }
+        if schema.decodes(msg) {
+            coders += &(PythonGenerator::deserialize(msg, schema.endianness) + "\n\n");
+        }
 
         let repr = PythonGenerator::repr(msg);
 
         let code = format!(
-            "class {name}(DuckMsg):\n{id}\n{size}\n\tdef __init__(self):\n{dec}\n\n{serialize}\n\n{deserialize}\n\n{repr}\n\n{gets}",
-            name=msg.name, id=msg_id, size=msg_size, dec=declarations, serialize=serialize, deserialize=deserialize, repr=repr, gets=getters
+            "class {name}(DuckMsg):\n{doc}{id}\n{size}\n\tdef __init__(self):\n\t\tself.reset()\n\n\tdef reset(self):\n{dec}\n\n{coders}{repr}\n\n{gets}",
+            name=msg.name, doc=msg.description().map_or(String::new(), |doc| PythonGenerator::docstring(&doc, "\t")), id=msg_id, size=msg_size, dec=declarations, coders=coders, repr=repr, gets=getters
         );
 
         code
     }
 
-    fn init_variable(name: &str, ty: &Type) -> String {
+    fn init_variable(name: &str, ty: &Type, default: Option<&DefaultValue>) -> String {
+        let value = match default {
+            Some(value) => PythonGenerator::literal(value, ty),
+            None => PythonGenerator::zero(ty),
+        };
+        format!("self._{} = {}", name, value)
+    }
+
+    /// Expression of the default `value` of a field of type `ty`.
+    fn literal(value: &DefaultValue, ty: &Type) -> String {
+        match (value, ty) {
+            (DefaultValue::Int(v), Type::Bitfield(bf)) => format!("{}({})", bf.name, v),
+            (DefaultValue::Int(v), _) => v.to_string(),
+            (DefaultValue::Bool(v), _) => if *v { "True" } else { "False" }.to_string(),
+            (DefaultValue::Float(v), _) => format!("{:?}", v),
+            (DefaultValue::Chars(v), _) => PythonGenerator::bytes_literal(v),
+            (DefaultValue::Variant(v), Type::Enum(e)) => {
+                format!("{}.{}", e.name, v.to_screaming_snake_case())
+            }
+            // Each bitfield is an instance of its own.
+            (DefaultValue::List(values), Type::Array(elt, len))
+                if *len > 1
+                    && values.iter().all(|v| *v == values[0])
+                    && !matches!(**elt, Type::Bitfield(_)) =>
+            {
+                format!("[{}] * {}", PythonGenerator::literal(&values[0], elt), len)
+            }
+            (DefaultValue::List(values), Type::Array(elt, _) | Type::VarArray(elt, _)) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|v| PythonGenerator::literal(v, elt))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Docstring of `doc`, on lines of their own indented by `indent`.
+    fn docstring(doc: &str, indent: &str) -> String {
+        let doc = doc.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "{indent}\"\"\"{doc}\"\"\"\n",
+            indent = indent,
+            doc = doc.replace('\n', &format!("\n{}", indent))
+        )
+    }
+
+    /// Bytes literal of `s`, as chars are stored.
+    fn bytes_literal(s: &str) -> String {
+        let escaped = s
+            .bytes()
+            .map(|c| match c {
+                b'\'' | b'\\' => format!("\\{}", c as char),
+                b' '..=b'~' => (c as char).to_string(),
+                _ => format!("\\x{:02x}", c),
+            })
+            .collect::<String>();
+        format!("b'{}'", escaped)
+    }
+
+    fn zero(ty: &Type) -> String {
         match ty {
-            Type::I8(_b) | Type::I16(_b) | Type::I32(_b) => format!("self._{} = 0", name),
-            Type::U8(_b) | Type::U16(_b) | Type::U32(_b) => format!("self._{} = 0", name),
-            Type::F32(_b) => format!("self._{} = 0.0", name),
-            Type::CHARS(_size) => format!("self._{} = b''", name),
+            Type::Bool => "False".to_string(),
+            Type::F32(_b) | Type::F64(_b) => "0.0".to_string(),
+            Type::CHARS(_size) | Type::VarChars(_size) => "b''".to_string(),
+            Type::VarArray(..) => "[]".to_string(),
+            Type::Enum(e) => format!("{}.{}", e.name, e.variants[0].0.to_screaming_snake_case()),
+            Type::Struct(st) => format!("{}()", st.name),
+            Type::Bitfield(bf) => format!("{}()", bf.name),
+            // Each struct or bitfield is an instance of its own.
+            Type::Array(elt, len) if matches!(**elt, Type::Struct(_) | Type::Bitfield(_)) => {
+                format!("[{} for _ in range({})]", PythonGenerator::zero(elt), len)
+            }
+            Type::Array(elt, len) => format!("[{}] * {}", PythonGenerator::zero(elt), len),
+            _ => "0".to_string(),
         }
     }
 
-    fn make_get_set(name: &str, ty: &Type) -> String {
+    /// Value stored by the setter of `name`, clamped to its bounds.
+    fn set_value(name: &str, ty: &Type) -> String {
+        match ty {
+            Type::Bool => format!("bool({})", name),
+            // Raises ValueError for unknown values.
+            Type::Enum(e) => format!("{}({})", e.name, name),
+            Type::I8(b)
+            | Type::I16(b)
+            | Type::I32(b)
+            | Type::I64(b)
+            | Type::U8(b)
+            | Type::U16(b)
+            | Type::U32(b)
+            | Type::U64(b) => format!("clamp({}, {}, {})", b.min, name, b.max),
+            Type::F32(b) | Type::F64(b) => format!("clamp({:?}, {}, {:?})", b.min, name, b.max),
+            Type::Scaled(t, scale) => {
+                format!("quantize({})", CGenerator::quantize_args(name, t, scale))
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// Attribute holding the values sent for `field`. Scaled integers are stored as sent,
+    /// behind their float property.
+    fn attribute(field: &Field) -> String {
+        match field.t.scalar() {
+            Type::Scaled(..) => format!("_{}", field.name),
+            _ => field.name.clone(),
+        }
+    }
+
+    fn make_get_set(field: &Field) -> String {
+        let (name, ty) = (field.name.as_str(), &field.t);
+        let get = match ty {
+            Type::Scaled(_t, scale) => CGenerator::unscale(&format!("self._{}", name), scale),
+            Type::Array(elt, _len) | Type::VarArray(elt, _len) => match &**elt {
+                Type::Scaled(_t, scale) => format!(
+                    "[{} for v in self._{}]",
+                    CGenerator::unscale("v", scale),
+                    name
+                ),
+                _ => format!("self._{}", name),
+            },
+            _ => format!("self._{}", name),
+        };
         let getter = format!(
-            "\t@property\n\tdef {name}(self):\n\t\treturn self._{name}",
-            name = name
+            "\t@property\n\tdef {name}(self):\n{doc}\t\treturn {get}",
+            name = name,
+            doc = field
+                .description()
+                .map_or(String::new(), |doc| PythonGenerator::docstring(
+                    &doc, "\t\t"
+                )),
+            get = get
         );
 
         let setter = match ty {
-            Type::CHARS(_) => format!("\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\tself._{name}={name}", name=name),
-            Type::I8(b)|Type::I16(b)|Type::I32(b)|
-            Type::U8(b)|Type::U16(b)|Type::U32(b)
-                => format!("\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\tself._{name}=clamp({min:.1}, {name}, {max:.1})", name=name, min=b.min, max=b.max),
-            Type::F32(b) => format!("\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\tself._{name}=clamp({min:.1}, {name}, {max:.1})", name=name, min=b.min, max=b.max),
+            Type::Array(elt, len) => format!(
+                "\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\t\
+                if len({name}) != {len}:\n\t\t\t\
+                raise ValueError(\"{name} shall have {len} elements\")\n\t\t\
+                self._{name}=[{value} for v in {name}]",
+                name = name,
+                len = len,
+                value = PythonGenerator::set_value("v", elt)
+            ),
+            Type::VarChars(max_len) => format!(
+                "\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\tself._{name}={name}[:{max_len}]",
+                name = name,
+                max_len = max_len
+            ),
+            Type::VarArray(elt, max_len) => format!(
+                "\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\t\
+                self._{name}=[{value} for v in {name}[:{max_len}]]",
+                name = name,
+                max_len = max_len,
+                value = PythonGenerator::set_value("v", elt)
+            ),
+            _ => format!(
+                "\t@{name}.setter\n\tdef {name}(self, {name}):\n\t\tself._{name}={value}",
+                name = name,
+                value = PythonGenerator::set_value(name, ty)
+            ),
         };
         format!("{}\n\n{}", getter, setter)
     }
@@ -72,7 +232,13 @@ impl PythonGenerator {
         let fields = msg
             .fields
             .iter()
-            .map(|field| format!("'{name} : {{}}'.format(self._{name})", name = field.name))
+            .map(|field| {
+                format!(
+                    "'{name} : {{}}'.format(self.{attribute})",
+                    name = field.name,
+                    attribute = PythonGenerator::repr_attribute(field)
+                )
+            })
             .collect::<Vec<String>>()
             .join(", ");
 
@@ -83,75 +249,449 @@ impl PythonGenerator {
         )
     }
 
-    fn serialize(msg: &MsgSpec) -> String {
-        let fields = msg
-            .fields
+    /// Attribute shown by `__repr__`, the float value for scaled integers.
+    fn repr_attribute(field: &Field) -> String {
+        match field.t.scalar() {
+            Type::Scaled(..) => field.name.clone(),
+            _ => format!("_{}", field.name),
+        }
+    }
+
+    /// bitstring format of `fields`, e.g. `intle:16, floatle:32`.
+    fn bit_format(fields: &[Field], endianness: Endianness) -> String {
+        let e = PythonGenerator::endian(endianness);
+        fields
             .iter()
-            .map(|field| format!("self.{}", field.name))
+            .map(|field| PythonGenerator::type_format(&field.t, e))
             .collect::<Vec<String>>()
-            .join(", ");
+            .join(", ")
+    }
 
-        let bit_format = msg
-            .fields
+    fn endian(endianness: Endianness) -> &'static str {
+        match endianness {
+            Endianness::Little => "le",
+            Endianness::Big => "be",
+        }
+    }
+
+    /// Runs of fixed size fields, each variable length field being a run of its own.
+    fn runs(msg: &MsgSpec) -> Vec<&[Field]> {
+        let mut runs = vec![];
+        let mut start = 0;
+        for (i, field) in msg.fields.iter().enumerate() {
+            if field.t.is_variable() {
+                if start < i {
+                    runs.push(&msg.fields[start..i]);
+                }
+                runs.push(&msg.fields[i..=i]);
+                start = i + 1;
+            }
+        }
+        if start < msg.fields.len() {
+            runs.push(&msg.fields[start..]);
+        }
+        runs
+    }
+
+    fn type_format(ty: &Type, e: &str) -> String {
+        match ty {
+            // bitstring bools are a single bit, they are sent as a whole byte.
+            Type::Bool => format!("uint{}:8", e),
+            Type::I8(_) => format!("int{}:8", e),
+            Type::I16(_) => format!("int{}:16", e),
+            Type::I32(_) => format!("int{}:32", e),
+            Type::I64(_) => format!("int{}:64", e),
+            Type::U8(_) => format!("uint{}:8", e),
+            Type::U16(_) => format!("uint{}:16", e),
+            Type::U32(_) => format!("uint{}:32", e),
+            Type::U64(_) => format!("uint{}:64", e),
+            Type::F32(_) => format!("float{}:32", e),
+            Type::F64(_) => format!("float{}:64", e),
+            Type::Enum(en) => PythonGenerator::type_format(&en.repr, e),
+            Type::Bitfield(bf) => PythonGenerator::type_format(&bf.repr, e),
+            Type::Scaled(t, _scale) => PythonGenerator::type_format(t, e),
+            Type::CHARS(s) => format!("bytes:{}", s),
+            Type::Struct(st) => st
+                .fields
+                .iter()
+                .map(|field| PythonGenerator::type_format(&field.t, e))
+                .collect::<Vec<String>>()
+                .join(", "),
+            Type::Array(elt, len) if matches!(**elt, Type::Struct(_)) => {
+                vec![PythonGenerator::type_format(elt, e); *len].join(", ")
+            }
+            Type::Array(elt, len) => format!("{}*{}", len, PythonGenerator::type_format(elt, e)),
+            // Variable length fields are packed element by element.
+            Type::VarChars(_) | Type::VarArray(..) => unreachable!(),
+        }
+    }
+
+    /// Values of `fields` to pack, arrays and structs being given element by element,
+    /// and bitfields as integers.
+    fn pack_values(fields: &[Field]) -> String {
+        fields
+            .iter()
+            .map(|field| match &field.t {
+                Type::Array(elt, _) if matches!(**elt, Type::Struct(_)) => {
+                    format!("*[v for s in self.{} for v in s.values()]", field.name)
+                }
+                Type::Array(elt, _) if matches!(**elt, Type::Bitfield(_)) => {
+                    format!("*[v.bits for v in self.{}]", field.name)
+                }
+                Type::Array(..) => format!("*self.{}", PythonGenerator::attribute(field)),
+                Type::Struct(_) => format!("*self.{}.values()", field.name),
+                Type::Bitfield(_) => format!("self.{}.bits", field.name),
+                _ => format!("self.{}", PythonGenerator::attribute(field)),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Number of values `ty` is packed as.
+    fn value_count(ty: &Type) -> usize {
+        match ty {
+            Type::Array(elt, len) => len * PythonGenerator::value_count(elt),
+            Type::Struct(st) => st
+                .fields
+                .iter()
+                .map(|field| PythonGenerator::value_count(&field.t))
+                .sum(),
+            _ => 1,
+        }
+    }
+
+    fn serialize(msg: &MsgSpec, endianness: Endianness) -> String {
+        if !msg.is_variable() {
+            let fields = PythonGenerator::pack_values(&msg.fields);
+            let bit_format = PythonGenerator::bit_format(&msg.fields, endianness);
+
+            return format!("\tdef serialize(self):\n\t\t\
+                            return bitstring.pack('uintle:8, uintle:8, {bit_format}', self.ID, self.SIZE-4, {fields})",
+                        fields=fields, bit_format=bit_format);
+        }
+
+        // The length byte depends on the content, so the payload is packed first.
+        let e = PythonGenerator::endian(endianness);
+        let packs = PythonGenerator::runs(msg)
+            .iter()
+            .map(|run| match &run[0].t {
+                Type::VarChars(_) => format!(
+                    "\t\tpayload += bitstring.pack('uint:8', len(self.{name}))\n\t\t\
+                     payload += bitstring.BitStream(bytes=self.{name})",
+                    name = run[0].name
+                ),
+                Type::VarArray(elt, _max_len) => format!(
+                    "\t\tpayload += bitstring.pack('uint:8', len(self.{name}))\n\t\t\
+                     for v in self.{name}:\n\t\t\t\
+                     payload += bitstring.pack('{format}', {value})",
+                    name = PythonGenerator::attribute(&run[0]),
+                    format = PythonGenerator::type_format(elt, e),
+                    value = match **elt {
+                        Type::Struct(_) => "*v.values()",
+                        Type::Bitfield(_) => "v.bits",
+                        _ => "v",
+                    }
+                ),
+                _ => format!(
+                    "\t\tpayload += bitstring.pack('{}', {})",
+                    PythonGenerator::bit_format(run, endianness),
+                    PythonGenerator::pack_values(run)
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "\tdef serialize(self):\n\t\t\
+             payload = bitstring.BitStream()\n\
+             {packs}\n\t\t\
+             return bitstring.pack('uintle:8, uintle:8', self.ID, len(payload) // 8 + 2) + payload",
+            packs = packs
+        )
+    }
+
+    /// Assignments of the fields of `obj` from `values`, returned by `unpack` or `readlist`.
+    fn assign_values(fields: &[Field], obj: &str) -> String {
+        // unpack returns the elements of arrays and structs one by one, they are sliced back together.
+        let mut index = 0;
+        fields
             .iter()
             .map(|field| {
-                let bitype = match field.t {
-                    Type::I8(_) => "intle:8".to_string(),
-                    Type::I16(_) => "intle:16".to_string(),
-                    Type::I32(_) => "intle:32".to_string(),
-                    Type::U8(_) => "uintle:8".to_string(),
-                    Type::U16(_) => "uintle:16".to_string(),
-                    Type::U32(_) => "uintle:32".to_string(),
-                    Type::F32(_) => "floatle:32".to_string(),
-                    Type::CHARS(s) => format!("bytes:{}", s),
+                let count = PythonGenerator::value_count(&field.t);
+                let value = match &field.t {
+                    Type::Array(elt, len) => match &**elt {
+                        Type::Struct(st) => {
+                            let n = count / len;
+                            format!(
+                                "[{name}.from_values(values[{index}+{n}*i:{index}+{n}*(i+1)]) for i in range({len})]",
+                                name = st.name,
+                                index = index,
+                                n = n,
+                                len = len
+                            )
+                        }
+                        Type::Bitfield(bf) => format!(
+                            "[{}(v) for v in values[{}:{}]]",
+                            bf.name,
+                            index,
+                            index + count
+                        ),
+                        _ => format!("values[{}:{}]", index, index + count),
+                    },
+                    Type::Bitfield(bf) => format!("{}(values[{}])", bf.name, index),
+                    Type::Struct(st) => format!(
+                        "{}.from_values(values[{}:{}])",
+                        st.name,
+                        index,
+                        index + count
+                    ),
+                    _ => format!("values[{}]", index),
                 };
-                format!("{}", bitype)
+                index += count;
+                format!(
+                    "\t\t{}.{} = {}",
+                    obj,
+                    PythonGenerator::attribute(field),
+                    value
+                )
             })
             .collect::<Vec<String>>()
-            .join(", ");
+            .join("\n")
+    }
+
+    fn deserialize(msg: &MsgSpec, endianness: Endianness) -> String {
+        if !msg.is_variable() {
+            return format!(
+                "\tdef deserialize(self, bytes):\n\t\t\
+                 s = bitstring.BitStream(bytes)\n\t\t\
+                 values = s.unpack('{bit_format}')\n\
+                 {assignments}",
+                assignments = PythonGenerator::assign_values(&msg.fields, "self"),
+                bit_format = PythonGenerator::bit_format(&msg.fields, endianness)
+            );
+        }
 
-        format!("\tdef serialize(self):\n\t\t\
-                        return bitstring.pack('uintle:8, uintle:8, {bit_format}', self.ID, self.SIZE-4, {fields})",
-                    fields=fields, bit_format=bit_format)
+        let e = PythonGenerator::endian(endianness);
+        let reads = PythonGenerator::runs(msg)
+            .iter()
+            .map(|run| match &run[0].t {
+                Type::VarChars(_) => format!(
+                    "{check}\n\t\t\
+                     self.{name} = s.read(8 * n).bytes",
+                    check = PythonGenerator::check_var_len(msg, &run[0]),
+                    name = run[0].name
+                ),
+                Type::VarArray(elt, _max_len) => format!(
+                    "{check}\n\t\t\
+                     self.{name} = [{read} for _ in range(n)]",
+                    check = PythonGenerator::check_var_len(msg, &run[0]),
+                    name = PythonGenerator::attribute(&run[0]),
+                    read = match &**elt {
+                        Type::Struct(st) => format!(
+                            "{}.from_values(s.readlist('{}'))",
+                            st.name,
+                            PythonGenerator::type_format(elt, e)
+                        ),
+                        Type::Bitfield(bf) => format!(
+                            "{}(s.read('{}'))",
+                            bf.name,
+                            PythonGenerator::type_format(elt, e)
+                        ),
+                        _ => format!("s.read('{}')", PythonGenerator::type_format(elt, e)),
+                    }
+                ),
+                _ => format!(
+                    "\t\tvalues = s.readlist('{}')\n{}",
+                    PythonGenerator::bit_format(run, endianness),
+                    PythonGenerator::assign_values(run, "self")
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        // The payload still ends with the 2 checksum bytes.
+        format!(
+            "\tdef deserialize(self, bytes):\n\t\t\
+             s = bitstring.BitStream(bytes)\n\
+             {reads}\n\t\t\
+             if s.bytepos != len(bytes) - 2:\n\t\t\t\
+             raise ValueError(\"bad payload length\")",
+            reads = reads
+        )
     }
 
-    fn deserialize(msg: &MsgSpec) -> String {
-        let fields = msg
+    /// Code reading the length byte `n` of the variable length `field` of `msg`, raising
+    /// ValueError if it is above the maximal length or if the elements, and the `rest` bytes
+    /// of the following fields, don't fit before the checksum, as the Rust decoder does.
+    fn check_var_len(msg: &MsgSpec, field: &Field) -> String {
+        let (max_len, elt_size) = match &field.t {
+            Type::VarChars(max_len) => (max_len, 1),
+            Type::VarArray(elt, max_len) => (max_len, elt.get_size()),
+            _ => unreachable!(),
+        };
+        let mut end = "s.bytepos + n".to_string();
+        if elt_size > 1 {
+            end += &format!(" * {}", elt_size);
+        }
+        let rest: usize = msg
             .fields
             .iter()
-            .map(|field| format!("self.{}", field.name))
+            .skip_while(|f| f.name != field.name)
+            .skip(1)
+            .map(|f| f.t.get_min_size())
+            .sum();
+        if rest > 0 {
+            end += &format!(" + {}", rest);
+        }
+        format!(
+            "\t\tn = s.read('uint:8')\n\t\t\
+             if n > {max_len} or {end} > len(bytes) - 2:\n\t\t\t\
+             raise ValueError(\"bad length of {name}\")",
+            max_len = max_len,
+            end = end,
+            name = field.name
+        )
+    }
+
+    fn declare_enum(e: &EnumSpec) -> String {
+        let values = e
+            .variants
+            .iter()
+            .map(|(name, value)| format!("\t{} = {}", name.to_screaming_snake_case(), value))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!("class {}(IntEnum):\n{}", e.name, values)
+    }
+
+    /// Class wrapping the bits of a bitfield, with a property for each bit range.
+    fn declare_bitfield(bf: &BitfieldSpec) -> String {
+        let nb_bits = 8 * bf.repr.get_size() as u32;
+        let properties = bf
+            .ranges
+            .iter()
+            .map(|range| {
+                let (get, value) = if range.width == 1 {
+                    (
+                        format!("self.bits & 0x{:X} != 0", range.mask()),
+                        format!("(1 if {} else 0)", range.name),
+                    )
+                } else if range.width < nb_bits {
+                    (
+                        format!("self.bits >> {} & 0x{:X}", range.start, range.max()),
+                        format!("clamp(0, {}, {})", range.name, range.max()),
+                    )
+                } else {
+                    (
+                        format!("self.bits >> {} & 0x{:X}", range.start, range.max()),
+                        range.name.clone(),
+                    )
+                };
+                format!(
+                    "\t@property\n\tdef {name}(self):\n\t\treturn {get}\n\n\t\
+                     @{name}.setter\n\tdef {name}(self, {name}):\n\t\t\
+                     self.bits = self.bits & ~0x{mask:X} | {value} << {start}",
+                    name = range.name,
+                    get = get,
+                    mask = range.mask(),
+                    value = value,
+                    start = range.start
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let fields = bf
+            .ranges
+            .iter()
+            .map(|range| format!("{}={{}}", range.name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let values = bf
+            .ranges
+            .iter()
+            .map(|range| format!("self.{}", range.name))
             .collect::<Vec<String>>()
             .join(", ");
 
-        let bit_format = msg
+        format!(
+            "class {name}:\n\t\
+             def __init__(self, bits=0):\n\t\t\
+             self.bits = bits\n\n\t\
+             def __eq__(self, other):\n\t\t\
+             return isinstance(other, {name}) and self.bits == other.bits\n\n\t\
+             def __repr__(self):\n\t\t\
+             return '{name}({fields})'.format({values})\n\n\
+             {properties}",
+            name = bf.name,
+            fields = fields,
+            values = values,
+            properties = properties
+        )
+    }
+
+    /// Class of a [types] entry, that messages pack with `values()` and unpack with `from_values()`.
+    fn declare_struct(st: &StructSpec) -> String {
+        let declarations = st
             .fields
             .iter()
             .map(|field| {
-                let bitype = match field.t {
-                    Type::I8(_) => "intle:8".to_string(),
-                    Type::I16(_) => "intle:16".to_string(),
-                    Type::I32(_) => "intle:32".to_string(),
-                    Type::U8(_) => "uintle:8".to_string(),
-                    Type::U16(_) => "uintle:16".to_string(),
-                    Type::U32(_) => "uintle:32".to_string(),
-                    Type::F32(_) => "floatle:32".to_string(),
-                    Type::CHARS(s) => format!("bytes:{}", s),
-                };
-                format!("{}", bitype)
+                format!(
+                    "\t\t{}",
+                    PythonGenerator::init_variable(&field.name, &field.t, field.default.as_ref())
+                )
             })
             .collect::<Vec<String>>()
+            .join("\n");
+
+        let getters = st
+            .fields
+            .iter()
+            .map(PythonGenerator::make_get_set)
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let fields = st
+            .fields
+            .iter()
+            .map(|field| format!("{name}={{}}", name = field.name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let values = st
+            .fields
+            .iter()
+            .map(|field| format!("self.{}", PythonGenerator::repr_attribute(field)))
+            .collect::<Vec<String>>()
             .join(", ");
 
         format!(
-            "\tdef deserialize(self, bytes):\n\t\t\
-             s = bitstring.BitStream(bytes)\n\t\t\
-             {fields}, = s.unpack('{bit_format}')",
+            "class {name}:\n\t\
+             def __init__(self):\n\
+             {dec}\n\n\t\
+             def values(self):\n\t\t\
+             return [{pack}]\n\n\t\
+             @classmethod\n\t\
+             def from_values(cls, values):\n\t\t\
+             obj = cls()\n\
+             {assignments}\n\t\t\
+             return obj\n\n\t\
+             def __eq__(self, other):\n\t\t\
+             return isinstance(other, {name}) and self.values() == other.values()\n\n\t\
+             def __repr__(self):\n\t\t\
+             return '{name}({fields})'.format({values})\n\n\
+             {gets}",
+            name = st.name,
+            dec = declarations,
+            pack = PythonGenerator::pack_values(&st.fields),
+            assignments = PythonGenerator::assign_values(&st.fields, "obj"),
             fields = fields,
-            bit_format = bit_format
+            values = values,
+            gets = getters
         )
     }
 
-    fn message_dict(messages: &Vec<MsgSpec>) -> String {
+    /// Messages this side receives, by id.
+    fn message_dict(messages: &[&MsgSpec]) -> String {
         let body = messages
             .iter()
             .map(|msg| format!("\t{id} : {name},", id = msg.id, name = msg.name))
@@ -160,26 +700,89 @@ impl PythonGenerator {
 
         format!("MESSAGES = {{\n{}\n}}", body)
     }
+
+    fn dispatcher(messages: &[&MsgSpec]) -> String {
+        let decorators = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "\tdef on_{sname}(self, handler):\n\t\t\
+                     return self.on({name})(handler)",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        format!(
+            "class Dispatcher:\n\t\
+             def __init__(self):\n\t\t\
+             self._handlers = {{}}\n\n\t\
+             def on(self, msg_class):\n\t\t\
+             \"\"\"Decorator registering the decorated function as a handler of msg_class messages.\"\"\"\n\t\t\
+             def register(handler):\n\t\t\t\
+             self._handlers.setdefault(msg_class.ID, []).append(handler)\n\t\t\t\
+             return handler\n\t\t\
+             return register\n\n\
+             {}\n\n\t\
+             def dispatch(self, msg):\n\t\t\
+             \"\"\"Call the handlers registered for the type of msg.\"\"\"\n\t\t\
+             for handler in self._handlers.get(msg.ID, []):\n\t\t\t\
+             handler(msg)",
+            decorators
+        )
+    }
 }
 
 impl Generator for PythonGenerator {
-    fn generate_messages(messages: &Vec<MsgSpec>, uid: u32) -> Vec<(String, String)> {
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        let messages = &schema.messages;
+        let received = schema.received();
         let classes = messages
             .iter()
-            .map(|msg| PythonGenerator::declare_class(msg))
+            .map(|msg| PythonGenerator::declare_class(msg, schema))
             .collect::<Vec<String>>()
             .join("\n\n");
 
-        let dict = PythonGenerator::message_dict(messages);
+        let enums = schema
+            .enums
+            .iter()
+            .map(|e| PythonGenerator::declare_enum(e) + "\n\n")
+            .chain(
+                schema
+                    .bitfields
+                    .iter()
+                    .map(|bf| PythonGenerator::declare_bitfield(bf) + "\n\n"),
+            )
+            .chain(
+                schema
+                    .structs
+                    .iter()
+                    .map(|st| PythonGenerator::declare_struct(st) + "\n\n"),
+            )
+            .collect::<String>();
+
+        let dict = PythonGenerator::message_dict(&received);
 
-        let uid_code = format!("UID = {}", uid);
+        let uid_code = std::iter::once(format!("UID = {}\n", uid))
+            .chain(
+                schema
+                    .constants
+                    .iter()
+                    .map(|c| format!("{} = {}", c.name, PythonGenerator::literal(&c.value, &c.t))),
+            )
+            .collect::<Vec<String>>()
+            .join("\n");
 
         let code = format!(
-            "{}\n\n{}\n\n{}\n\n{}\n",
+            "{}\n\n{}\n\n{}{}\n\n{}\n\n{}\n",
             PythonGenerator::HEADER,
             uid_code,
+            enums,
             classes,
-            dict
+            dict,
+            PythonGenerator::dispatcher(&received)
         );
 
         vec![("messages.py".to_string(), code)]
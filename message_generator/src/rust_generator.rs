@@ -0,0 +1,1193 @@
+use crate::c_generator::CGenerator;
+use crate::generator::Generator;
+use crate::message::{
+    BitfieldSpec, ConstSpec, DefaultValue, Endianness, EnumSpec, Field, MsgSpec, Schema,
+    StructSpec, Type,
+};
+use inflector::Inflector;
+
+pub struct RustGenerator;
+
+impl RustGenerator {
+    const CARGO_TOML: &'static str = "[package]\n\
+                                      name = \"ducklink_messages\"\n\
+                                      version = \"0.1.0\"\n\
+                                      edition = \"2018\"\n\n\
+                                      [lib]\n\
+                                      path = \"lib.rs\"\n";
+
+    /// Crate root. The messages are in their own file so that they can also be
+    /// `include!`d, e.g. from a `build.rs` output directory.
+    const LIB_RS: &'static str =
+        "//! Ducklink messages, generated by the Ducklink message generator. Do not edit.\n\
+         #![no_std]\n\n\
+         mod messages;\n\n\
+         pub use messages::*;\n";
+
+    const HEADER: &'static str =
+        "// Ducklink messages, generated by the Ducklink message generator. Do not edit.\n\n\
+         use core::convert::TryInto;";
+
+    const CLAMP: &'static str = "fn clamp<T: PartialOrd>(lo: T, v: T, hi: T) -> T {\n    \
+                                 if v < lo {\n        \
+                                 lo\n    \
+                                 } else if hi < v {\n        \
+                                 hi\n    \
+                                 } else {\n        \
+                                 v\n    \
+                                 }\n\
+                                 }";
+
+    /// See `CGenerator::QUANTIZE`.
+    const QUANTIZE: &'static str =
+        "/// Integer nearest to `(v - offset) / scale`, within `[lo, hi]`.\n\
+         fn quantize(v: f64, scale: f64, offset: f64, lo: f64, hi: f64) -> f64 {\n    \
+         let mut x = (v - offset) / scale + 0.5;\n    \
+         x = if x >= lo { x } else { lo }; // also catches NaN\n    \
+         x = if x <= hi { x } else { hi };\n    \
+         let t = x as i64 as f64; // floor, without std\n    \
+         if t > x {\n        \
+         t - 1.0\n    \
+         } else {\n        \
+         t\n    \
+         }\n\
+         }";
+
+    const CHECKSUM: &'static str =
+        "/// Fletcher-16 checksum over the message id, length and payload.\n\
+         pub fn compute_checksum(buffer: &[u8]) -> u16 {\n    \
+         let mut ck_a: u8 = 0;\n    \
+         let mut ck_b: u8 = 0;\n    \
+         for b in buffer {\n        \
+         ck_a = ck_a.wrapping_add(*b);\n        \
+         ck_b = ck_b.wrapping_add(ck_a);\n    \
+         }\n    \
+         (u16::from(ck_a) << 8) | u16::from(ck_b)\n\
+         }";
+
+    const ERROR: &'static str = "#[derive(Debug, Clone, Copy, PartialEq)]\n\
+                                 pub enum Error {\n    \
+                                 /// The buffer is too small to hold the message.\n    \
+                                 BufferTooSmall,\n    \
+                                 /// No message has this id.\n    \
+                                 UnknownId(u8),\n    \
+                                 /// The payload length doesn't match the message size.\n    \
+                                 BadLength,\n    \
+                                 /// A decoded value is out of range, e.g. an unknown enum value.\n    \
+                                 InvalidValue,\n    \
+                                 /// The message with this id is only sent the other way,\n    \
+                                 /// the messages being generated for one side of the link.\n    \
+                                 WrongDirection(u8),\n\
+                                 }";
+
+    fn get_type(ty: &Type) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::I8(_b) => "i8".to_string(),
+            Type::I16(_b) => "i16".to_string(),
+            Type::I32(_b) => "i32".to_string(),
+            Type::I64(_b) => "i64".to_string(),
+            Type::U8(_b) => "u8".to_string(),
+            Type::U16(_b) => "u16".to_string(),
+            Type::U32(_b) => "u32".to_string(),
+            Type::U64(_b) => "u64".to_string(),
+            Type::F32(_b) => "f32".to_string(),
+            Type::F64(_b) => "f64".to_string(),
+            Type::CHARS(size) => format!("[u8; {}]", size),
+            Type::Array(elt, len) => format!("[{}; {}]", RustGenerator::get_type(elt), len),
+            Type::VarChars(max_len) => format!("[u8; {}]", max_len),
+            Type::VarArray(elt, max_len) => {
+                format!("[{}; {}]", RustGenerator::get_type(elt), max_len)
+            }
+            Type::Enum(e) => e.name.clone(),
+            Type::Struct(st) => st.name.clone(),
+            Type::Bitfield(bf) => bf.name.clone(),
+            Type::Scaled(t, _scale) => RustGenerator::get_type(t),
+        }
+    }
+
+    /// Type taken and returned by the accessors of `ty`, f64 for scaled integers.
+    fn value_type(ty: &Type) -> String {
+        match ty {
+            Type::Scaled(..) => "f64".to_string(),
+            Type::Array(elt, len) => format!("[{}; {}]", RustGenerator::value_type(elt), len),
+            _ => RustGenerator::get_type(ty),
+        }
+    }
+
+    /// Variable length fields are stored with their length in `<name>_len`.
+    fn declare_variable(name: &str, ty: &Type) -> String {
+        let var = format!("    {}: {},", name, RustGenerator::get_type(ty));
+        if ty.is_variable() {
+            format!("    {}_len: u8,\n{}", name, var)
+        } else {
+            var
+        }
+    }
+
+    fn init_variable(name: &str, ty: &Type, default: Option<&DefaultValue>) -> String {
+        let value = match default {
+            Some(value) => RustGenerator::literal(value, ty),
+            None => RustGenerator::zero(ty),
+        };
+        let init = format!("            {}: {},", name, value);
+        if ty.is_variable() {
+            let len = match default {
+                Some(DefaultValue::Chars(v)) => v.len(),
+                Some(DefaultValue::List(values)) => values.len(),
+                _ => 0,
+            };
+            format!("            {}_len: {},\n{}", name, len, init)
+        } else {
+            init
+        }
+    }
+
+    /// Constants of the schema, preceded by a blank line if there are any.
+    fn declare_constants(constants: &[ConstSpec]) -> String {
+        constants
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let separator = if i == 0 { "\n\n" } else { "\n" };
+                match (&c.t, &c.value) {
+                    (Type::CHARS(_), DefaultValue::Chars(v)) => format!(
+                        "{}pub const {}: &[u8] = {};",
+                        separator,
+                        c.name,
+                        RustGenerator::byte_string(v)
+                    ),
+                    _ => format!(
+                        "{}pub const {}: {} = {};",
+                        separator,
+                        c.name,
+                        RustGenerator::get_type(&c.t),
+                        RustGenerator::literal(&c.value, &c.t)
+                    ),
+                }
+            })
+            .collect::<String>()
+    }
+
+    fn byte_string(s: &str) -> String {
+        let escaped = s
+            .bytes()
+            .flat_map(std::ascii::escape_default)
+            .map(char::from)
+            .collect::<String>();
+        format!("b\"{}\"", escaped)
+    }
+
+    /// Expression of the default `value` of a field of type `ty`. Chars and variable length
+    /// arrays are padded with zeros up to their size.
+    fn literal(value: &DefaultValue, ty: &Type) -> String {
+        match (value, ty) {
+            (DefaultValue::Int(v), Type::Bitfield(bf)) => format!("{}({})", bf.name, v),
+            (DefaultValue::Int(v), _) => v.to_string(),
+            (DefaultValue::Bool(v), _) => v.to_string(),
+            (DefaultValue::Float(v), _) => format!("{:?}", v),
+            (DefaultValue::Chars(v), Type::CHARS(size) | Type::VarChars(size)) => format!(
+                "{{\n                \
+                 let mut v = [0; {size}];\n                \
+                 v[..{len}].copy_from_slice({bytes});\n                \
+                 v\n            \
+                 }}",
+                size = size,
+                len = v.len(),
+                bytes = RustGenerator::byte_string(v)
+            ),
+            (DefaultValue::Variant(v), Type::Enum(e)) => {
+                format!("{}::{}", e.name, v.to_pascal_case())
+            }
+            (DefaultValue::List(values), Type::Array(elt, len))
+                if *len > 1 && values.iter().all(|v| *v == values[0]) =>
+            {
+                format!("[{}; {}]", RustGenerator::literal(&values[0], elt), len)
+            }
+            (DefaultValue::List(values), Type::Array(elt, _len)) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|v| RustGenerator::literal(v, elt))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            (DefaultValue::List(values), Type::VarArray(elt, _max_len)) => format!(
+                "{{\n                \
+                 let mut v = {zero};\n                \
+                 v[..{len}].copy_from_slice(&[{values}]);\n                \
+                 v\n            \
+                 }}",
+                zero = RustGenerator::zero(ty),
+                len = values.len(),
+                values = values
+                    .iter()
+                    .map(|v| RustGenerator::literal(v, elt))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    fn zero(ty: &Type) -> String {
+        match ty {
+            Type::CHARS(size) | Type::VarChars(size) => format!("[0; {}]", size),
+            Type::Array(elt, len) | Type::VarArray(elt, len) => {
+                format!("[{}; {}]", RustGenerator::zero(elt), len)
+            }
+            Type::Bool => "false".to_string(),
+            Type::F32(_b) | Type::F64(_b) => "0.0".to_string(),
+            Type::Enum(e) => format!("{}::{}", e.name, e.variants[0].0.to_pascal_case()),
+            Type::Struct(st) => format!("{}::new()", st.name),
+            Type::Bitfield(bf) => format!("{}(0)", bf.name),
+            _ => "0".to_string(),
+        }
+    }
+
+    /// Value stored by the setter of `name`, clamped to its bounds.
+    fn set_value(name: &str, ty: &Type) -> String {
+        match ty {
+            Type::I8(b)
+            | Type::I16(b)
+            | Type::I32(b)
+            | Type::I64(b)
+            | Type::U8(b)
+            | Type::U16(b)
+            | Type::U32(b)
+            | Type::U64(b) => format!("clamp({}, {}, {})", b.min, name, b.max),
+            Type::F32(b) | Type::F64(b) => format!("clamp({:?}, {}, {:?})", b.min, name, b.max),
+            Type::Struct(_) => format!("{}.clamped()", name),
+            Type::Scaled(t, scale) => format!(
+                "quantize({}) as {}",
+                CGenerator::quantize_args(name, t, scale),
+                RustGenerator::get_type(t)
+            ),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Float value of the scaled integer `raw`.
+    fn unscale(raw: &str, ty: &Type) -> String {
+        match ty {
+            Type::Scaled(_t, scale) => CGenerator::unscale(&format!("f64::from({})", raw), scale),
+            _ => unreachable!(),
+        }
+    }
+
+    /// `///` comment of `doc`, each line indented by `indent`.
+    fn doc_comment(doc: &str, indent: &str) -> String {
+        doc.lines()
+            .map(|line| format!("{}/// {}", indent, line).trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// Doc comment of the doc and unit of a field, if it has any.
+    fn field_doc(field: &Field) -> String {
+        field.description().map_or(String::new(), |doc| {
+            RustGenerator::doc_comment(&doc, "    ")
+        })
+    }
+
+    fn make_get_set(name: &str, ty: &Type) -> String {
+        let getter = match ty {
+            Type::Scaled(..) => format!(
+                "    pub fn {name}(&self) -> f64 {{\n        {get}\n    }}",
+                name = name,
+                get = RustGenerator::unscale(&format!("self.{}", name), ty)
+            ),
+            Type::Array(elt, len) if matches!(**elt, Type::Scaled(..)) => format!(
+                "    pub fn {name}(&self) -> [f64; {len}] {{\n        \
+                 let mut values = [0.0; {len}];\n        \
+                 for (value, &v) in values.iter_mut().zip(self.{name}.iter()) {{\n            \
+                 *value = {get};\n        \
+                 }}\n        \
+                 values\n    \
+                 }}",
+                name = name,
+                len = len,
+                get = RustGenerator::unscale("v", elt)
+            ),
+            Type::VarArray(elt, _max_len) if matches!(**elt, Type::Scaled(..)) => format!(
+                "    pub fn {name}(&self) -> impl Iterator<Item = f64> + '_ {{\n        \
+                 self.{name}[..usize::from(self.{name}_len)]\n            \
+                 .iter()\n            \
+                 .map(|&v| {get})\n    \
+                 }}",
+                name = name,
+                get = RustGenerator::unscale("v", elt)
+            ),
+            Type::CHARS(_) | Type::Array(..) => format!(
+                "    pub fn {name}(&self) -> &{t} {{\n        &self.{name}\n    }}",
+                name = name,
+                t = RustGenerator::get_type(ty)
+            ),
+            Type::VarChars(_) => format!(
+                "    pub fn {name}(&self) -> &[u8] {{\n        \
+                 &self.{name}[..usize::from(self.{name}_len)]\n    \
+                 }}",
+                name = name
+            ),
+            Type::VarArray(elt, _max_len) => format!(
+                "    pub fn {name}(&self) -> &[{t}] {{\n        \
+                 &self.{name}[..usize::from(self.{name}_len)]\n    \
+                 }}",
+                name = name,
+                t = RustGenerator::get_type(elt)
+            ),
+            _ => format!(
+                "    pub fn {name}(&self) -> {t} {{\n        self.{name}\n    }}",
+                name = name,
+                t = RustGenerator::get_type(ty)
+            ),
+        };
+
+        let setter = match ty {
+            Type::CHARS(size) => format!(
+                "    /// Copy at most {size} bytes of `{name}`, the remaining bytes are zeroed.\n    \
+                 pub fn set_{name}(&mut self, {name}: &[u8]) {{\n        \
+                 let len = {name}.len().min({size});\n        \
+                 self.{name} = [0; {size}];\n        \
+                 self.{name}[..len].copy_from_slice(&{name}[..len]);\n    \
+                 }}",
+                name = name,
+                size = size
+            ),
+            Type::Array(elt, _len) => format!(
+                "    pub fn set_{name}(&mut self, {name}: {t}) {{\n        \
+                 for (dst, &v) in self.{name}.iter_mut().zip({name}.iter()) {{\n            \
+                 *dst = {value};\n        \
+                 }}\n    \
+                 }}",
+                name = name,
+                t = RustGenerator::value_type(ty),
+                value = RustGenerator::set_value("v", elt)
+            ),
+            Type::VarChars(max_len) => format!(
+                "    /// Copy at most {max_len} bytes of `{name}`, the remaining bytes are zeroed.\n    \
+                 pub fn set_{name}(&mut self, {name}: &[u8]) {{\n        \
+                 let len = {name}.len().min({max_len});\n        \
+                 self.{name} = [0; {max_len}];\n        \
+                 self.{name}[..len].copy_from_slice(&{name}[..len]);\n        \
+                 self.{name}_len = len as u8;\n    \
+                 }}",
+                name = name,
+                max_len = max_len
+            ),
+            Type::VarArray(elt, max_len) => format!(
+                "    /// Copy at most {max_len} elements of `{name}`, the remaining elements are zeroed.\n    \
+                 pub fn set_{name}(&mut self, {name}: &[{t}]) {{\n        \
+                 self.{name} = {zero};\n        \
+                 for (dst, &v) in self.{name}.iter_mut().zip({name}.iter()) {{\n            \
+                 *dst = {value};\n        \
+                 }}\n        \
+                 self.{name}_len = {name}.len().min({max_len}) as u8;\n    \
+                 }}",
+                name = name,
+                max_len = max_len,
+                t = RustGenerator::value_type(elt),
+                zero = RustGenerator::zero(ty),
+                value = RustGenerator::set_value("v", elt)
+            ),
+            _ => format!(
+                "    pub fn set_{name}(&mut self, {name}: {t}) {{\n        \
+                 self.{name} = {value};\n    \
+                 }}",
+                name = name,
+                t = RustGenerator::value_type(ty),
+                value = RustGenerator::set_value(name, ty)
+            ),
+        };
+
+        format!("{}\n\n{}", getter, setter)
+    }
+
+    /// Suffix of the `to_*_bytes`/`from_*_bytes` methods for the wire byte order.
+    fn byte_order(endianness: Endianness) -> &'static str {
+        match endianness {
+            Endianness::Little => "le",
+            Endianness::Big => "be",
+        }
+    }
+
+    /// Code writing `value` at `buffer[offset..]`, then moving `offset` past it.
+    fn serialise_var(value: &str, ty: &Type, endianness: Endianness) -> String {
+        let bytes = match ty {
+            Type::Array(elt, _len) => {
+                let code = RustGenerator::serialise_var("v", elt, endianness);
+                return format!(
+                    "        for &v in {}.iter() {{\n    {}\n        }}",
+                    value,
+                    code.replace('\n', "\n    ")
+                );
+            }
+            Type::VarChars(_) | Type::VarArray(..) => {
+                return RustGenerator::serialise_var_len(value, ty, endianness)
+            }
+            Type::Struct(st) => {
+                return st
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        RustGenerator::serialise_var(
+                            &format!("{}.{}", value, field.name),
+                            &field.t,
+                            endianness,
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+            Type::CHARS(_size) => value.to_string(),
+            Type::Bool => format!("[{} as u8]", value),
+            Type::Enum(e) => format!(
+                "({} as {}).to_{}_bytes()",
+                value,
+                RustGenerator::get_type(&e.repr),
+                RustGenerator::byte_order(endianness)
+            ),
+            Type::Bitfield(_) => format!(
+                "{}.0.to_{}_bytes()",
+                value,
+                RustGenerator::byte_order(endianness)
+            ),
+            _ => format!(
+                "{}.to_{}_bytes()",
+                value,
+                RustGenerator::byte_order(endianness)
+            ),
+        };
+
+        format!(
+            "        buffer[offset..offset + {size}].copy_from_slice(&{bytes});\n        \
+             offset += {size};",
+            size = ty.get_size(),
+            bytes = bytes
+        )
+    }
+
+    /// Code writing the length byte of a variable length `value`, then its elements.
+    fn serialise_var_len(value: &str, ty: &Type, endianness: Endianness) -> String {
+        let elements = match ty {
+            Type::VarArray(elt, _max_len) => {
+                let code = RustGenerator::serialise_var("v", elt, endianness);
+                format!(
+                    "        for &v in {}[..len].iter() {{\n    {}\n        }}",
+                    value,
+                    code.replace('\n', "\n    ")
+                )
+            }
+            _ => format!(
+                "        buffer[offset..offset + len].copy_from_slice(&{}[..len]);\n        \
+                 offset += len;",
+                value
+            ),
+        };
+
+        format!(
+            "        let len = usize::from({value}_len);\n        \
+             buffer[offset] = {value}_len;\n        \
+             offset += 1;\n\
+             {elements}",
+            value = value,
+            elements = elements
+        )
+    }
+
+    /// Code reading `lvalue` from `payload[offset..]`, then moving `offset` past it.
+    fn deserialise_var(lvalue: &str, ty: &Type, endianness: Endianness) -> String {
+        let bytes = format!(
+            "payload[offset..offset + {size}].try_into().unwrap()",
+            size = ty.get_size()
+        );
+        let value = match ty {
+            Type::Array(elt, _len) => {
+                let code = RustGenerator::deserialise_var("*v", elt, endianness);
+                return format!(
+                    "        for v in {}.iter_mut() {{\n    {}\n        }}",
+                    lvalue,
+                    code.replace('\n', "\n    ")
+                );
+            }
+            // Fields are reached through references without dereferencing them.
+            Type::Struct(st) => {
+                return st
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        RustGenerator::deserialise_var(
+                            &format!("{}.{}", lvalue.trim_start_matches('*'), field.name),
+                            &field.t,
+                            endianness,
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+            Type::CHARS(_size) => bytes,
+            Type::Bool => "payload[offset] != 0".to_string(),
+            Type::Enum(e) => format!(
+                "{name}::try_from({t}::from_{e}_bytes({bytes}))?",
+                name = e.name,
+                t = RustGenerator::get_type(&e.repr),
+                e = RustGenerator::byte_order(endianness),
+                bytes = bytes
+            ),
+            Type::Bitfield(bf) => format!(
+                "{name}({t}::from_{e}_bytes({bytes}))",
+                name = bf.name,
+                t = RustGenerator::get_type(&bf.repr),
+                e = RustGenerator::byte_order(endianness),
+                bytes = bytes
+            ),
+            _ => format!(
+                "{t}::from_{e}_bytes({bytes})",
+                t = RustGenerator::get_type(ty),
+                e = RustGenerator::byte_order(endianness),
+                bytes = bytes
+            ),
+        };
+
+        format!(
+            "        {lvalue} = {value};\n        \
+             offset += {size};",
+            lvalue = lvalue,
+            value = value,
+            size = ty.get_size()
+        )
+    }
+
+    /// Code reading the length byte of a variable length `lvalue`, then its elements.
+    /// The length is checked against the maximum, and against what is left of the payload
+    /// once the `rest` bytes of the following fields are put aside.
+    fn deserialise_var_len(lvalue: &str, ty: &Type, endianness: Endianness, rest: usize) -> String {
+        let (elements, max_len, elt_size) = match ty {
+            Type::VarChars(max_len) => (
+                format!(
+                    "        {}[..len].copy_from_slice(&payload[offset..offset + len]);\n        \
+                     offset += len;",
+                    lvalue
+                ),
+                max_len,
+                1,
+            ),
+            Type::VarArray(elt, max_len) => {
+                let code = RustGenerator::deserialise_var("*v", elt, endianness);
+                (
+                    format!(
+                        "        for v in {}[..len].iter_mut() {{\n    {}\n        }}",
+                        lvalue,
+                        code.replace('\n', "\n    ")
+                    ),
+                    max_len,
+                    elt.get_size(),
+                )
+            }
+            _ => unreachable!(),
+        };
+
+        let mut end = "offset + 1 + len".to_string();
+        if elt_size > 1 {
+            end += &format!(" * {}", elt_size);
+        }
+        if rest > 0 {
+            end += &format!(" + {}", rest);
+        }
+
+        format!(
+            "        let len = usize::from(payload[offset]);\n        \
+             if len > {max_len} || {end} > payload.len() {{\n            \
+             return Err(Error::BadLength);\n        \
+             }}\n        \
+             {lvalue}_len = len as u8;\n        \
+             offset += 1;\n\
+             {elements}",
+            max_len = max_len,
+            end = end,
+            lvalue = lvalue,
+            elements = elements
+        )
+    }
+
+    fn to_bytes(msg: &MsgSpec, endianness: Endianness) -> String {
+        let serialisations = msg
+            .fields
+            .iter()
+            .map(|field| {
+                RustGenerator::serialise_var(&format!("self.{}", field.name), &field.t, endianness)
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "    /// Write the whole frame into `buffer` and return its length.\n    \
+             pub fn to_bytes(&self, buffer: &mut [u8]) -> Result<usize, Error> {{\n        \
+             if buffer.len() < Self::SIZE {{\n            \
+             return Err(Error::BufferTooSmall);\n        \
+             }}\n        \
+             buffer[0] = 0xFF;\n        \
+             buffer[1] = 0xFF;\n        \
+             buffer[2] = Self::ID;\n        \
+             let mut offset = 4;\n\
+             {serialisations}\n        \
+             buffer[3] = (offset - 2) as u8;\n        \
+             let checksum = compute_checksum(&buffer[2..offset]);\n        \
+             buffer[offset..offset + 2].copy_from_slice(&checksum.to_le_bytes());\n        \
+             Ok(offset + 2)\n    \
+             }}",
+            serialisations = serialisations
+        )
+    }
+
+    fn from_bytes(msg: &MsgSpec, endianness: Endianness) -> String {
+        let deserialisations = msg
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let lvalue = format!("msg.{}", field.name);
+                if field.t.is_variable() {
+                    let rest = msg.fields[i + 1..].iter().map(|f| f.t.get_min_size()).sum();
+                    RustGenerator::deserialise_var_len(&lvalue, &field.t, endianness, rest)
+                } else {
+                    RustGenerator::deserialise_var(&lvalue, &field.t, endianness)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        // Messages with variable length fields are checked as they are decoded.
+        let (check_len, check_end) = if msg.is_variable() {
+            (
+                "payload.len() < Self::MIN_SIZE - 6 || payload.len() > Self::SIZE - 6",
+                "if offset != payload.len() {\n            \
+                 return Err(Error::BadLength);\n        \
+                 }",
+            )
+        } else {
+            ("payload.len() != Self::SIZE - 6", "let _ = offset;")
+        };
+
+        format!(
+            "    /// Decode the message from its payload, without the start bytes, id, length and checksum.\n    \
+             pub fn from_bytes(payload: &[u8]) -> Result<Self, Error> {{\n        \
+             if {check_len} {{\n            \
+             return Err(Error::BadLength);\n        \
+             }}\n        \
+             let mut msg = Self::new();\n        \
+             let mut offset = 0;\n\
+             {deser}\n        \
+             {check_end}\n        \
+             Ok(msg)\n    \
+             }}",
+            check_len = check_len,
+            deser = deserialisations,
+            check_end = check_end
+        )
+    }
+
+    fn declare_enum(e: &EnumSpec) -> String {
+        let variants = e
+            .variants
+            .iter()
+            .map(|(name, value)| format!("    {} = {},", name.to_pascal_case(), value))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let cases = e
+            .variants
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "            {} => Ok({}::{}),",
+                    value,
+                    e.name,
+                    name.to_pascal_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "#[derive(Debug, Clone, Copy, PartialEq)]\n\
+             #[repr({repr})]\n\
+             pub enum {name} {{\n\
+             {variants}\n\
+             }}\n\n\
+             impl TryFrom<{repr}> for {name} {{\n    \
+             type Error = Error;\n\n    \
+             fn try_from(value: {repr}) -> Result<Self, Error> {{\n        \
+             match value {{\n\
+             {cases}\n            \
+             _ => Err(Error::InvalidValue),\n        \
+             }}\n    \
+             }}\n\
+             }}",
+            repr = RustGenerator::get_type(&e.repr),
+            name = e.name,
+            variants = variants,
+            cases = cases
+        )
+    }
+
+    /// Tuple struct wrapping the bits of a bitfield, with a getter and a setter for each bit range.
+    fn declare_bitfield(bf: &BitfieldSpec) -> String {
+        let t = RustGenerator::get_type(&bf.repr);
+        let nb_bits = 8 * bf.repr.get_size() as u32;
+        let shift = |value: String, op: &str, start: u32| {
+            if start == 0 {
+                value
+            } else {
+                format!("({} {} {})", value, op, start)
+            }
+        };
+        let getsets = bf
+            .ranges
+            .iter()
+            .map(|range| {
+                let (value_t, get, value) = if range.width == 1 {
+                    (
+                        "bool".to_string(),
+                        format!("self.0 & 0x{:X} != 0", range.mask()),
+                        format!("{}::from({})", t, range.name),
+                    )
+                } else if range.width < nb_bits {
+                    (
+                        t.clone(),
+                        format!(
+                            "{} & 0x{:X}",
+                            shift("self.0".to_string(), ">>", range.start),
+                            range.max()
+                        ),
+                        format!("{}.min(0x{:X})", range.name, range.max()),
+                    )
+                } else {
+                    (t.clone(), "self.0".to_string(), range.name.clone())
+                };
+                format!(
+                    "    pub fn {name}(&self) -> {value_t} {{\n        \
+                     {get}\n    \
+                     }}\n\n    \
+                     pub fn set_{name}(&mut self, {name}: {value_t}) {{\n        \
+                     self.0 = (self.0 & !0x{mask:X}) | {value};\n    \
+                     }}",
+                    name = range.name,
+                    value_t = value_t,
+                    get = get,
+                    mask = range.mask(),
+                    value = shift(value, "<<", range.start)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        format!(
+            "/// Bits packed into a {t}, wider ranges are clamped by their setter.\n\
+             #[derive(Debug, Clone, Copy, PartialEq, Default)]\n\
+             pub struct {name}(pub {t});\n\n\
+             impl {name} {{\n\
+             {getsets}\n\
+             }}",
+            name = bf.name,
+            t = t,
+            getsets = getsets
+        )
+    }
+
+    /// Statement clamping `lvalue` to its bounds, `None` if any value of `ty` is in range.
+    fn clamp_value(lvalue: &str, ty: &Type) -> Option<String> {
+        match ty {
+            // `*v.clamped()` would dereference the returned struct.
+            Type::Array(elt, _len) if matches!(**elt, Type::Struct(_)) => Some(format!(
+                "        for v in {}.iter_mut() {{\n            *v = v.clamped();\n        }}",
+                lvalue
+            )),
+            Type::Array(elt, _len) => RustGenerator::clamp_value("*v", elt).map(|clamp| {
+                format!(
+                    "        for v in {}.iter_mut() {{\n    {}\n        }}",
+                    lvalue, clamp
+                )
+            }),
+            // The setters store quantized values, only the integer may be out of bounds.
+            Type::Scaled(t, _scale) => RustGenerator::clamp_value(lvalue, t),
+            _ => {
+                let value = RustGenerator::set_value(lvalue, ty);
+                if value == lvalue {
+                    None
+                } else {
+                    Some(format!("        {} = {};", lvalue, value))
+                }
+            }
+        }
+    }
+
+    /// Struct of a [types] entry. Its fields are public, setters of the messages clamp them.
+    fn declare_struct(st: &StructSpec) -> String {
+        let vars = st
+            .fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "{}    pub {}: {},",
+                    RustGenerator::field_doc(field),
+                    field.name,
+                    RustGenerator::get_type(&field.t)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let inits = st
+            .fields
+            .iter()
+            .map(|field| {
+                RustGenerator::init_variable(&field.name, &field.t, field.default.as_ref())
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let clamps = st
+            .fields
+            .iter()
+            .filter_map(|field| {
+                RustGenerator::clamp_value(&format!("self.{}", field.name), &field.t)
+            })
+            .collect::<Vec<String>>();
+        let getsets = st
+            .fields
+            .iter()
+            .filter(|field| matches!(field.t.scalar(), Type::Scaled(..)))
+            .map(|field| {
+                format!(
+                    "\n\n{}{}",
+                    RustGenerator::field_doc(field),
+                    RustGenerator::make_get_set(&field.name, &field.t)
+                )
+            })
+            .collect::<String>();
+
+        let clamped = if clamps.is_empty() {
+            "    pub fn clamped(self) -> Self {\n        self\n    }".to_string()
+        } else {
+            format!(
+                "    pub fn clamped(mut self) -> Self {{\n\
+                 {}\n        \
+                 self\n    \
+                 }}",
+                clamps.join("\n")
+            )
+        };
+
+        format!(
+            "#[derive(Debug, Clone, Copy, PartialEq)]\n\
+             pub struct {name} {{\n\
+             {vars}\n\
+             }}\n\n\
+             impl {name} {{\n    \
+             pub fn new() -> Self {{\n        \
+             {name} {{\n\
+             {inits}\n        \
+             }}\n    \
+             }}\n\n    \
+             /// Copy of the struct with its fields clamped to their bounds.\n\
+             {clamped}{getsets}\n\
+             }}\n\n\
+             impl Default for {name} {{\n    \
+             fn default() -> Self {{\n        \
+             Self::new()\n    \
+             }}\n\
+             }}",
+            name = st.name,
+            vars = vars,
+            inits = inits,
+            clamped = clamped,
+            getsets = getsets
+        )
+    }
+
+    fn declare_class(msg: &MsgSpec, schema: &Schema) -> String {
+        let vars = msg
+            .fields
+            .iter()
+            .map(|field| RustGenerator::declare_variable(field.name.as_ref(), &field.t))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let inits = msg
+            .fields
+            .iter()
+            .map(|field| {
+                RustGenerator::init_variable(field.name.as_ref(), &field.t, field.default.as_ref())
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let getsets = msg
+            .fields
+            .iter()
+            .map(|field| {
+                RustGenerator::field_doc(field)
+                    + &RustGenerator::make_get_set(field.name.as_ref(), &field.t)
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let mut coders = String::new();
+        if schema.encodes(msg) {
+            coders += &format!("\n\n{}", RustGenerator::to_bytes(msg, schema.endianness));
+        }
+        if schema.decodes(msg) {
+            coders += &format!("\n\n{}", RustGenerator::from_bytes(msg, schema.endianness));
+        }
+
+        format!(
+            "{doc}#[derive(Debug, Clone, Copy, PartialEq)]\n\
+             pub struct {name} {{\n\
+             {vars}\n\
+             }}\n\n\
+             impl {name} {{\n    \
+             pub const ID: u8 = {id};\n    \
+             pub const SIZE: usize = {size};\n    \
+             pub const MIN_SIZE: usize = {min_size};\n\n    \
+             pub fn new() -> Self {{\n        \
+             {name} {{\n\
+             {inits}\n        \
+             }}\n    \
+             }}\n\n    \
+             /// Sets the fields to their default values.\n    \
+             pub fn reset(&mut self) {{\n        \
+             *self = Self::new();\n    \
+             }}\n\n\
+             {getsets}{coders}\n\
+             }}\n\n\
+             impl Default for {name} {{\n    \
+             fn default() -> Self {{\n        \
+             Self::new()\n    \
+             }}\n\
+             }}",
+            name = msg.name,
+            doc = msg
+                .description()
+                .map_or(String::new(), |doc| RustGenerator::doc_comment(&doc, "")),
+            id = msg.id,
+            size = msg.get_buffer_size(),
+            min_size = msg.get_min_buffer_size(),
+            vars = vars,
+            inits = inits,
+            getsets = getsets,
+            coders = coders
+        )
+    }
+
+    fn message_enum(schema: &Schema) -> String {
+        let messages = &schema.messages;
+        let variants = messages
+            .iter()
+            .map(|msg| format!("    {name}({name}),", name = msg.name))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let ids = messages
+            .iter()
+            .map(|msg| format!("            Message::{}(_) => {}::ID,", msg.name, msg.name))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let to_bytes = messages
+            .iter()
+            .map(|msg| {
+                if schema.encodes(msg) {
+                    format!(
+                        "            Message::{}(m) => m.to_bytes(buffer),",
+                        msg.name
+                    )
+                } else {
+                    format!(
+                        "            Message::{name}(_) => Err(Error::WrongDirection({name}::ID)),",
+                        name = msg.name
+                    )
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let from_bytes = messages
+            .iter()
+            .map(|msg| {
+                if schema.decodes(msg) {
+                    format!(
+                        "            {name}::ID => Ok(Message::{name}({name}::from_bytes(payload)?)),",
+                        name = msg.name
+                    )
+                } else {
+                    format!(
+                        "            {}::ID => Err(Error::WrongDirection(id)),",
+                        msg.name
+                    )
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "#[derive(Debug, Clone, Copy, PartialEq)]\n\
+             pub enum Message {{\n\
+             {variants}\n\
+             }}\n\n\
+             impl Message {{\n    \
+             pub fn id(&self) -> u8 {{\n        \
+             match self {{\n\
+             {ids}\n        \
+             }}\n    \
+             }}\n\n    \
+             /// Write the whole frame into `buffer` and return its length.\n    \
+             pub fn to_bytes(&self, buffer: &mut [u8]) -> Result<usize, Error> {{\n        \
+             match self {{\n\
+             {to_bytes}\n        \
+             }}\n    \
+             }}\n\n    \
+             /// Decode the message `id` from its payload, without the start bytes, id, length and checksum.\n    \
+             pub fn from_bytes(id: u8, payload: &[u8]) -> Result<Message, Error> {{\n        \
+             match id {{\n\
+             {from_bytes}\n            \
+             _ => Err(Error::UnknownId(id)),\n        \
+             }}\n    \
+             }}\n\
+             }}",
+            variants = variants,
+            ids = ids,
+            to_bytes = to_bytes,
+            from_bytes = from_bytes
+        )
+    }
+
+    fn handler(schema: &Schema) -> String {
+        let messages = schema.received();
+        let methods = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "    fn on_{sname}(&mut self, _msg: &{name}) {{}}",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let cases = messages
+            .iter()
+            .map(|msg| {
+                format!(
+                    "            Message::{name}(m) => handler.on_{sname}(m),",
+                    name = msg.name,
+                    sname = msg.name.to_snake_case()
+                )
+            })
+            .chain(if messages.len() < schema.messages.len() {
+                // Messages this side sends are never received.
+                Some("            _ => {}".to_string())
+            } else {
+                None
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        format!(
+            "/// Implement the methods of the messages you want to handle, then pass the handler\n\
+             /// to `Message::dispatch`.\n\
+             pub trait Handler {{\n\
+             {methods}\n\
+             }}\n\n\
+             impl Message {{\n    \
+             /// Call the method of `handler` matching this message.\n    \
+             pub fn dispatch<H: Handler>(&self, handler: &mut H) {{\n        \
+             match self {{\n\
+             {cases}\n        \
+             }}\n    \
+             }}\n\
+             }}",
+            methods = methods,
+            cases = cases
+        )
+    }
+}
+
+impl Generator for RustGenerator {
+    fn generate_messages(schema: &Schema, uid: u32) -> Vec<(String, String)> {
+        let messages = &schema.messages;
+        let max_size: usize = messages
+            .iter()
+            .map(|msg| msg.get_buffer_size())
+            .max()
+            .unwrap();
+
+        let consts = format!(
+            "pub const UID: u32 = {};\n\n\
+             pub const MAX_MSG_BUFFER_SIZE: usize = {};{}",
+            uid,
+            max_size,
+            RustGenerator::declare_constants(&schema.constants)
+        );
+
+        let classes = messages
+            .iter()
+            .map(|msg| RustGenerator::declare_class(msg, schema))
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let enums = schema
+            .enums
+            .iter()
+            .map(|e| RustGenerator::declare_enum(e) + "\n\n")
+            .chain(
+                schema
+                    .bitfields
+                    .iter()
+                    .map(|bf| RustGenerator::declare_bitfield(bf) + "\n\n"),
+            )
+            .chain(
+                schema
+                    .structs
+                    .iter()
+                    .map(|st| RustGenerator::declare_struct(st) + "\n\n"),
+            )
+            .collect::<String>();
+
+        // TryFrom is only in the prelude from the 2021 edition.
+        let header = if schema.enums.is_empty() {
+            RustGenerator::HEADER.to_string()
+        } else {
+            format!("{}\nuse core::convert::TryFrom;", RustGenerator::HEADER)
+        };
+
+        let quantize = if schema.has_scaled() {
+            format!("\n\n{}", RustGenerator::QUANTIZE)
+        } else {
+            String::new()
+        };
+
+        let code = format!(
+            "{}\n\n{}\n\n{}\n\n{}{}\n\n{}\n\n{}{}\n\n{}\n\n{}\n",
+            header,
+            consts,
+            RustGenerator::ERROR,
+            RustGenerator::CLAMP,
+            quantize,
+            RustGenerator::CHECKSUM,
+            enums,
+            classes,
+            RustGenerator::message_enum(schema),
+            RustGenerator::handler(schema)
+        );
+
+        vec![
+            (
+                "Cargo.toml".to_string(),
+                RustGenerator::CARGO_TOML.to_string(),
+            ),
+            ("lib.rs".to_string(), RustGenerator::LIB_RS.to_string()),
+            ("messages.rs".to_string(), code),
+        ]
+    }
+}
@@ -0,0 +1,80 @@
+use crate::message::Schema;
+
+/// 32 bits FNV-1a hasher used to derive the protocol UID.
+///
+/// `std::hash` is not used on purpose: its output is not guaranteed to be stable
+/// across Rust versions, and the UID must be identical wherever the schema is generated.
+pub struct UidHasher {
+    state: u32,
+}
+
+impl UidHasher {
+    const OFFSET_BASIS: u32 = 0x811c_9dc5;
+    const PRIME: u32 = 0x0100_0193;
+
+    pub fn new() -> UidHasher {
+        UidHasher {
+            state: UidHasher::OFFSET_BASIS,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state ^= u32::from(*b);
+            self.state = self.state.wrapping_mul(UidHasher::PRIME);
+        }
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.write(&[v]);
+    }
+
+    pub fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub fn write_i64(&mut self, v: i64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub fn write_i128(&mut self, v: i128) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, v: f64) {
+        self.write(&v.to_bits().to_le_bytes());
+    }
+
+    /// Strings are length prefixed so that ("ab", "c") and ("a", "bc") hash differently.
+    pub fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write(s.as_bytes());
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.state
+    }
+}
+
+impl Default for UidHasher {
+    fn default() -> Self {
+        UidHasher::new()
+    }
+}
+
+/// Compute the UID of a schema.
+///
+/// Messages are hashed in id order, so the UID only depends on what goes on the wire,
+/// not on the order the messages are stored in.
+pub fn schema_uid(schema: &Schema) -> u32 {
+    let mut sorted = schema.messages.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|msg| msg.id);
+
+    let mut hasher = UidHasher::new();
+    schema.hash_wire(&mut hasher);
+    hasher.write_u64(sorted.len() as u64);
+    for msg in sorted {
+        msg.hash_wire(&mut hasher);
+    }
+    hasher.finish()
+}
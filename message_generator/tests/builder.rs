@@ -0,0 +1,106 @@
+//! Checks the `Builder` API, as used from a build script.
+
+use ducklink_codegen::{Builder, Lang};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const COMMON: &str = "[types.Point]\nx = \"f32\"\ny = \"f32\"\n";
+
+const SCHEMA: &str = "include = [\"common.toml\"]\n\n[up.Odom]\np = \"Point\"\n";
+
+/// Lock pinning every message of `SCHEMA`.
+const LOCK: &str = "[ids]\nUpOdom = 1\n";
+
+/// Set when a test runs itself, to capture what `generate` prints.
+const CHILD_ROOT: &str = "DUCKLINK_BUILDER_ROOT";
+
+/// A fresh directory holding `SCHEMA`, the file it includes and, if given, its lock.
+fn project(name: &str, lock: Option<&str>) -> PathBuf {
+    let root = env::temp_dir().join(format!("ducklink_builder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("common.toml"), COMMON).unwrap();
+    fs::write(root.join("schema.toml"), SCHEMA).unwrap();
+    if let Some(lock) = lock {
+        fs::write(root.join("schema.lock"), lock).unwrap();
+    }
+    root
+}
+
+#[test]
+fn build_scripts_get_the_files_and_what_they_depend_on() {
+    if let Ok(root) = env::var(CHILD_ROOT) {
+        let root = PathBuf::from(root);
+        Builder::new(root.join("schema.toml"))
+            .lang(Lang::Rust)
+            .out_dir(root.join("out"))
+            .generate()
+            .unwrap();
+        return;
+    }
+
+    // The cargo lines are printed on the standard output, so generate in a child process.
+    // Quiet, the test harness doesn't print the name of the test on the first of them.
+    let root = project("build_script", Some(LOCK));
+    let output = Command::new(env::current_exe().unwrap())
+        .args([
+            "build_scripts_get_the_files_and_what_they_depend_on",
+            "--exact",
+            "--nocapture",
+            "--quiet",
+        ])
+        .env(CHILD_ROOT, &root)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+
+    let rerun = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("cargo:rerun-if-changed="))
+        .collect::<Vec<_>>();
+    let expected = ["common.toml", "schema.toml", "schema.lock"]
+        .iter()
+        .map(|file| root.join(file).display().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(rerun, expected);
+
+    let mut files = fs::read_dir(root.join("out"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["Cargo.toml", "lib.rs", "messages.rs"]);
+    let messages = fs::read_to_string(root.join("out/messages.rs")).unwrap();
+    assert!(messages.contains("pub struct UpOdom {"), "{}", messages);
+    assert!(messages.contains("pub struct Point {"), "{}", messages);
+
+    // The lock is left as it was.
+    assert_eq!(fs::read_to_string(root.join("schema.lock")).unwrap(), LOCK);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn stale_locks_are_only_updated_on_demand() {
+    let root = project("stale_lock", None);
+    let builder = Builder::new(root.join("schema.toml"))
+        .lang(Lang::Rust)
+        .out_dir(root.join("out"))
+        .cargo_rerun(false);
+
+    let errs = builder.generate().unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].message, "the lock file is out of date");
+    assert!(!root.join("schema.lock").exists());
+    assert!(!root.join("out").exists());
+
+    builder.update_lock(true).generate().unwrap();
+    let lock = fs::read_to_string(root.join("schema.lock")).unwrap();
+    assert!(lock.ends_with("[ids]\nUpOdom = 1\n"), "{}", lock);
+    assert!(root.join("out/messages.rs").exists());
+
+    fs::remove_dir_all(&root).ok();
+}
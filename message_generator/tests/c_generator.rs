@@ -0,0 +1,25 @@
+//! Checks the code emitted by the C generator.
+
+use ducklink_codegen::lock::IdLock;
+use ducklink_codegen::{parse_toml, Lang};
+
+/// Header and source generated for `schema`.
+fn generate(schema: &str) -> String {
+    let schema = parse_toml(schema, &IdLock::default()).unwrap();
+    Lang::C
+        .generate_messages(&schema, 0)
+        .into_iter()
+        .map(|(_name, code)| code)
+        .collect()
+}
+
+#[test]
+fn f64_fields_need_64_bits_doubles() {
+    let guard = "typedef char duck_double_is_64_bits";
+    assert!(generate("[up.A]\nx = \"f64\"\n").contains(guard));
+    assert!(generate("[up.A]\nx = {type = \"f64\", len = 2}\n").contains(guard));
+    assert!(!generate("[up.A]\nx = \"f32\"\n").contains(guard));
+    // Also in the fields of types.
+    assert!(generate("[types.P]\nx = \"f64\"\n\n[up.A]\np = \"P\"\n").contains(guard));
+    assert!(!generate("[types.P]\nx = \"f32\"\n\n[up.A]\np = \"P\"\n").contains(guard));
+}
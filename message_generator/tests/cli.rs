@@ -0,0 +1,143 @@
+//! Checks where the command line tool writes the generated files.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const SCHEMA: &str = "[up.Odom]\nx = \"f32\"\n";
+
+/// A schema in `root/work`, the generator being run from there.
+struct Project {
+    root: PathBuf,
+    work: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str) -> Project {
+        let root = env::temp_dir().join(format!("ducklink_cli_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let work = root.join("work");
+        fs::create_dir_all(&work).unwrap();
+        fs::write(work.join("schema.toml"), SCHEMA).unwrap();
+        Project { root, work }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_message_generator"))
+            .current_dir(&self.work)
+            .arg("schema.toml")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Paths of the files under the project, relative to its root, sorted.
+    fn files(&self) -> Vec<String> {
+        fn walk(dir: &Path, root: &Path, files: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(&path, root, files);
+                } else {
+                    let file = path.strip_prefix(root).unwrap();
+                    files.push(file.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        let mut files = vec![];
+        walk(&self.root, &self.root, &mut files);
+        files.sort();
+        files
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+#[test]
+fn stdout_only_prints_the_files() {
+    let project = Project::new("stdout");
+    let output = project.run(&["-l", "C", "Rust", "--stdout"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.contains("#ifndef MESSAGES_H"), "{}", stdout);
+    assert!(stdout.contains("pub struct UpOdom {"), "{}", stdout);
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+    // Not even the lock file is written.
+    assert_eq!(project.files(), ["work/schema.toml"]);
+}
+
+#[test]
+fn files_are_written_in_the_out_dir() {
+    let project = Project::new("out_dir");
+    project.run(&["-l", "C", "Python"]);
+    project.run(&["-l", "CPP", "-o", "out"]);
+    assert_eq!(
+        project.files(),
+        [
+            "lib/C/messages/messages.c",
+            "lib/C/messages/messages.h",
+            "lib/Python/messages/messages.py",
+            "work/out/CPP/messages/messages.cpp",
+            "work/out/CPP/messages/messages.h",
+            "work/schema.lock",
+            "work/schema.toml",
+        ]
+    );
+}
+
+#[test]
+fn each_language_can_be_written_elsewhere() {
+    let project = Project::new("lang_out");
+    project.run(&[
+        "-l",
+        "C",
+        "CPP",
+        "Python",
+        "Rust",
+        "Doc",
+        "-o",
+        "out",
+        "--c-out",
+        "c",
+        "--cpp-out",
+        "cpp",
+        "--python-out",
+        "python",
+        "--rust-out",
+        "rust",
+        "--doc-out",
+        "doc",
+    ]);
+    assert_eq!(
+        project.files(),
+        [
+            "work/c/messages.c",
+            "work/c/messages.h",
+            "work/cpp/messages.cpp",
+            "work/cpp/messages.h",
+            "work/doc/protocol.md",
+            "work/python/messages.py",
+            "work/rust/Cargo.toml",
+            "work/rust/lib.rs",
+            "work/rust/messages.rs",
+            "work/schema.lock",
+            "work/schema.toml",
+        ]
+    );
+
+    // Languages without their own directory still go in the out dir.
+    project.run(&["-l", "C", "Doc", "-o", "out", "--c-out", "c"]);
+    assert!(project.work.join("out/Doc/messages/protocol.md").exists());
+    assert!(!project.work.join("out/C").exists());
+}
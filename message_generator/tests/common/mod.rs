@@ -0,0 +1,46 @@
+//! Helpers shared by the tests running the generator on a schema.
+
+// Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The compiler named by the environment variable `var`, or `default`, if it runs.
+pub fn compiler(var: &str, default: &str) -> Option<String> {
+    let cc = env::var(var).unwrap_or_else(|_| default.to_string());
+    match Command::new(&cc).arg("--version").output() {
+        Ok(output) if output.status.success() => Some(cc),
+        _ => None,
+    }
+}
+
+/// The schema the generated code is checked on.
+pub fn test_schema() -> String {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    fs::read_to_string(manifest_dir.join("msgs_test.toml")).unwrap()
+}
+
+/// Generate the code of `schema` for each of `langs` in a fresh directory, named after
+/// `name`, and return that directory. The code of each language is written in its
+/// lowercased name, e.g. `cpp` for `CPP`. `args` are passed on to the generator.
+pub fn generate(name: &str, schema: &str, langs: &[&str], args: &[&str]) -> PathBuf {
+    let root = env::temp_dir().join(format!("ducklink_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    // The lock file is written next to the schema, so work on a copy.
+    fs::write(root.join("schema.toml"), schema).unwrap();
+
+    let mut generator = Command::new(env!("CARGO_BIN_EXE_message_generator"));
+    generator
+        .arg(root.join("schema.toml"))
+        .arg("-l")
+        .args(langs);
+    for lang in langs {
+        let dir = lang.to_lowercase();
+        generator.arg(format!("--{}-out", dir)).arg(root.join(dir));
+    }
+    assert!(generator.args(args).status().unwrap().success());
+    root
+}
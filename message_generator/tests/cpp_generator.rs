@@ -0,0 +1,104 @@
+//! Checks the code emitted by the C++ generator, and that it compiles.
+//! Skipped if no C++ compiler is found (set `CXX` to choose one).
+
+mod common;
+
+use common::{compiler, generate, test_schema};
+use ducklink_codegen::lock::IdLock;
+use ducklink_codegen::{parse_toml, Lang};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn generated_cpp_compiles() {
+    let cxx = match compiler("CXX", "g++") {
+        Some(cxx) => cxx,
+        None => {
+            eprintln!("No C++ compiler found, skipping.");
+            return;
+        }
+    };
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    // Without --side, then with only the code each side needs.
+    for side in &[None, Some("mcu"), Some("host")] {
+        let args = side.map_or(vec![], |side| vec!["--side", side]);
+        let name = format!("cpp_{}", side.unwrap_or("both"));
+        let root = generate(&name, &test_schema(), &["CPP"], &args);
+        let out_dir = root.join("cpp");
+
+        // The MCU only encodes the up messages, and only decodes the down ones.
+        if *side == Some("mcu") {
+            let code = fs::read_to_string(out_dir.join("messages.cpp")).unwrap();
+            for msg in &[
+                "UpOdomReport",
+                "UpLogReport",
+                "DownSpeedCommand",
+                "DownGoTo",
+            ] {
+                let up = msg.starts_with("Up");
+                let decodes = code.contains(&format!("{0}::{0}(uint8_t *buffer)", msg));
+                let encodes = code.contains(&format!("int {}::to_bytes(", msg));
+                assert_eq!((decodes, encodes), (!up, up), "{}", msg);
+            }
+
+            // Sending a message the MCU only receives doesn't compile.
+            fs::write(
+                root.join("send.cpp"),
+                "#include \"messages.h\"\n\n\
+                 int send(uint8_t *buffer) {\n  \
+                 DownSpeedCommand msg;\n  \
+                 return msg.to_bytes(buffer);\n\
+                 }\n",
+            )
+            .unwrap();
+            let output = Command::new(&cxx)
+                .args(["-std=c++11", "-fsyntax-only", "-I"])
+                .arg(manifest_dir.join("../lib/CPP/messages"))
+                .arg("-I")
+                .arg(&out_dir)
+                .arg(root.join("send.cpp"))
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success());
+            assert!(stderr.contains("deleted"), "{}", stderr);
+        }
+
+        // The union storage needs C++11, the std::variant overload C++17.
+        for std in &["c++11", "c++17"] {
+            let output = Command::new(&cxx)
+                .arg(format!("-std={}", std))
+                .arg("-fsyntax-only")
+                .arg("-I")
+                .arg(manifest_dir.join("../lib/CPP/messages"))
+                .arg(out_dir.join("messages.cpp"))
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{} failed with -std={} and side {:?}:\n{}",
+                cxx,
+                std,
+                side,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
+
+#[test]
+fn float_bounds_keep_their_precision() {
+    let schema = "[down.Gain]\nk = {type = \"f32\", min = 0.001, max = 0.5}\n";
+    let schema = parse_toml(schema, &IdLock::default()).unwrap();
+    let code = Lang::Cpp
+        .generate_messages(&schema, 0)
+        .into_iter()
+        .map(|(_name, code)| code)
+        .collect::<String>();
+    assert!(code.contains("_k = clamp(0.001, k, 0.5);"), "{}", code);
+}
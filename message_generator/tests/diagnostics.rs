@@ -0,0 +1,364 @@
+//! Checks that schema errors are all reported, with their location.
+
+use ducklink_codegen::lock::IdLock;
+use ducklink_codegen::{parse_files, parse_toml, Type};
+
+const SCHEMA: &str = r#"
+[down.SpeedCommand]
+vx = "i16"
+vy = {type = "i32", min = 5, max = 2}
+
+[down.Empty]
+
+[up]
+Odom = { x = "f32", name = {type = "chars"} }
+"#;
+
+#[test]
+fn errors_are_collected_and_located() {
+    let errs = parse_toml(SCHEMA, &IdLock::default()).unwrap_err();
+    let located = errs
+        .iter()
+        .map(|d| (d.path.as_str(), d.span.as_ref().map(|s| (s.line, s.column))))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        located,
+        vec![
+            ("down.SpeedCommand.vy", Some((4, 1))),
+            ("down.Empty", Some((6, 1))),
+            ("up.Odom.name", Some((9, 21))),
+        ]
+    );
+    assert_eq!(
+        errs[0].value.as_deref(),
+        Some("{ type = \"i32\", min = 5, max = 2 }")
+    );
+}
+
+#[test]
+fn toml_syntax_errors_keep_their_location() {
+    let errs = parse_toml("[a.B]\nx = \n", &IdLock::default()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].span.as_ref().map(|s| s.line), Some(2));
+}
+
+#[test]
+fn enum_values_are_checked() {
+    let schema =
+        "[enums.DriveMode]\nStop = 0\nSpeed = 300\n\n[down.SpeedCommand]\nmode = \"DriveMode\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    // The enum is left out, so the field referring to it is reported too.
+    assert_eq!(
+        paths,
+        vec!["enums.DriveMode.Speed", "down.SpeedCommand.mode"]
+    );
+    assert_eq!(errs[0].span.as_ref().map(|s| s.line), Some(3));
+}
+
+#[test]
+fn recursive_types_are_rejected() {
+    let schema = "[types.A]\nb = \"B\"\n\n[types.B]\na = {type = \"A\", len = 2}\n\n\
+                  [up.Pose]\nx = \"f32\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].path, "types.B.a");
+    assert_eq!(errs[0].hint.as_deref(), Some("A -> B -> A would never end"));
+}
+
+#[test]
+fn bit_ranges_are_checked() {
+    let schema = "[bitfields.ResetFlags]\nreset_odom = 0\nlevel = \"0..2\"\nmode = \"6..9\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec!["bitfields.ResetFlags.level", "bitfields.ResetFlags.mode"]
+    );
+}
+
+#[test]
+fn scales_are_checked() {
+    let schema = "[down.SpeedCommand]\nvx = {type = \"i16\", scale = 0.001}\n\
+                  vy = {type = \"f32\", scale = 0.001}\nvtheta = {type = \"i16\", scale = 0}\n\
+                  ticks = {type = \"i64\", scale = 0.5}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            "down.SpeedCommand.vy",
+            "down.SpeedCommand.vtheta",
+            "down.SpeedCommand.ticks"
+        ]
+    );
+    assert!(errs[2]
+        .hint
+        .as_deref()
+        .unwrap()
+        .contains("not to i64 and u64"));
+}
+
+#[test]
+fn defaults_are_checked() {
+    let schema = "[enums.DriveMode]\nStop = 0\n\n[down.PIDGains]\n\
+                  kp = {type = \"f32\", default = 1.5}\nki = {type = \"u8\", default = 300}\n\
+                  kd = {type = \"f32\", default = \"fast\"}\nmode = {type = \"DriveMode\", default = \"Run\"}\n\
+                  name = {type = \"chars\", size = 4, default = \"abcd\"}\n\
+                  gains = {type = \"i8\", len = 2, default = [1, 2, 3]}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            "down.PIDGains.ki",
+            "down.PIDGains.kd",
+            "down.PIDGains.mode",
+            "down.PIDGains.name",
+            "down.PIDGains.gains"
+        ]
+    );
+}
+
+#[test]
+fn constants_are_checked_and_resolved() {
+    let schema = "[constants]\nNB_MOTORS = 3\nMAX_SPEED = 1.5\nUID = 2\n\
+                  MAX_TICKS = {type = \"u8\", value = 300}\nFLAG = true\n\n\
+                  [down.Motors]\nspeeds = {type = \"f32\", len = \"NB_MOTORS\", max = \"MAX_SPEED\"}\n\
+                  ticks = {type = \"u16\", max = \"MAX_TICKS\"}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            "constants.UID",
+            "constants.MAX_TICKS",
+            "constants.FLAG",
+            "down.Motors.ticks"
+        ]
+    );
+
+    let schema = "[constants]\nNB_MOTORS = 3\nMAX_SPEED = 1.5\n\n\
+                  [down.Motors]\nspeeds = {type = \"f32\", len = \"NB_MOTORS\", max = \"MAX_SPEED\"}\n";
+    let schema = parse_toml(schema, &IdLock::default()).unwrap();
+    match &schema.messages[0].fields[0].t {
+        Type::Array(elt, 3) => assert!(matches!(&**elt, Type::F32(b) if b.max == 1.5)),
+        t => panic!("unexpected type {:?}", t),
+    }
+    // Constants are converted to the kind of the field, unless they would change.
+    let schema = "[constants]\nNB_MOTORS = 3.0\nMAX_SPEED = 2\nHALF = 0.5\n\n\
+                  [down.Motors]\nspeeds = {type = \"f32\", len = \"NB_MOTORS\", max = \"MAX_SPEED\"}\n\
+                  ticks = {type = \"u16\", max = \"HALF\"}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, vec!["down.Motors.ticks"]);
+
+    let schema = schema.replace("max = \"HALF\"", "max = \"NB_MOTORS\"");
+    let schema = parse_toml(&schema, &IdLock::default()).unwrap();
+    match &schema.messages[0].fields[0].t {
+        Type::Array(elt, 3) => assert!(matches!(&**elt, Type::F32(b) if b.max == 2.0)),
+        t => panic!("unexpected type {:?}", t),
+    }
+    assert!(matches!(&schema.messages[0].fields[1].t, Type::U16(b) if b.max == 3));
+}
+
+#[test]
+fn constants_dont_shadow_generated_names() {
+    let schema = "[constants]\nDRIVE_MODE_STOP = 1\nSIZE_UpOdom = 2\nMIN_SIZE_UpOdom = 3\n\
+                  ID_UpOdom = 4\nmatch = 5\nNone = 6\nNULL = 7\nMESSAGES_H = 8\nSIZE_Odom = 9\n\n\
+                  [enums.DriveMode]\nStop = 0\n\n\
+                  [up.Odom]\nx = \"f32\"\nmode = \"DriveMode\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let located = errs
+        .iter()
+        .map(|d| (d.path.as_str(), d.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        located,
+        vec![
+            ("constants.match", "reserved constant name"),
+            ("constants.None", "reserved constant name"),
+            ("constants.NULL", "reserved constant name"),
+            ("constants.MESSAGES_H", "reserved constant name"),
+            (
+                "constants.DRIVE_MODE_STOP",
+                "DRIVE_MODE_STOP is also generated for enums.DriveMode.Stop"
+            ),
+            (
+                "constants.SIZE_UpOdom",
+                "SIZE_UpOdom is also generated for up.Odom"
+            ),
+            (
+                "constants.MIN_SIZE_UpOdom",
+                "MIN_SIZE_UpOdom is also generated for up.Odom"
+            ),
+            (
+                "constants.ID_UpOdom",
+                "ID_UpOdom is also generated for up.Odom"
+            ),
+        ]
+    );
+    assert_eq!(errs[0].span.as_ref().map(|s| s.line), Some(6));
+}
+
+#[test]
+fn docs_are_parsed() {
+    let schema = "[down.SpeedCommand]\ndoc = \"Speed setpoint.\"\n\
+                  vx = {type = \"f32\", doc = \"Forward speed\", unit = \"m/s\"}\n\
+                  vy = {type = \"f32\", unit = 3}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, vec!["down.SpeedCommand.vy"]);
+
+    // A field can still be named doc when given as a table.
+    let schema = "[down.Note]\ndoc = {type = \"u8\"}\n\n\
+                  [down.Speed]\ndoc = \"Speed setpoint.\"\nunit = \"m/s\"\nv = \"f32\"\n";
+    let schema = parse_toml(schema, &IdLock::default()).unwrap();
+    assert_eq!(schema.messages[0].fields[0].name, "doc");
+    assert_eq!(schema.messages[0].doc, None);
+    assert_eq!(
+        schema.messages[1].description().as_deref(),
+        Some("Speed setpoint. [m/s]")
+    );
+
+    // Given as a type name, the field would silently become the description.
+    let schema =
+        "[enums.Mode]\nStop = 0\n\n[down.Beep]\ndoc = \"u8\"\nunit = \"Mode\"\nn = \"u8\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, vec!["down.Beep.doc", "down.Beep.unit"]);
+}
+
+#[test]
+fn files_share_one_namespace() {
+    let common = "[types.Pose]\nx = \"f32\"\ny = \"f32\"\n";
+    let drive = "include = [\"common.toml\"]\n\n[up.Odom]\nid = 3\npose = \"Pose\"\n";
+    let arm = "[types.Pose]\nx = \"f32\"\n\n[up.Arm]\nid = 4\nangle = \"f32\"\n";
+    let errs = parse_files(
+        &[
+            ("common.toml", common),
+            ("drive.toml", drive),
+            ("arm.toml", arm),
+        ],
+        &IdLock::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].path, "types.Pose");
+    assert_eq!(errs[0].file.as_deref(), Some("arm.toml"));
+    assert_eq!(errs[0].message, "already declared in common.toml");
+
+    let arm = "[up.Arm]\nid = 3\nangle = \"f32\"\n";
+    let errs = parse_files(
+        &[
+            ("common.toml", common),
+            ("drive.toml", drive),
+            ("arm.toml", arm),
+        ],
+        &IdLock::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].path, "up.Arm.id");
+    assert_eq!(errs[0].file.as_deref(), Some("arm.toml"));
+    assert_eq!(errs[0].span.as_ref().map(|s| s.line), Some(2));
+    assert_eq!(
+        errs[0].message,
+        "id 3 is already used by UpOdom (drive.toml)"
+    );
+
+    let arm = "[up.Arm]\nangle = \"f32\"\n";
+    let schema = parse_files(
+        &[
+            ("common.toml", common),
+            ("drive.toml", drive),
+            ("arm.toml", arm),
+        ],
+        &IdLock::default(),
+    )
+    .unwrap();
+    let names = schema
+        .messages
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["UpOdom", "UpArm"]);
+    assert_eq!(schema.structs.len(), 1);
+}
+
+#[test]
+fn generated_names_are_unique() {
+    let drive = "[up.odom_report]\nx = \"f32\"\n";
+    let arm = "[up.OdomReport]\ny = \"f32\"\n";
+    let errs = parse_files(
+        &[("drive.toml", drive), ("arm.toml", arm)],
+        &IdLock::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].path, "up.OdomReport");
+    assert_eq!(errs[0].file.as_deref(), Some("arm.toml"));
+    assert_eq!(
+        errs[0].message,
+        "generates UpOdomReport like up.odom_report (drive.toml)"
+    );
+
+    let common = "[enums.arm_mode]\nIDLE = 0\nMOVING = 1\n";
+    let arm = "[types.ArmMode]\nx = \"f32\"\n\n[up.Arm]\nangle = \"f32\"\n";
+    let errs = parse_files(
+        &[("common.toml", common), ("arm.toml", arm)],
+        &IdLock::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].path, "types.ArmMode");
+    assert_eq!(errs[0].file.as_deref(), Some("arm.toml"));
+    assert_eq!(
+        errs[0].message,
+        "generates ArmMode like enums.arm_mode (common.toml)"
+    );
+}
+
+#[test]
+fn lengths_are_bounded() {
+    let schema = "[constants]\nHUGE = 4611686018427387904\n\n\
+                  [types.Wide]\nv = {type = \"u64\", len = 200}\n\n\
+                  [types.Point]\nx = {type = \"u64\", len = 30}\n\n\
+                  [down.Big]\na = {type = \"u64\", len = 4611686018427387904}\n\
+                  b = {type = \"u8\", len = \"HUGE\"}\nc = {type = \"chars\", size = 254}\n\n\
+                  [down.Points]\np = {type = \"Point\", len = 253}\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let paths = errs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            "types.Wide",
+            "down.Big.a",
+            "down.Big.b",
+            "down.Big.c",
+            "down.Points"
+        ]
+    );
+    assert_eq!(errs[4].value.as_deref(), Some("60720"));
+}
+
+#[test]
+fn lengths_of_variable_fields_are_reserved() {
+    let schema = "[up.Log]\ntext = {type = \"chars\", max_len = 8}\ntext_len = \"u8\"\n\n\
+                  [up.Path]\npoints_len = \"u8\"\npoints = {type = \"i16\", max_len = 4}\n\
+                  size_len = \"u8\"\n";
+    let errs = parse_toml(schema, &IdLock::default()).unwrap_err();
+    let located = errs
+        .iter()
+        .map(|d| (d.path.as_str(), d.span.as_ref().map(|s| s.line)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        located,
+        vec![
+            ("up.Log.text_len", Some(3)),
+            ("up.Path.points_len", Some(6))
+        ]
+    );
+    assert_eq!(errs[0].message, "clashes with the length of text");
+}
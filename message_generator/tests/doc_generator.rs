@@ -0,0 +1,18 @@
+//! Checks the protocol documentation emitted for the test schema.
+
+mod common;
+
+use common::{generate, test_schema};
+use std::fs;
+
+#[test]
+fn protocol_doc_lists_offsets() {
+    let root = generate("doc", &test_schema(), &["Doc"], &[]);
+
+    let doc = fs::read_to_string(root.join("doc/protocol.md")).unwrap();
+    assert!(doc.contains("| 0 | [InterMcuUid](#intermcuuid) | inter_mcu | both ways | 10 |"));
+    assert!(doc.contains("| 0 | currents | i16[3], scale 0.001 | -32.768 to 32.767 |"));
+    assert!(doc.contains("frame of 7 bytes (payload of 1 byte)"));
+
+    fs::remove_dir_all(&root).ok();
+}
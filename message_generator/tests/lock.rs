@@ -0,0 +1,136 @@
+//! Checks that message ids are pinned by the lock file across schema changes.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A schema generated again and again in the same directory, along with its lock file.
+struct Project {
+    work: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str) -> Project {
+        let root = env::temp_dir().join(format!("ducklink_lock_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        // The C code is written in ../lib/C/messages, relative to the working directory.
+        let work = root.join("work");
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(root.join("lib/C/messages")).unwrap();
+        Project { work }
+    }
+
+    /// Generate `schema`, and return the ids pinned in the lock file, sorted by id.
+    /// On failure, return what the generator printed.
+    fn generate(&self, schema: &str) -> Result<Vec<(String, usize)>, String> {
+        self.generate_named("schema", schema)
+    }
+
+    /// Same as `generate`, for the schema `<name>.toml` locked by `<name>.lock`.
+    fn generate_named(&self, name: &str, schema: &str) -> Result<Vec<(String, usize)>, String> {
+        let file = format!("{}.toml", name);
+        fs::write(self.work.join(&file), schema).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_message_generator"))
+            .current_dir(&self.work)
+            .args([file.as_str(), "-l", "C"])
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+
+        let lock = fs::read_to_string(self.work.join(format!("{}.lock", name))).unwrap();
+        Ok(lock
+            .lines()
+            .filter_map(|line| {
+                let (name, id) = line.split_once(" = ")?;
+                Some((name.to_string(), id.parse().unwrap()))
+            })
+            .collect())
+    }
+}
+
+fn named(ids: &[(&str, usize)]) -> Vec<(String, usize)> {
+    ids.iter()
+        .map(|(name, id)| (name.to_string(), *id))
+        .collect()
+}
+
+#[test]
+fn pinned_ids_survive_new_messages() {
+    let project = Project::new("new_messages");
+    let schema = "[up.Odom]\nx = \"f32\"\n\n[up.Speed]\nv = \"f32\"\n";
+    assert_eq!(
+        project.generate(schema),
+        Ok(named(&[("UpOdom", 1), ("UpSpeed", 2)]))
+    );
+
+    // Declared first, the new message still doesn't renumber the others.
+    let schema = "[up.Arm]\nangle = \"f32\"\n\n[up.Odom]\nx = \"f32\"\n\n[up.Speed]\nv = \"f32\"\n";
+    assert_eq!(
+        project.generate(schema),
+        Ok(named(&[("UpOdom", 1), ("UpSpeed", 2), ("UpArm", 3)]))
+    );
+}
+
+#[test]
+fn fresh_ids_skip_removed_ones() {
+    let project = Project::new("removed_messages");
+    let schema = "[up.Odom]\nx = \"f32\"\n\n[up.Speed]\nv = \"f32\"\n\n[up.Arm]\nangle = \"f32\"\n";
+    assert_eq!(
+        project.generate(schema),
+        Ok(named(&[("UpOdom", 1), ("UpSpeed", 2), ("UpArm", 3)]))
+    );
+
+    // The id of the removed message is kept in the lock, so it is not reused.
+    let schema =
+        "[up.Odom]\nx = \"f32\"\n\n[up.Arm]\nangle = \"f32\"\n\n[up.Gripper]\nopen = \"u8\"\n";
+    assert_eq!(
+        project.generate(schema),
+        Ok(named(&[
+            ("UpOdom", 1),
+            ("UpSpeed", 2),
+            ("UpArm", 3),
+            ("UpGripper", 4)
+        ]))
+    );
+}
+
+#[test]
+fn schemas_of_one_directory_have_their_own_lock() {
+    let project = Project::new("two_schemas");
+    let robot = "[up.Odom]\nx = \"f32\"\n\n[up.Speed]\nv = \"f32\"\n";
+    assert_eq!(
+        project.generate_named("robot", robot),
+        Ok(named(&[("UpOdom", 1), ("UpSpeed", 2)]))
+    );
+
+    // An unrelated schema next to it numbers its messages from 1 too.
+    let lidar = "[up.Scan]\nangle = \"f32\"\n\n[down.Start]\nspeed = \"u8\"\n";
+    assert_eq!(
+        project.generate_named("lidar", lidar),
+        Ok(named(&[("UpScan", 1), ("DownStart", 2)]))
+    );
+    assert_eq!(
+        project.generate_named("robot", robot),
+        Ok(named(&[("UpOdom", 1), ("UpSpeed", 2)]))
+    );
+}
+
+#[test]
+fn out_of_range_ids_are_rejected() {
+    let project = Project::new("out_of_range");
+    fs::write(
+        project.work.join("schema.lock"),
+        "[ids]\nUpOdom = 1\nUpSpeed = 256\nUpArm = 0\n",
+    )
+    .unwrap();
+    let errs = project.generate("[up.Odom]\nx = \"f32\"\n").unwrap_err();
+    assert!(
+        errs.contains("UpSpeed") && errs.contains("UpArm"),
+        "{}",
+        errs
+    );
+    assert!(!errs.contains("UpOdom"), "{}", errs);
+}
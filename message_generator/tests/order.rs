@@ -0,0 +1,64 @@
+//! Checks the order in which messages and fields are laid out, and given their ids.
+
+use ducklink_codegen::lock::IdLock;
+use ducklink_codegen::parse_toml;
+
+const SCHEMA: &str = r#"
+[up.Odom]
+y = "f32"
+x = "f32"
+
+[up.Battery]
+voltage = "u16"
+
+[down.Stop]
+now = "u8"
+"#;
+
+/// Name and id of each message, with the names of its fields.
+fn layout(schema: &str) -> Vec<(String, usize, Vec<String>)> {
+    let schema = parse_toml(schema, &IdLock::default()).unwrap();
+    schema
+        .messages
+        .iter()
+        .map(|msg| {
+            let fields = msg.fields.iter().map(|f| f.name.clone()).collect();
+            (msg.name.clone(), msg.id, fields)
+        })
+        .collect()
+}
+
+fn named(layout: &[(&str, usize, &[&str])]) -> Vec<(String, usize, Vec<String>)> {
+    layout
+        .iter()
+        .map(|(name, id, fields)| {
+            let fields = fields.iter().map(|f| f.to_string()).collect();
+            (name.to_string(), *id, fields)
+        })
+        .collect()
+}
+
+#[test]
+fn declaration_order_is_kept() {
+    assert_eq!(
+        layout(SCHEMA),
+        named(&[
+            ("UpOdom", 1, &["y", "x"]),
+            ("UpBattery", 2, &["voltage"]),
+            ("DownStop", 3, &["now"]),
+        ])
+    );
+}
+
+#[test]
+fn alphabetical_order_can_be_restored() {
+    let schema = format!("[options]\norder = \"alphabetical\"\n{}", SCHEMA);
+    assert_eq!(
+        layout(&schema),
+        named(&[
+            ("DownStop", 1, &["now"]),
+            ("UpBattery", 2, &["voltage"]),
+            ("UpOdom", 3, &["x", "y"]),
+        ])
+    );
+}
@@ -0,0 +1,942 @@
+//! Runs the C, C++, Rust and Python code generated for small schemas, and checks what
+//! they send and decode. Skipped if no C or C++ compiler is found (set `CC` or `CXX`).
+//!
+//! The Python tests need the `bitstring` package, that the generated messages import:
+//! they are ignored by default, run them with `cargo test -- --ignored` where it is
+//! installed (set `PYTHON` to choose the interpreter).
+
+mod common;
+
+use common::{compiler, generate};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Languages the schemas are generated for.
+const LANGS: &[&str] = &["C", "CPP", "Rust", "Python"];
+
+/// Run `command`, and return its standard output.
+fn output(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed:\n{}{}",
+        command,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Build `main` with the C messages generated in `root`, run it and return its output.
+fn run_c(cc: &str, root: &Path, main: &str) -> String {
+    fs::write(root.join("main.c"), main).unwrap();
+    output(
+        Command::new(cc)
+            .args(["-std=c99", "-Wall", "-Werror", "-I"])
+            .arg(root.join("c"))
+            .arg(root.join("c/messages.c"))
+            .arg(root.join("main.c"))
+            .arg("-o")
+            .arg(root.join("main_c")),
+    );
+    output(&mut Command::new(root.join("main_c")))
+}
+
+/// Build `main` with the C++ messages generated in `root`, run it and return its output.
+fn run_cpp(cxx: &str, root: &Path, main: &str) -> String {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    fs::write(root.join("main.cpp"), main).unwrap();
+    output(
+        Command::new(cxx)
+            .args(["-std=c++11", "-Wall", "-Werror", "-I"])
+            .arg(manifest_dir.join("../lib/CPP/messages"))
+            .arg("-I")
+            .arg(root.join("cpp"))
+            .arg(root.join("cpp/messages.cpp"))
+            .arg(root.join("main.cpp"))
+            .arg("-o")
+            .arg(root.join("main_cpp")),
+    );
+    output(&mut Command::new(root.join("main_cpp")))
+}
+
+/// Run `main` with the Python messages generated in `root`, and return its output.
+fn run_python(root: &Path, main: &str) -> String {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let python = env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    fs::write(root.join("main.py"), main).unwrap();
+    let path = env::join_paths([
+        root.join("python"),
+        manifest_dir.join("../lib/Python/messages"),
+    ])
+    .unwrap();
+    output(
+        Command::new(python)
+            .arg(root.join("main.py"))
+            .env("PYTHONPATH", path),
+    )
+}
+
+/// Run `main` with the Rust crate generated in `root`, and return its output.
+fn run_rust(root: &Path, main: &str) -> String {
+    let bin = root.join("main_rs");
+    fs::create_dir_all(&bin).unwrap();
+    fs::write(
+        bin.join("Cargo.toml"),
+        "[package]\nname = \"main_rs\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [dependencies]\nducklink_messages = { path = \"../rust\" }\n",
+    )
+    .unwrap();
+    fs::create_dir_all(bin.join("src")).unwrap();
+    fs::write(bin.join("src/main.rs"), main).unwrap();
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    output(
+        Command::new(cargo)
+            .args(["run", "--quiet", "--offline", "--manifest-path"])
+            .arg(bin.join("Cargo.toml"))
+            .env("CARGO_TARGET_DIR", root.join("target")),
+    )
+}
+
+/// Prints `n` bytes of `buf` in hex, in C and C++.
+const PRINT_C: &str = "static void print(const uint8_t *buf, int n) {\n  \
+                       for(int i=0; i<n; i++) {\n    \
+                       printf(\"%02x \", buf[i]);\n  \
+                       }\n  \
+                       printf(\"\\n\");\n\
+                       }\n";
+
+/// Prints the bytes of `buf` in hex, in Rust.
+const PRINT_RS: &str = "fn print(buf: &[u8]) {\n    \
+                        for b in buf {\n        \
+                        print!(\"{:02x} \", b);\n    \
+                        }\n    \
+                        println!();\n\
+                        }\n";
+
+const NESTED_SCHEMA: &str = "[types.Inner]\na = \"u8\"\narr = {type = \"u16\", len = 3}\n\n\
+                             [types.Outer]\narr = {type = \"Inner\", len = 2}\nk = \"u8\"\n\n\
+                             [down.Nested]\nvs = {type = \"Outer\", len = 2}\n";
+
+/// Payload of `Nested`: 2 Outer of 2 Inner of 7 bytes plus 1 byte, every byte different.
+const NESTED_PAYLOAD: &str = "{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, \
+                              20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30}";
+
+#[test]
+fn nested_arrays_of_structs_round_trip() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("nested", NESTED_SCHEMA, LANGS, &[]);
+
+    // Decode the payload, then encode it back into a frame.
+    let c = run_c(
+        &cc,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n{print}\n\
+             int main(void) {{\n  \
+             uint8_t payload[] = {payload};\n  \
+             union Message_t msg;\n  \
+             if(!down_nested_from_bytes(&msg, payload, sizeof(payload))) {{\n    return 1;\n  }}\n  \
+             uint8_t frame[MAX_MSG_BUFFER_SIZE];\n  \
+             print(frame, down_nested_to_bytes(&msg.down_nested, frame));\n  \
+             return 0;\n\
+             }}\n",
+            print = PRINT_C,
+            payload = NESTED_PAYLOAD
+        ),
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n{print}\n\
+             int main() {{\n  \
+             uint8_t payload[] = {payload};\n  \
+             DownNested msg(payload);\n  \
+             uint8_t frame[DownNested::SIZE];\n  \
+             print(frame, msg.to_bytes(frame));\n  \
+             return 0;\n\
+             }}\n",
+            print = PRINT_C,
+            payload = NESTED_PAYLOAD
+        ),
+    );
+    let rust = run_rust(
+        &root,
+        &format!(
+            "use ducklink_messages::DownNested;\n\n{print}\n\
+             fn main() {{\n    \
+             let payload: Vec<u8> = (1..=30).collect();\n    \
+             let msg = DownNested::from_bytes(&payload).unwrap();\n    \
+             let mut frame = [0; DownNested::SIZE];\n    \
+             let len = msg.to_bytes(&mut frame).unwrap();\n    \
+             print(&frame[..len]);\n\
+             }}\n",
+            print = PRINT_RS
+        ),
+    );
+
+    let payload = (1..=30).map(|b| format!("{:02x} ", b)).collect::<String>();
+    assert!(
+        rust.starts_with(&format!("ff ff 01 20 {}", payload)),
+        "{}",
+        rust
+    );
+    assert_eq!(c, rust);
+    assert_eq!(cpp, rust);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+const TEXT_SCHEMA: &str = "[down.Text]\ntext = {type = \"chars\", max_len = 100}\n";
+
+/// A frame with the smallest length byte of `Text`, whose inner length claims 64 chars.
+/// It follows a valid frame of 64 chars, that left them in the parser buffer.
+const SHORT_FRAME: &str = "uint8_t bad[] = {0xFF, 0xFF, ID, 3, 64, 0, 0};\n  \
+                           uint16_t ck = CHECKSUM(bad + 2, 3);\n  \
+                           bad[5] = ck & 0xFF;\n  \
+                           bad[6] = ck >> 8;\n";
+
+#[test]
+fn var_len_beyond_the_payload_is_rejected() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("short", TEXT_SCHEMA, LANGS, &[]);
+
+    // Prints whether each frame is decoded or rejected.
+    let c = run_c(
+        &cc,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n\
+             static void feed(struct DuckParser *parser, const uint8_t *frame, int n) {{\n  \
+             struct TagMessage msg;\n  \
+             for(int i=0; i<n; i++) {{\n    \
+             enum DuckParserStatus status = duck_parser_parse(parser, frame[i], &msg);\n    \
+             if(status == DUCK_COMPLETE) {{\n      \
+             printf(\"decoded %d\\n\", msg.msg.down_text.text_len);\n    \
+             }} else if(status != DUCK_INCOMPLETE) {{\n      \
+             printf(\"rejected %d\\n\", status == DUCK_BAD_VALUE);\n    \
+             }}\n  \
+             }}\n\
+             }}\n\n\
+             int main(void) {{\n  \
+             struct DuckParser parser;\n  \
+             duck_parser_init(&parser);\n  \
+             struct DownText text;\n  \
+             memset(&text, 0, sizeof(text));\n  \
+             text.text_len = 64;\n  \
+             uint8_t frame[MAX_MSG_BUFFER_SIZE];\n  \
+             feed(&parser, frame, down_text_to_bytes(&text, frame));\n  \
+             {bad}  \
+             feed(&parser, bad, sizeof(bad));\n  \
+             return 0;\n\
+             }}\n",
+            bad = SHORT_FRAME
+                .replace("ID", "ID_DownText")
+                .replace("CHECKSUM", "compute_cheksum")
+        ),
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n\
+             static void feed(DuckParser &parser, const uint8_t *frame, int n) {{\n  \
+             MsgStorage storage;\n  \
+             for(int i=0; i<n; i++) {{\n    \
+             if(parser.parse(frame[i]) == DuckParser::COMPLETE) {{\n      \
+             DuckMsg *msg = make_msg(parser.msg_id(), parser.payload(), parser.payload_len(), &storage);\n      \
+             if(msg) {{\n        \
+             printf(\"decoded %d\\n\", static_cast<DownText*>(msg)->get_text_len());\n      \
+             }} else {{\n        \
+             printf(\"rejected 1\\n\");\n      \
+             }}\n    \
+             }}\n  \
+             }}\n\
+             }}\n\n\
+             int main() {{\n  \
+             DuckParser parser;\n  \
+             DownText text;\n  \
+             char chars[64] = {{0}};\n  \
+             text.set_text(chars, 64);\n  \
+             uint8_t frame[DownText::SIZE];\n  \
+             feed(parser, frame, text.to_bytes(frame));\n  \
+             {bad}  \
+             feed(parser, bad, sizeof(bad));\n  \
+             return 0;\n\
+             }}\n",
+            bad = SHORT_FRAME
+                .replace("ID", "DownText::ID")
+                .replace("CHECKSUM", "DuckMsg::compute_cheksum")
+        ),
+    );
+
+    assert_eq!(c, "decoded 64\nrejected 1\n");
+    assert_eq!(cpp, "decoded 64\nrejected 1\n");
+
+    fs::remove_dir_all(&root).ok();
+}
+
+/// Decodes a payload of 64 chars, then a payload whose length byte claims 64 chars.
+/// The decoder is given the payload followed by the checksum, as `SerialCom` does.
+const TEXT_PY: &str = "from messages import DownText
+
+def feed(payload):
+    msg = DownText()
+    try:
+        msg.deserialize(bytes(payload + [0, 0]))
+        print('decoded {}'.format(len(msg.text)))
+    except ValueError:
+        print('rejected 1')
+
+feed([64] + [0] * 64)
+feed([64])
+";
+
+#[test]
+#[ignore = "needs Python with bitstring"]
+fn python_var_len_beyond_the_payload_is_rejected() {
+    let root = generate("short_py", TEXT_SCHEMA, LANGS, &[]);
+    assert_eq!(run_python(&root, TEXT_PY), "decoded 64\nrejected 1\n");
+    fs::remove_dir_all(&root).ok();
+}
+
+const BIG_ENDIAN_SCHEMA: &str = "[options]\nendianness = \"big\"\n\n\
+                                 [down.Wide]\nid = 1\na = \"u16\"\nb = \"i32\"\nc = \"u64\"\nd = \"f32\"\n";
+
+#[test]
+fn big_endian_fields_are_sent_most_significant_byte_first() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("big_endian", BIG_ENDIAN_SCHEMA, LANGS, &[]);
+
+    let c = run_c(
+        &cc,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n{print}\n\
+             int main(void) {{\n  \
+             struct DownWide msg;\n  \
+             init_down_wide(&msg);\n  \
+             msg.a = 0x0102;\n  \
+             msg.b = -2;\n  \
+             msg.c = 0x030405060708090AULL;\n  \
+             msg.d = 1.5f;\n  \
+             uint8_t frame[SIZE_DownWide];\n  \
+             print(frame, down_wide_to_bytes(&msg, frame));\n  \
+             return 0;\n\
+             }}\n",
+            print = PRINT_C
+        ),
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n{print}\n\
+             int main() {{\n  \
+             DownWide msg;\n  \
+             msg.set_a(0x0102);\n  \
+             msg.set_b(-2);\n  \
+             msg.set_c(0x030405060708090AULL);\n  \
+             msg.set_d(1.5f);\n  \
+             uint8_t frame[DownWide::SIZE];\n  \
+             print(frame, msg.to_bytes(frame));\n  \
+             return 0;\n\
+             }}\n",
+            print = PRINT_C
+        ),
+    );
+    let rust = run_rust(
+        &root,
+        &format!(
+            "use ducklink_messages::DownWide;\n\n{print}\n\
+             fn main() {{\n    \
+             let mut msg = DownWide::new();\n    \
+             msg.set_a(0x0102);\n    \
+             msg.set_b(-2);\n    \
+             msg.set_c(0x0304_0506_0708_090A);\n    \
+             msg.set_d(1.5);\n    \
+             let mut frame = [0; DownWide::SIZE];\n    \
+             let len = msg.to_bytes(&mut frame).unwrap();\n    \
+             print(&frame[..len]);\n\
+             }}\n",
+            print = PRINT_RS
+        ),
+    );
+
+    // 1.5 is 0x3FC00000 as a f32.
+    let payload = "01 02 ff ff ff fe 03 04 05 06 07 08 09 0a 3f c0 00 00 ";
+    assert!(
+        rust.starts_with(&format!("ff ff 01 14 {}", payload)),
+        "{}",
+        rust
+    );
+    assert_eq!(c, rust);
+    assert_eq!(cpp, rust);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+/// Python isn't run without bitstring, but the byte order is all in the formats it is
+/// given to pack and unpack the fields.
+#[test]
+fn big_endian_fields_are_packed_most_significant_byte_first_in_python() {
+    let root = generate("big_endian_py", BIG_ENDIAN_SCHEMA, &["Python"], &[]);
+    let py = fs::read_to_string(root.join("python/messages.py")).unwrap();
+    // The id and the length are single bytes.
+    assert!(
+        py.contains("'uintle:8, uintle:8, uintbe:16, intbe:32, uintbe:64, floatbe:32'"),
+        "{}",
+        py
+    );
+    assert!(
+        py.contains("s.unpack('uintbe:16, intbe:32, uintbe:64, floatbe:32')"),
+        "{}",
+        py
+    );
+    fs::remove_dir_all(&root).ok();
+}
+
+const PATH_SCHEMA: &str = "[down.Path]\nxs = {type = \"i16\", len = 2}\n\
+                           ys = {type = \"i16\", max_len = 4}\n";
+
+#[test]
+fn cpp_indexed_accessors_are_bounded() {
+    let cxx = match compiler("CXX", "g++") {
+        Some(cxx) => cxx,
+        None => {
+            eprintln!("No C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("indexed", PATH_SCHEMA, &["CPP"], &[]);
+
+    // Out of range, setters do nothing and getters return 0. Variable length arrays are
+    // bounded by their current length.
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         int main() {\n  \
+         DownPath msg;\n  \
+         msg.set_xs(1, 5);\n  \
+         msg.set_xs(2, 6);\n  \
+         printf(\"%d %d\\n\", msg.get_xs(1), msg.get_xs(2));\n  \
+         msg.set_ys(0, 7);\n  \
+         msg.set_ys_len(2);\n  \
+         msg.set_ys(1, 8);\n  \
+         msg.set_ys(2, 9);\n  \
+         printf(\"%d %d %d\\n\", msg.get_ys(0), msg.get_ys(1), msg.get_ys(2));\n  \
+         return 0;\n\
+         }\n",
+    );
+    assert_eq!(cpp, "5 0\n0 8 0\n");
+
+    fs::remove_dir_all(&root).ok();
+}
+
+const QUANTIZE_SCHEMA: &str = "[down.Level]\nv = {type = \"i8\", scale = 0.5}\n";
+
+/// Values quantized by every language: ties, negative, out of range and NaN.
+const QUANTIZED: &[&str] = &[
+    "0.25", "-0.25", "0.75", "-0.75", "-1.1", "100", "-100", "NAN",
+];
+
+#[test]
+fn scaled_values_are_quantized_alike() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("quantize", QUANTIZE_SCHEMA, &["C", "CPP"], &[]);
+
+    // duck_quantize is private to messages.c, so C goes through the setter of the field.
+    let c = run_c(
+        &cc,
+        &root,
+        &format!(
+            "#include <math.h>\n#include <stdio.h>\n#include \"messages.h\"\n\n\
+             int main(void) {{\n  \
+             double values[] = {{{values}}};\n  \
+             struct DownLevel msg;\n  \
+             for(unsigned i=0; i<sizeof(values)/sizeof(values[0]); i++) {{\n    \
+             down_level_set_v(&msg, values[i]);\n    \
+             printf(\"%d\\n\", msg.v);\n  \
+             }}\n  \
+             return 0;\n\
+             }}\n",
+            values = QUANTIZED.join(", ")
+        ),
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        &format!(
+            "#include <math.h>\n#include <stdio.h>\n#include \"messages.h\"\n\n\
+             int main() {{\n  \
+             double values[] = {{{values}}};\n  \
+             for(double v: values) {{\n    \
+             printf(\"%d\\n\", (int)DuckMsg::quantize(v, 0.5, 0.0, -128.0, 127.0));\n  \
+             }}\n  \
+             return 0;\n\
+             }}\n",
+            values = QUANTIZED.join(", ")
+        ),
+    );
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let python = env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    let py = output(
+        Command::new(python)
+            .arg("-c")
+            .arg(format!(
+                "from duckmsg import quantize\n\
+                 for v in [{}]:\n    \
+                 print(quantize(v, 0.5, 0.0, -128.0, 127.0))\n",
+                QUANTIZED.join(", ").replace("NAN", "float('nan')")
+            ))
+            .env("PYTHONPATH", manifest_dir.join("../lib/Python/messages")),
+    );
+
+    assert_eq!(c, "1\n0\n2\n-1\n-2\n127\n-128\n-128\n");
+    assert_eq!(cpp, c);
+    assert_eq!(py, c);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+const DISPATCH_SCHEMA: &str = "[up.Odom]\nx = \"i16\"\n\n[down.Stop]\nnow = \"u8\"\n";
+
+/// What the handlers print for an `Odom` frame with x = -3, then a `Stop` frame with now = 7.
+const DISPATCHED: &str = "odom -3\nstop 7\n";
+
+#[test]
+fn decoded_frames_are_dispatched_to_their_handler() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("dispatch", DISPATCH_SCHEMA, LANGS, &[]);
+
+    // Encode each message, then decode the frame and dispatch it.
+    let c = run_c(
+        &cc,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         static void on_odom(struct UpOdom *msg) {\n  printf(\"odom %d\\n\", msg->x);\n}\n\n\
+         static void on_stop(struct DownStop *msg) {\n  printf(\"stop %d\\n\", msg->now);\n}\n\n\
+         static void feed(struct DuckParser *parser, struct DuckHandlers *handlers, const uint8_t *frame, int n) {\n  \
+         struct TagMessage msg;\n  \
+         for(int i=0; i<n; i++) {\n    \
+         if(duck_parser_parse(parser, frame[i], &msg) == DUCK_COMPLETE) {\n      \
+         dispatch_msg(handlers, &msg);\n    \
+         }\n  \
+         }\n\
+         }\n\n\
+         int main(void) {\n  \
+         struct DuckParser parser;\n  \
+         duck_parser_init(&parser);\n  \
+         struct DuckHandlers handlers;\n  \
+         memset(&handlers, 0, sizeof(handlers));\n  \
+         handlers.on_up_odom = on_odom;\n  \
+         handlers.on_down_stop = on_stop;\n  \
+         uint8_t frame[MAX_MSG_BUFFER_SIZE];\n  \
+         struct UpOdom odom;\n  \
+         init_up_odom(&odom);\n  \
+         odom.x = -3;\n  \
+         feed(&parser, &handlers, frame, up_odom_to_bytes(&odom, frame));\n  \
+         struct DownStop stop;\n  \
+         init_down_stop(&stop);\n  \
+         stop.now = 7;\n  \
+         feed(&parser, &handlers, frame, down_stop_to_bytes(&stop, frame));\n  \
+         return 0;\n\
+         }\n",
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         class Printer: public DuckHandler {\n\
+         public:\n  \
+         using DuckHandler::on;\n  \
+         void on(const UpOdom &msg) override { printf(\"odom %d\\n\", msg.get_x()); }\n  \
+         void on(const DownStop &msg) override { printf(\"stop %d\\n\", msg.get_now()); }\n\
+         };\n\n\
+         static void feed(DuckParser &parser, Printer &printer, const uint8_t *frame, int n) {\n  \
+         MsgStorage storage;\n  \
+         for(int i=0; i<n; i++) {\n    \
+         if(parser.parse(frame[i]) == DuckParser::COMPLETE) {\n      \
+         DuckMsg *msg = make_msg(parser.msg_id(), parser.payload(), parser.payload_len(), &storage);\n      \
+         if(msg) {\n        \
+         printer.dispatch(msg);\n      \
+         }\n    \
+         }\n  \
+         }\n\
+         }\n\n\
+         int main() {\n  \
+         DuckParser parser;\n  \
+         Printer printer;\n  \
+         uint8_t frame[UpOdom::SIZE];\n  \
+         UpOdom odom;\n  \
+         odom.set_x(-3);\n  \
+         feed(parser, printer, frame, odom.to_bytes(frame));\n  \
+         DownStop stop;\n  \
+         stop.set_now(7);\n  \
+         feed(parser, printer, frame, stop.to_bytes(frame));\n  \
+         return 0;\n\
+         }\n",
+    );
+    let rust = run_rust(
+        &root,
+        "use ducklink_messages::{DownStop, Handler, Message, UpOdom};\n\n\
+         struct Printer;\n\n\
+         impl Handler for Printer {\n    \
+         fn on_up_odom(&mut self, msg: &UpOdom) {\n        \
+         println!(\"odom {}\", msg.x());\n    \
+         }\n\n    \
+         fn on_down_stop(&mut self, msg: &DownStop) {\n        \
+         println!(\"stop {}\", msg.now());\n    \
+         }\n\
+         }\n\n\
+         fn feed(frame: &[u8]) {\n    \
+         let msg = Message::from_bytes(frame[2], &frame[4..frame.len() - 2]).unwrap();\n    \
+         msg.dispatch(&mut Printer);\n\
+         }\n\n\
+         fn main() {\n    \
+         let mut frame = [0; 16];\n    \
+         let mut odom = UpOdom::new();\n    \
+         odom.set_x(-3);\n    \
+         let len = odom.to_bytes(&mut frame).unwrap();\n    \
+         feed(&frame[..len]);\n    \
+         let mut stop = DownStop::new();\n    \
+         stop.set_now(7);\n    \
+         let len = stop.to_bytes(&mut frame).unwrap();\n    \
+         feed(&frame[..len]);\n\
+         }\n",
+    );
+
+    assert_eq!(c, DISPATCHED);
+    assert_eq!(cpp, DISPATCHED);
+    assert_eq!(rust, DISPATCHED);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+/// Registers a handler with `Dispatcher.on` and one with its shorthand, then decodes and
+/// dispatches the frames of `DISPATCHED`. The checksum isn't checked by `deserialize`.
+const DISPATCH_PY: &str = "from messages import MESSAGES, Dispatcher, UpOdom
+
+dispatcher = Dispatcher()
+
+@dispatcher.on(UpOdom)
+def on_odom(msg):
+    print('odom {}'.format(msg.x))
+
+@dispatcher.on_down_stop
+def on_stop(msg):
+    print('stop {}'.format(msg.now))
+
+def feed(frame):
+    msg = MESSAGES[frame[2]]()
+    msg.deserialize(bytes(frame[4:]))
+    dispatcher.dispatch(msg)
+
+feed([0xff, 0xff, 1, 4, 0xfd, 0xff, 0, 0])
+feed([0xff, 0xff, 2, 3, 7, 0, 0])
+";
+
+#[test]
+#[ignore = "needs Python with bitstring"]
+fn python_decoded_frames_are_dispatched_to_their_handler() {
+    let root = generate("dispatch_py", DISPATCH_SCHEMA, LANGS, &[]);
+    assert_eq!(run_python(&root, DISPATCH_PY), DISPATCHED);
+    fs::remove_dir_all(&root).ok();
+}
+
+const ENUM_SCHEMA: &str =
+    "[enums.DriveMode]\nStop = 0\nSpeed = 2\n\n[down.Drive]\nmode = \"DriveMode\"\n";
+
+/// Payloads of `Drive`: a known value, then two unknown ones.
+const ENUM_PAYLOADS: &[u8] = &[2, 1, 3];
+
+#[test]
+fn unknown_enum_values_are_rejected() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("enums", ENUM_SCHEMA, LANGS, &[]);
+    let payloads = ENUM_PAYLOADS
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // Prints whether each payload is decoded.
+    let c = run_c(
+        &cc,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n\
+             int main(void) {{\n  \
+             uint8_t payloads[] = {{{payloads}}};\n  \
+             union Message_t msg;\n  \
+             for(unsigned i=0; i<sizeof(payloads); i++) {{\n    \
+             printf(\"%d \", down_drive_from_bytes(&msg, payloads + i, 1));\n  \
+             }}\n  \
+             return 0;\n\
+             }}\n",
+            payloads = payloads
+        ),
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        &format!(
+            "#include <stdio.h>\n#include \"messages.h\"\n\n\
+             int main() {{\n  \
+             uint8_t payloads[] = {{{payloads}}};\n  \
+             MsgStorage storage;\n  \
+             for(unsigned i=0; i<sizeof(payloads); i++) {{\n    \
+             printf(\"%d \", make_msg(DownDrive::ID, payloads + i, 1, &storage) != NULL);\n  \
+             }}\n  \
+             return 0;\n\
+             }}\n",
+            payloads = payloads
+        ),
+    );
+    let rust = run_rust(
+        &root,
+        &format!(
+            "use ducklink_messages::{{DownDrive, Error}};\n\n\
+             fn main() {{\n    \
+             for v in [{payloads}] {{\n        \
+             match DownDrive::from_bytes(&[v]) {{\n            \
+             Ok(_) => print!(\"1 \"),\n            \
+             Err(Error::InvalidValue) => print!(\"0 \"),\n            \
+             Err(e) => panic!(\"{{:?}}\", e),\n        \
+             }}\n    \
+             }}\n\
+             }}\n",
+            payloads = payloads
+        ),
+    );
+
+    assert_eq!(c, "1 0 0 ");
+    assert_eq!(cpp, c);
+    assert_eq!(rust, c);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+const BITFIELD_SCHEMA: &str = "[bitfields.Flags]\ntype = \"u8\"\narmed = 0\nlevel = \"1..3\"\n\
+                               mode = \"4..7\"\n\n[down.Status]\nflags = \"Flags\"\n";
+
+/// Bits and ranges of `Flags` after each step of the bitfield tests. Bit 7, in no range,
+/// is set from the start and never touched, and the setters clamp 5 to 3 and 9 to 7.
+const BITFIELD_STEPS: &str = "87 1 3 0\na3 1 1 2\nf2 0 1 7\n";
+
+#[test]
+fn bitfield_ranges_are_masked_shifted_and_clamped() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("bitfields", BITFIELD_SCHEMA, LANGS, &[]);
+
+    let c = run_c(
+        &cc,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         static void print(uint8_t bits) {\n  \
+         printf(\"%02x %d %d %d\\n\", bits, flags_get_armed(bits), flags_get_level(bits), flags_get_mode(bits));\n\
+         }\n\n\
+         int main(void) {\n  \
+         uint8_t bits = 0x80;\n  \
+         flags_set_armed(&bits, true);\n  \
+         flags_set_level(&bits, 5);\n  \
+         print(bits);\n  \
+         flags_set_mode(&bits, 2);\n  \
+         flags_set_level(&bits, 1);\n  \
+         print(bits);\n  \
+         flags_set_mode(&bits, 9);\n  \
+         flags_set_armed(&bits, false);\n  \
+         print(bits);\n  \
+         return 0;\n\
+         }\n",
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         static void print(const Flags &flags) {\n  \
+         printf(\"%02x %d %d %d\\n\", flags.get_bits(), flags.get_armed(), flags.get_level(), flags.get_mode());\n\
+         }\n\n\
+         int main() {\n  \
+         Flags flags(0x80);\n  \
+         flags.set_armed(true);\n  \
+         flags.set_level(5);\n  \
+         print(flags);\n  \
+         flags.set_mode(2);\n  \
+         flags.set_level(1);\n  \
+         print(flags);\n  \
+         flags.set_mode(9);\n  \
+         flags.set_armed(false);\n  \
+         print(flags);\n  \
+         return 0;\n\
+         }\n",
+    );
+    let rust = run_rust(
+        &root,
+        "use ducklink_messages::Flags;\n\n\
+         fn print(flags: Flags) {\n    \
+         println!(\"{:02x} {} {} {}\", flags.0, flags.armed() as u8, flags.level(), flags.mode());\n\
+         }\n\n\
+         fn main() {\n    \
+         let mut flags = Flags(0x80);\n    \
+         flags.set_armed(true);\n    \
+         flags.set_level(5);\n    \
+         print(flags);\n    \
+         flags.set_mode(2);\n    \
+         flags.set_level(1);\n    \
+         print(flags);\n    \
+         flags.set_mode(9);\n    \
+         flags.set_armed(false);\n    \
+         print(flags);\n\
+         }\n",
+    );
+
+    assert_eq!(c, BITFIELD_STEPS);
+    assert_eq!(cpp, BITFIELD_STEPS);
+    assert_eq!(rust, BITFIELD_STEPS);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+const DEFAULT_SCHEMA: &str = "[enums.DriveMode]\nStop = 0\nSpeed = 2\n\n\
+                              [down.Setup]\nspeed = {type = \"i16\", default = -5}\n\
+                              mode = {type = \"DriveMode\", default = \"Speed\"}\n\
+                              name = {type = \"chars\", max_len = 8, default = \"duck\"}\n\
+                              gains = {type = \"u8\", len = 3, default = [1, 2, 3]}\n";
+
+/// Fields of a new `Setup`, then of a `Setup` changed and reset.
+const DEFAULTS: &str = "-5 2 duck 1 2 3\n-5 2 duck 1 2 3\n";
+
+#[test]
+fn defaults_are_applied_by_init_and_reset() {
+    let (cc, cxx) = match (compiler("CC", "cc"), compiler("CXX", "g++")) {
+        (Some(cc), Some(cxx)) => (cc, cxx),
+        _ => {
+            eprintln!("No C or C++ compiler found, skipping.");
+            return;
+        }
+    };
+    let root = generate("defaults", DEFAULT_SCHEMA, LANGS, &[]);
+
+    let c = run_c(
+        &cc,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         static void print(const struct DownSetup *msg) {\n  \
+         printf(\"%d %d %.*s %d %d %d\\n\", msg->speed, msg->mode, msg->name_len, msg->name,\n         \
+         msg->gains[0], msg->gains[1], msg->gains[2]);\n\
+         }\n\n\
+         int main(void) {\n  \
+         struct DownSetup msg;\n  \
+         init_down_setup(&msg);\n  \
+         print(&msg);\n  \
+         msg.speed = 7;\n  \
+         msg.mode = DRIVE_MODE_STOP;\n  \
+         msg.name_len = 1;\n  \
+         msg.name[0] = 'x';\n  \
+         msg.gains[0] = 9;\n  \
+         init_down_setup(&msg);\n  \
+         print(&msg);\n  \
+         return 0;\n\
+         }\n",
+    );
+    let cpp = run_cpp(
+        &cxx,
+        &root,
+        "#include <stdio.h>\n#include \"messages.h\"\n\n\
+         static void print(const DownSetup &msg) {\n  \
+         printf(\"%d %d %.*s %d %d %d\\n\", msg.get_speed(), (int)msg.get_mode(), msg.get_name_len(),\n         \
+         msg.get_name(), msg.get_gains(0), msg.get_gains(1), msg.get_gains(2));\n\
+         }\n\n\
+         int main() {\n  \
+         DownSetup msg;\n  \
+         print(msg);\n  \
+         msg.set_speed(7);\n  \
+         msg.set_mode(DriveMode::Stop);\n  \
+         msg.set_name(\"x\", 1);\n  \
+         msg.set_gains(0, 9);\n  \
+         msg.reset();\n  \
+         print(msg);\n  \
+         return 0;\n\
+         }\n",
+    );
+    let rust = run_rust(
+        &root,
+        "use ducklink_messages::{DownSetup, DriveMode};\n\n\
+         fn print(msg: &DownSetup) {\n    \
+         let gains = msg.gains();\n    \
+         println!(\n        \
+         \"{} {} {} {} {} {}\",\n        \
+         msg.speed(),\n        \
+         msg.mode() as u8,\n        \
+         String::from_utf8_lossy(msg.name()),\n        \
+         gains[0],\n        \
+         gains[1],\n        \
+         gains[2]\n    \
+         );\n\
+         }\n\n\
+         fn main() {\n    \
+         let mut msg = DownSetup::new();\n    \
+         print(&msg);\n    \
+         msg.set_speed(7);\n    \
+         msg.set_mode(DriveMode::Stop);\n    \
+         msg.set_name(b\"x\");\n    \
+         msg.set_gains([9, 2, 3]);\n    \
+         msg.reset();\n    \
+         print(&msg);\n\
+         }\n",
+    );
+
+    assert_eq!(c, DEFAULTS);
+    assert_eq!(cpp, DEFAULTS);
+    assert_eq!(rust, DEFAULTS);
+
+    fs::remove_dir_all(&root).ok();
+}
@@ -0,0 +1,116 @@
+//! Checks that the no_std crate emitted by the Rust generator compiles, and that its
+//! messages go through a round trip.
+
+mod common;
+
+use common::{generate, test_schema};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Run `cargo check` on the crate generated in `root`.
+fn check(root: &Path) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["check", "--offline", "--manifest-path"])
+        .arg(root.join("rust/Cargo.toml"))
+        .env("CARGO_TARGET_DIR", root.join("target"))
+        .env("RUSTFLAGS", "-D warnings")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "cargo check failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Run `main` with the crate generated in `root`, and return its output.
+fn run(root: &Path, main: &str) -> String {
+    let bin = root.join("main_rs");
+    fs::create_dir_all(bin.join("src")).unwrap();
+    fs::write(
+        bin.join("Cargo.toml"),
+        "[package]\nname = \"main_rs\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [dependencies]\nducklink_messages = { path = \"../rust\" }\n",
+    )
+    .unwrap();
+    fs::write(bin.join("src/main.rs"), main).unwrap();
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["run", "--quiet", "--offline", "--manifest-path"])
+        .arg(bin.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", root.join("target"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "cargo run failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn generated_rust_compiles() {
+    let root = generate("rust", &test_schema(), &["Rust"], &[]);
+    check(&root);
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn nested_arrays_of_structs_compile() {
+    // Setters of the messages clamp each element of the arrays of structs.
+    let schema = "[types.Inner]\na = {type = \"i16\", min = -10, max = 10}\n\
+                  arr = {type = \"u16\", len = 3, max = 1000}\n\n\
+                  [types.Outer]\narr = {type = \"Inner\", len = 2}\nk = \"u8\"\n\n\
+                  [down.Nested]\nvs = {type = \"Outer\", len = 2}\n\
+                  inner = {type = \"Inner\", max_len = 4}\n";
+    let root = generate("rust_nested", schema, &["Rust"], &[]);
+    check(&root);
+    fs::remove_dir_all(&root).ok();
+}
+
+const ROUND_TRIP_SCHEMA: &str = "[up.Odom]\nx = {type = \"i16\", min = -100, max = 100}\n\
+                                 y = \"f32\"\nname = \"chars\"\n";
+
+/// Encodes a clamped message, prints its frame, and decodes it back.
+const ROUND_TRIP_MAIN: &str = r#"
+use ducklink_messages::*;
+
+fn main() {
+    let mut msg = UpOdom::new();
+    msg.set_x(-1000);
+    msg.set_y(1.5);
+    msg.set_name(b"duck");
+    assert_eq!(msg.x(), -100);
+
+    let mut buffer = [0; MAX_MSG_BUFFER_SIZE];
+    let len = msg.to_bytes(&mut buffer).unwrap();
+    for b in &buffer[..len] {
+        print!("{:02x} ", b);
+    }
+    println!();
+
+    let decoded = Message::from_bytes(buffer[2], &buffer[4..len - 2]).unwrap();
+    assert_eq!(decoded, Message::UpOdom(msg));
+    assert_eq!(
+        UpOdom::from_bytes(&buffer[4..len - 3]),
+        Err(Error::BadLength)
+    );
+}
+"#;
+
+#[test]
+fn generated_rust_round_trips() {
+    let root = generate("rust_round_trip", ROUND_TRIP_SCHEMA, &["Rust"], &[]);
+    // Start bytes, id, length of the payload plus 2, x clamped to -100, y, the chars
+    // zero padded, and the Fletcher-16 checksum of the id, length and payload.
+    assert_eq!(
+        run(&root, ROUND_TRIP_MAIN).trim_end(),
+        "ff ff 01 12 9c ff 00 00 c0 3f 64 75 63 6b 00 00 00 00 00 00 b4 54"
+    );
+    fs::remove_dir_all(&root).ok();
+}
@@ -0,0 +1,73 @@
+//! Checks that the protocol UID is stable, and changes along with the wire format.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SCHEMA: &str = r#"
+[down.Stop]
+now = "u8"
+
+[up.Odom]
+speed = "i16"
+x = "f32"
+y = "f32"
+"#;
+
+/// Generate the C code of `schema` and read back the UID it defines.
+fn uid(schema: &str) -> u32 {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let root = env::temp_dir().join(format!(
+        "ducklink_uid_{}_{}",
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::SeqCst)
+    ));
+    // The C code is written in ../lib/C/messages, relative to the working directory.
+    let work = root.join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::create_dir_all(root.join("lib/C/messages")).unwrap();
+    fs::write(work.join("schema.toml"), schema).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_message_generator"))
+        .current_dir(&work)
+        .args(["schema.toml", "-l", "C"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let header = fs::read_to_string(root.join("lib/C/messages/messages.h")).unwrap();
+    header
+        .lines()
+        .find_map(|line| line.strip_prefix("#define UID "))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+/// The UID is checked by the two sides of the link, that may be generated by different
+/// versions of the generator: it shall not change unless the wire format does.
+#[test]
+fn uid_is_stable() {
+    assert_eq!(uid(SCHEMA), 0x4b56_7a4c);
+}
+
+#[test]
+fn uid_follows_the_wire_format() {
+    let changes = [
+        ("speed = \"i16\"", "speed = \"u16\""),
+        ("speed = \"i16\"", "speed = \"i16\"\nturn = \"i16\""),
+        ("y = \"f32\"", "y = \"i32\""),
+        ("now = \"u8\"", "now = \"u8\"\n\n[down.Start]\nnow = \"u8\""),
+    ];
+    for (from, to) in &changes {
+        assert_ne!(
+            uid(&SCHEMA.replace(from, to)),
+            uid(SCHEMA),
+            "{} -> {}",
+            from,
+            to
+        );
+    }
+}